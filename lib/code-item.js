@@ -43,6 +43,22 @@ export default class CodeItem {
     this.header.appendChild(extension);
     this.header.appendChild(title);
 
+    // Matches come from the unsaved buffer rather than the file on disk
+    if (block.dirty) {
+      const dirty = document.createElement('span');
+      dirty.classList.add('inline-block', 'highlight-info');
+      dirty.textContent = 'unsaved';
+      this.header.appendChild(dirty);
+    }
+
+    // Binary files are reported without matched lines
+    if (block.binary) {
+      const binary = document.createElement('span');
+      binary.classList.add('inline-block', 'highlight-warning');
+      binary.textContent = 'binary file matches';
+      this.header.appendChild(binary);
+    }
+
     const clickHandler = (event) => {
       this.toggle()
     }
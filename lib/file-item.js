@@ -3,11 +3,11 @@
 import {Disposable} from 'atom';
 
 export default class FileItem {
-  constructor(path, ext) {
+  constructor(path, relPath, ext) {
     this.extension = ext;
 
     const pathElement = document.createElement('a');
-    pathElement.textContent = `${path}`;
+    pathElement.textContent = `${relPath || path}`;
 
     const clickHandler = (event) => {
       atom.workspace.open(path);
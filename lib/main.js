@@ -36,6 +36,8 @@ export default {
         'omnisearch:toggle': () => this.toggle()
       }),
 
+      atom.workspace.observeTextEditors(editor => this.observeBuffer(editor)),
+
       new Disposable(() => {
         atom.workspace.getPaneItems().forEach(item => {
           if (item instanceof SearchView) {
@@ -64,6 +66,30 @@ export default {
     return view;
   },
 
+  // Keeps server overlay in sync with unsaved changes of the editor buffer.
+  observeBuffer(editor) {
+    const sync = () => {
+      const process = this.process;
+      const path = editor.getPath();
+      if (!process || !process.address || !path) {
+        return;
+      }
+      const request = editor.isModified() ?
+        process.pushBuffer(path, editor.getText()) : process.clearBuffer(path);
+      request.catch(err => console.log(`Failed to sync buffer ${path}: ${err}`));
+    };
+    const subscriptions = new CompositeDisposable(
+      editor.onDidStopChanging(sync),
+      editor.onDidSave(sync),
+      editor.onDidDestroy(() => {
+        if (this.process && this.process.address && editor.getPath()) {
+          this.process.clearBuffer(editor.getPath()).catch(() => {});
+        }
+        subscriptions.dispose();
+      })
+    );
+  },
+
   isPlatformSupported() {
     return {'darwin': 'mac', 'linux': 'linux'}[process.platform] != null;
   },
@@ -11,9 +11,18 @@ export default class MetricsView {
     this.runStatus = document.createElement('span');
     this.runStatus.classList.add('inline-block');
 
+    // Cache indexing progress
+    this.indexStatus = document.createElement('span');
+    this.indexStatus.classList.add('inline-block');
+    this.indexProgress = document.createElement('progress');
+    this.indexProgress.classList.add('inline-block');
+
     this.element.appendChild(this.runStatus);
+    this.element.appendChild(this.indexStatus);
+    this.element.appendChild(this.indexProgress);
 
     this.update({});
+    this.updateProgress(null);
   }
 
   // Updates metrics.
@@ -21,6 +30,33 @@ export default class MetricsView {
     this._updateMetric(this.runStatus, obj.runStatus);
   }
 
+  // Updates cache indexing progress.
+  // Progress is hidden when directory is not cached or index is not being built.
+  updateProgress(progress) {
+    if (!progress || progress.state != 'indexing') {
+      this._updateMetric(this.indexStatus, null);
+      this.indexProgress.classList.add('hidden');
+      return;
+    }
+
+    let status = `Indexing ${progress.files_read}/${progress.files_discovered} files`;
+    if (!progress.discovery_done) {
+      status = `Indexing, discovered ${progress.files_discovered} files`;
+    } else if (progress.estimated_remaining_sec != null) {
+      status = `${status}, ${progress.estimated_remaining_sec.toFixed(0)} sec left`;
+    }
+    this._updateMetric(this.indexStatus, status);
+
+    if (progress.discovery_done && progress.files_discovered > 0) {
+      this.indexProgress.max = progress.files_discovered;
+      this.indexProgress.value = progress.files_read;
+    } else {
+      // Indeterminate progress bar while files are being discovered
+      this.indexProgress.removeAttribute('value');
+    }
+    this.indexProgress.classList.remove('hidden');
+  }
+
   // Internal method to update metric.
   _updateMetric(metric, value) {
     if (value) {
@@ -20,7 +20,7 @@ export default class ResultFileView {
     // Create file objects
     if (!files) return;
     for (const file of files) {
-      const item = new FileItem(file.path, file.ext);
+      const item = new FileItem(file.path, file.rel_path, file.ext);
       this.files.push(item);
       this.fileList.appendChild(item.getElement());
     }
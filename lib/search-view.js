@@ -7,6 +7,9 @@ import Query from './query';
 import ResultContentView from './result-content-view';
 import ResultFileView from './result-file-view';
 
+// Interval in milliseconds to poll cache indexing progress.
+const PROGRESS_POLL_INTERVAL_MS = 1000;
+
 export default class SearchView {
   constructor(serializedState) {
     // Use `setProcess` to assign currently running process.
@@ -83,6 +86,10 @@ export default class SearchView {
       }
     }));
 
+    // Poll cache indexing progress for the selected project
+    const progressTimer = setInterval(() => this.pollProgress(), PROGRESS_POLL_INTERVAL_MS);
+    this.disposables.add(new Disposable(() => clearInterval(progressTimer)));
+
     // Add handles for editor history selection
     this.disposables.add(
       atom.commands.add('atom-text-editor.omnisearch', 'omnisearch:history-prev', () => {
@@ -117,6 +124,19 @@ export default class SearchView {
       .catch(err => this.onSearchError(err, false))
   }
 
+  // Requests cache indexing progress for the current project and updates metrics.
+  // Errors are ignored, progress is simply hidden.
+  pollProgress() {
+    if (!this.serverProcess || !this.serverProcess.isRunning() || !this.query.getPath()) {
+      return;
+    }
+
+    this.serverProcess.cacheProgress(this.query.getPath())
+      .then(response => response.json())
+      .then(json => this.controlView.getMetrics().updateProgress(json && !json.err ? json : null))
+      .catch(err => this.controlView.getMetrics().updateProgress(null));
+  }
+
   // Triggers on successful request.
   onSearchSuccess(json) {
     const countBadge = (obj) => {
@@ -45,7 +45,39 @@ export default class ServerProcess {
     });
   }
 
+  // Sends search query.
   send(input) {
+    return this.request('/search', input);
+  }
+
+  // Requests progress of the cache refresh for the directory.
+  // Resolves to null if directory is not cached.
+  cacheProgress(dir) {
+    return this.request('/cache/progress', {dir: dir});
+  }
+
+  // Pushes content of the unsaved buffer, search uses it instead of the file on disk.
+  pushBuffer(path, content) {
+    return this.request('/buffers/push', {path: path, content: content});
+  }
+
+  // Clears unsaved buffer once it is saved or closed.
+  clearBuffer(path) {
+    return this.request('/buffers/clear', {path: path});
+  }
+
+  // Searches symbol definitions by name in the cached directory.
+  symbols(dir, pattern) {
+    return this.request('/symbols', {dir: dir, pattern: pattern});
+  }
+
+  // Searches commits in the range that added or removed lines matching the pattern.
+  history(dir, pattern, range) {
+    return this.request('/history', {dir: dir, pattern: pattern, range: range});
+  }
+
+  // Sends POST request with JSON payload to the server endpoint.
+  request(endpoint, input) {
     const options = {
       method: 'POST',
       headers: {
@@ -53,7 +85,7 @@ export default class ServerProcess {
       },
       body: JSON.stringify(input)
     };
-    return fetch(`http://${this.address}/search`, options);
+    return fetch(`http://${this.address}${endpoint}`, options);
   }
 
   // Returns true if process is running.
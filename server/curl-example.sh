@@ -2,7 +2,32 @@
 
 curl -d "{\"dir\":\"/Users/sadikovi/developer/spark\",\"pattern\":\"os_supported\"}" -X POST http://127.0.0.1:49555/search
 curl -d "{\"dir\":\"/Users/sadikovi/developer/spark\",\"pattern\":\"os_supported\",\"use_cache\":true}" -X POST http://127.0.0.1:49555/search
+curl -d "{\"dir\":\"/Users/sadikovi/developer/spark\",\"pattern\":\"sql/core/\",\"use_cache\":true}" -X POST http://127.0.0.1:49555/search
+curl -d "{\"dir\":\"/Users/sadikovi/developer/spark\",\"pattern\":\"os_supported\",\"sort\":\"path\"}" -X POST http://127.0.0.1:49555/search
+curl -d "{\"dir\":\"/Users/sadikovi/developer/spark\",\"pattern\":\"SQLConf\",\"mode\":\"references\"}" -X POST http://127.0.0.1:49555/search
+curl -d "{\"dir\":\"/Users/sadikovi/developer/spark\",\"pattern\":\"SQLConf\",\"scope\":\"diff:HEAD~1\"}" -X POST http://127.0.0.1:49555/search
+curl -d "{\"dir\":\"/Users/sadikovi/developer/spark\",\"pattern\":\"SQLConf\",\"rev\":\"branch-2.4\"}" -X POST http://127.0.0.1:49555/search
+curl -d "{\"dir\":\"/Users/sadikovi/developer/spark\",\"pattern\":\"SparkSession\",\"search_archives\":true}" -X POST http://127.0.0.1:49555/search
+curl -d "{\"dir\":\"/Users/sadikovi/developer/spark\",\"pattern\":\"SparkSession\",\"binary\":\"report\"}" -X POST http://127.0.0.1:49555/search
+curl -d "{\"dir\":\"/Users/sadikovi/developer/spark\",\"pattern\":\"café\",\"encoding\":\"latin1\"}" -X POST http://127.0.0.1:49555/search
+curl -d "{\"dir\":\"/Users/sadikovi/developer/spark\",\"pattern\":\"sqlconf\",\"case\":\"insensitive\"}" -X POST http://127.0.0.1:49555/search
+curl -d "{\"dir\":\"/Users/sadikovi/developer/spark\",\"pattern\":\"sqlconf\",\"include_hidden\":true,\"include_ignored\":true}" -X POST http://127.0.0.1:49555/search
+curl -d "{\"dir\":\"/Users/sadikovi/developer/spark\",\"pattern\":\"spark\\\\.(\\\\w+)\",\"replacement\":\"conf.\$1\",\"use_regex\":true}" -X POST http://127.0.0.1:49555/replace
+curl -d "{\"dir\":\"/Users/sadikovi/developer/spark\",\"pattern\":\"os_supported\",\"replacement\":\"is_supported\",\"files\":[{\"path\":\"/Users/sadikovi/developer/spark/README.md\",\"hash\":\"6387ee58ddf2c146\",\"lines\":[2,4]}]}" -X POST http://127.0.0.1:49555/replace/apply
+curl -d "{\"dir\":\"/Users/sadikovi/developer/spark\",\"pattern\":\"spark.sql.adaptive.enabled\",\"range\":\"v2.4.0..master\"}" -X POST http://127.0.0.1:49555/history
+curl -d "{\"path\":\"/Users/sadikovi/developer/spark/README.md\",\"content\":\"unsaved content\"}" -X POST http://127.0.0.1:49555/buffers/push
+curl -d "{\"path\":\"/Users/sadikovi/developer/spark/README.md\"}" -X POST http://127.0.0.1:49555/buffers/clear
+curl -d "{\"dir\":\"/Users/sadikovi/developer/spark\",\"pattern\":\"sqlconf\",\"limit\":20}" -X POST http://127.0.0.1:49555/files
+curl -d "{\"dir\":\"/Users/sadikovi/developer/spark\",\"pattern\":\"SQLConf\",\"kinds\":[\"class\",\"module\"]}" -X POST http://127.0.0.1:49555/symbols
 
 curl -d "{\"dir\":\"/Users/sadikovi/developer/spark\"}" -X POST http://127.0.0.1:49555/cache/add
+curl -d "{\"dir\":\"/Users/sadikovi/developer/spark\",\"extensions\":[\"scala\",\"java\"],\"ignore\":[\"target/**\"],\"max_file_size\":1000000,\"refresh_interval_secs\":30}" -X POST http://127.0.0.1:49555/cache/add
+curl -d "{\"dir\":\"/Users/sadikovi/developer/spark\",\"rev\":\"branch-2.4\"}" -X POST http://127.0.0.1:49555/cache/add
+curl -d "{\"dir\":\"/Users/sadikovi/developer/spark\",\"include_hidden\":true}" -X POST http://127.0.0.1:49555/cache/add
+curl -d "{\"dir\":\"/Users/sadikovi/developer/spark\",\"encoding\":\"utf-16le\"}" -X POST http://127.0.0.1:49555/cache/add
+
+curl -d "{\"dir\":\"/Users/sadikovi/developer/spark\"}" -X POST http://127.0.0.1:49555/cache/progress
+curl -d "{\"dir\":\"/Users/sadikovi/developer/spark\"}" -X POST http://127.0.0.1:49555/cache/refresh
 
 curl http://127.0.0.1:49555/cache/stats
+curl http://127.0.0.1:49555/cache/status
@@ -0,0 +1,322 @@
+//! Search inside archives and compressed files.
+//! Compressed files are decompressed in memory and searched under their own path, zip and tar
+//! archives are expanded into entries with virtual paths such as `lib/foo.jar!/com/x/Y.java`.
+//! Nested archives are not expanded. Entries are decompressed one at a time and passed to the
+//! caller, expansion stops once the total size or number of entries exceeds the limits.
+
+use std::fs::File;
+use std::io::{self, BufReader, Cursor, Read, Seek};
+use std::path::Path;
+
+use bzip2::read::BzDecoder;
+use errors;
+use ext::Extension;
+use flate2::read::MultiGzDecoder;
+use lang;
+use tar;
+use xz2::read::XzDecoder;
+use zip::ZipArchive;
+use zstd::stream::read::Decoder as ZstdDecoder;
+
+/// Separator between path of the archive and path of the entry in the archive.
+pub const SEPARATOR: &str = "!/";
+// Max size in bytes of the decompressed entry, larger entries are skipped.
+const MAX_ENTRY_BYTES: u64 = 64 * 1024 * 1024;
+// Max total size in bytes of decompressed entries of the archive.
+const MAX_TOTAL_BYTES: u64 = 512 * 1024 * 1024;
+// Max number of entries expanded from the archive, including skipped entries.
+const MAX_ENTRIES: usize = 100_000;
+
+/// Compression of the file or tar archive.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Compression {
+  None,
+  Gzip,
+  Bzip2,
+  Xz,
+  Zstd
+}
+
+/// Format of the file that can be expanded.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+  Compressed(Compression), // single compressed file, e.g. `app.log.gz`
+  Tar(Compression),
+  Zip // zip archive, including jar, war and ear
+}
+
+impl Format {
+  /// Returns format of the file based on the file name, or None for regular files.
+  pub fn from_path(path: &str) -> Option<Self> {
+    let name = Path::new(path).file_name()?.to_str()?.to_lowercase();
+    let formats = [
+      (".tar", Format::Tar(Compression::None)),
+      (".tar.gz", Format::Tar(Compression::Gzip)),
+      (".tgz", Format::Tar(Compression::Gzip)),
+      (".tar.bz2", Format::Tar(Compression::Bzip2)),
+      (".tbz2", Format::Tar(Compression::Bzip2)),
+      (".tar.xz", Format::Tar(Compression::Xz)),
+      (".txz", Format::Tar(Compression::Xz)),
+      (".tar.zst", Format::Tar(Compression::Zstd)),
+      (".zip", Format::Zip),
+      (".jar", Format::Zip),
+      (".war", Format::Zip),
+      (".ear", Format::Zip),
+      (".gz", Format::Compressed(Compression::Gzip)),
+      (".bz2", Format::Compressed(Compression::Bzip2)),
+      (".xz", Format::Compressed(Compression::Xz)),
+      (".zst", Format::Compressed(Compression::Zstd))
+    ];
+    // Tar suffixes are checked first, so that `.tar.gz` is not treated as a compressed file.
+    formats.iter()
+      .find(|(suffix, _)| name.ends_with(suffix) && name.len() > suffix.len())
+      .map(|(_, format)| *format)
+  }
+}
+
+/// Decompressed file or entry of the archive.
+#[derive(Clone, Debug)]
+pub struct Entry {
+  path: String, // path of the compressed file, or virtual path of the archive entry
+  ext: Extension,
+  content: Vec<u8>
+}
+
+impl Entry {
+  /// Returns path of the entry.
+  pub fn path(&self) -> &str {
+    &self.path
+  }
+
+  /// Returns extension of the entry.
+  pub fn ext(&self) -> Extension {
+    self.ext
+  }
+
+  /// Returns decompressed content of the entry.
+  pub fn content(&self) -> &[u8] {
+    &self.content
+  }
+}
+
+// Returns reader that decompresses data.
+fn decoder<'a, R: Read + 'a>(
+  compression: Compression,
+  reader: R
+) -> io::Result<Box<dyn Read + 'a>> {
+  match compression {
+    Compression::None => Ok(Box::new(reader)),
+    Compression::Gzip => Ok(Box::new(MultiGzDecoder::new(reader))),
+    Compression::Bzip2 => Ok(Box::new(BzDecoder::new(reader))),
+    Compression::Xz => Ok(Box::new(XzDecoder::new(reader))),
+    Compression::Zstd => Ok(Box::new(ZstdDecoder::new(reader)?))
+  }
+}
+
+// Budget of the archive expansion, shared by all entries.
+struct Budget {
+  bytes: u64,
+  entries: usize
+}
+
+impl Budget {
+  // Counts the entry, returns false if no more entries can be expanded.
+  fn take_entry(&mut self) -> bool {
+    self.entries += 1;
+    self.entries <= MAX_ENTRIES && self.bytes <= MAX_TOTAL_BYTES
+  }
+
+  // Counts decompressed bytes, returns false if the total size is exceeded.
+  fn take_bytes(&mut self, len: usize) -> bool {
+    self.bytes += len as u64;
+    self.bytes <= MAX_TOTAL_BYTES
+  }
+}
+
+// Reads content up to the max entry size, returns None if content is larger.
+fn read_limited<R: Read>(reader: R) -> io::Result<Option<Vec<u8>>> {
+  let mut content = Vec::new();
+  reader.take(MAX_ENTRY_BYTES + 1).read_to_end(&mut content)?;
+  if content.len() as u64 > MAX_ENTRY_BYTES {
+    Ok(None)
+  } else {
+    Ok(Some(content))
+  }
+}
+
+/// Expands compressed file or archive and calls the function with each entry.
+/// Data is read from the path if not provided, directories and entries larger than the max
+/// entry size are skipped, expansion stops once the total size or number of entries of the
+/// archive exceeds the limits.
+pub fn for_each_entry<F: FnMut(Entry)>(
+  path: &str,
+  format: Format,
+  data: Option<&[u8]>,
+  f: F
+) -> Result<(), errors::Error> {
+  match data {
+    Some(data) => expand(path, format, Cursor::new(data), f),
+    None => expand(path, format, BufReader::new(File::open(path)?), f)
+  }
+}
+
+// Expands data of the compressed file or archive read from the reader.
+fn expand<R: Read + Seek, F: FnMut(Entry)>(
+  path: &str,
+  format: Format,
+  reader: R,
+  mut f: F
+) -> Result<(), errors::Error> {
+  let mut budget = Budget { bytes: 0, entries: 0 };
+  match format {
+    Format::Compressed(compression) => {
+      // Extension of the decompressed file, e.g. `json` for `data.json.gz`.
+      let stem = Path::new(path).file_stem().and_then(|os| os.to_str()).unwrap_or("");
+      if let Some(content) = read_limited(decoder(compression, reader)?)? {
+        let ext = lang::detect(Path::new(stem), Some(&content));
+        f(Entry { path: path.to_owned(), ext, content });
+      }
+    },
+    Format::Tar(compression) => {
+      let mut archive = tar::Archive::new(decoder(compression, reader)?);
+      for entry in archive.entries()? {
+        if !budget.take_entry() {
+          break;
+        }
+        let entry = entry?;
+        if !entry.header().entry_type().is_file() || entry.size() > MAX_ENTRY_BYTES {
+          continue;
+        }
+        let name = match entry.path()?.to_str() {
+          Some(name) => name.trim_start_matches("./").to_owned(),
+          None => continue
+        };
+        if let Some(content) = read_limited(entry)? {
+          if !budget.take_bytes(content.len()) {
+            break;
+          }
+          let path = format!("{}{}{}", path, SEPARATOR, name);
+          let ext = lang::detect(Path::new(&name), Some(&content));
+          f(Entry { path, ext, content });
+        }
+      }
+    },
+    Format::Zip => {
+      let mut archive = ZipArchive::new(reader)?;
+      for i in 0..archive.len() {
+        if !budget.take_entry() {
+          break;
+        }
+        let file = archive.by_index(i)?;
+        if file.is_dir() || file.size() > MAX_ENTRY_BYTES {
+          continue;
+        }
+        let name = file.name().to_owned();
+        if let Some(content) = read_limited(file)? {
+          if !budget.take_bytes(content.len()) {
+            break;
+          }
+          let path = format!("{}{}{}", path, SEPARATOR, name);
+          let ext = lang::detect(Path::new(&name), Some(&content));
+          f(Entry { path, ext, content });
+        }
+      }
+    }
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use flate2::write::GzEncoder;
+  use flate2::Compression as GzCompression;
+  use std::io::Write;
+  use zip::write::{FileOptions, ZipWriter};
+
+  // Returns path, extension and content of the entries.
+  fn expand_data(path: &str, data: &[u8]) -> Vec<(String, Extension, String)> {
+    let format = Format::from_path(path).expect("archive format");
+    let mut entries = Vec::new();
+    for_each_entry(path, format, Some(data), |entry| {
+      let content = String::from_utf8(entry.content().to_vec()).unwrap();
+      entries.push((entry.path().to_owned(), entry.ext(), content));
+    }).unwrap();
+    entries
+  }
+
+  // Returns gzip compressed data.
+  fn gzip(data: &[u8]) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::new(), GzCompression::default());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+  }
+
+  // Returns tar archive with the files.
+  fn tar(files: &[(&str, &str)]) -> Vec<u8> {
+    let mut builder = tar::Builder::new(Vec::new());
+    for (name, content) in files {
+      let mut header = tar::Header::new_gnu();
+      header.set_size(content.len() as u64);
+      header.set_mode(0o644);
+      header.set_cksum();
+      builder.append_data(&mut header, name, content.as_bytes()).unwrap();
+    }
+    builder.into_inner().unwrap()
+  }
+
+  #[test]
+  fn test_format_from_path() {
+    assert_eq!(Format::from_path("/a/app.tar"), Some(Format::Tar(Compression::None)));
+    assert_eq!(Format::from_path("/a/app.tar.gz"), Some(Format::Tar(Compression::Gzip)));
+    assert_eq!(Format::from_path("/a/app.TGZ"), Some(Format::Tar(Compression::Gzip)));
+    assert_eq!(Format::from_path("/a/app.tar.zst"), Some(Format::Tar(Compression::Zstd)));
+    assert_eq!(Format::from_path("/a/lib.jar"), Some(Format::Zip));
+    assert_eq!(Format::from_path("/a/app.log.gz"), Some(Format::Compressed(Compression::Gzip)));
+    assert_eq!(Format::from_path("/a/data.xz"), Some(Format::Compressed(Compression::Xz)));
+    assert_eq!(Format::from_path("/a/.gz"), None);
+    assert_eq!(Format::from_path("/a/main.rs"), None);
+  }
+
+  #[test]
+  fn test_expand_gzip() {
+    let entries = expand_data("/a/main.rs.gz", &gzip(b"fn main() {}"));
+    assert_eq!(entries, vec![
+      ("/a/main.rs.gz".to_owned(), Extension::RS, "fn main() {}".to_owned())
+    ]);
+  }
+
+  #[test]
+  fn test_expand_tar_gz() {
+    let data = gzip(&tar(&[("./src/lib.rs", "pub fn a() {}"), ("README", "docs")]));
+    let entries = expand_data("/a/app.tar.gz", &data);
+    assert_eq!(entries, vec![
+      ("/a/app.tar.gz!/src/lib.rs".to_owned(), Extension::RS, "pub fn a() {}".to_owned()),
+      ("/a/app.tar.gz!/README".to_owned(), Extension::UNKNOWN, "docs".to_owned())
+    ]);
+  }
+
+  #[test]
+  fn test_expand_zip() {
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    writer.add_directory("com/", FileOptions::default()).unwrap();
+    writer.start_file("com/A.java", FileOptions::default()).unwrap();
+    writer.write_all(b"class A {}").unwrap();
+    let data = writer.finish().unwrap().into_inner();
+    let entries = expand_data("/a/lib.jar", &data);
+    assert_eq!(entries, vec![
+      ("/a/lib.jar!/com/A.java".to_owned(), Extension::JAVA, "class A {}".to_owned())
+    ]);
+  }
+
+  #[test]
+  fn test_budget() {
+    let mut budget = Budget { bytes: 0, entries: MAX_ENTRIES - 1 };
+    assert!(budget.take_entry());
+    assert!(!budget.take_entry());
+    let mut budget = Budget { bytes: 0, entries: 0 };
+    assert!(budget.take_bytes(MAX_TOTAL_BYTES as usize));
+    assert!(!budget.take_bytes(1));
+    assert!(!budget.take_entry());
+  }
+}
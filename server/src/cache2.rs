@@ -1,19 +1,31 @@
-use std::collections::HashMap;
+use std::cmp;
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io::Read;
 use std::mem::size_of;
-use std::path::Path;
-use std::sync::{mpsc, Arc, Mutex};
-use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
+use archive;
+use config::{self, ProjectConfig};
+use encoding;
+use encoding_rs::Encoding;
 use errors;
-use grep::searcher::Searcher;
+use git2::{ObjectType, Oid, Repository, TreeWalkMode, TreeWalkResult};
+use grep::matcher::Matcher;
 use ext::{Extension, Extensions};
 use ignore::WalkBuilder;
+use ignore::overrides::{Override, OverrideBuilder};
+use lang::Mappings;
+use params::CacheParams;
 use result::{ContentItem, FileItem};
-use search::{CONTENT_MAX_MATCHES, FILE_MAX_MATCHES, Collector, MatcherSpec};
+use search::{Collector, DirectMatcher, Limits, SearchOptions, WalkFilters};
+use search::{file_match_target, relative_path, search_buffer, search_path, search_slice};
+use suffix::SuffixTree;
+use symbols::{Symbol, SymbolExtractor};
 
 // Default hashmap capacity.
 const DEFAULT_HASH_MAP_CAPACITY: usize = 64;
@@ -23,9 +35,15 @@ const DEFAULT_THREAD_POOL_SIZE: usize = 4;
 const MIN_BYTES_TO_CACHE: u64 = 1_000;
 // Number of seconds after which trigger cache refresh.
 const CACHE_POLL_INTERVAL_SECS: u64 = 5;
+// Number of seconds between checks whether or not any path is due for refresh.
+const CACHE_TICK_INTERVAL_SECS: u64 = 1;
+// Multiplier of the last refresh duration when refresh takes longer than the interval.
+const REFRESH_BACKOFF_FACTOR: u64 = 2;
+// Max number of seconds to wait between refreshes when backing off.
+const MAX_REFRESH_BACKOFF_SECS: u64 = 300;
 
 // Global txid sequence
-static GLOBAL_INDEX_SEQ: AtomicUsize = ATOMIC_USIZE_INIT;
+static GLOBAL_INDEX_SEQ: AtomicUsize = AtomicUsize::new(0);
 
 
 ///////////////////////////////////////////////////////////
@@ -89,7 +107,7 @@ impl <F: FnOnce()> FnBox for F {
   }
 }
 
-type Job = Box<FnBox + Send + 'static>;
+type Job = Box<dyn FnBox + Send + 'static>;
 
 enum Message {
   NewJob(Job),
@@ -125,14 +143,14 @@ impl Worker {
 // Memory size
 ///////////////////////////////////////////////////////////
 
-trait MemoryUsed {
+pub trait MemoryUsed {
   // Returns size in bytes.
   fn memory_used(&self) -> usize;
 }
 
 impl MemoryUsed for String {
   fn memory_used(&self) -> usize {
-    self.as_bytes().len()
+    self.len()
   }
 }
 
@@ -144,7 +162,10 @@ impl<T: MemoryUsed> MemoryUsed for Option<T> {
 
 impl MemoryUsed for FileIndex {
   fn memory_used(&self) -> usize {
-    size_of::<FileIndex>() + size_of::<Vec<u8>>() + self.content.len()
+    let content_len = self.content.as_ref().map(|content| content.len()).unwrap_or(0);
+    self.symbols.iter().fold(size_of::<FileIndex>() + content_len, |n, symbol| {
+      n + symbol.memory_used()
+    })
   }
 }
 
@@ -152,9 +173,9 @@ impl MemoryUsed for FileIndexTree {
   fn memory_used(&self) -> usize {
     let entries_size = match self {
       FileIndexTree::Null(_) => 0,
-      FileIndexTree::List(_, ref vec) => {
-        vec.iter().fold(0, |n, (key, value)| {
-          n + key.memory_used() + value.memory_used()
+      FileIndexTree::List(_, ref vec, ref names) => {
+        vec.iter().fold(names.memory_used(), |n, (key, _, value)| {
+          n + key.memory_used() + size_of::<Extension>() + value.memory_used()
         })
       }
     };
@@ -174,20 +195,27 @@ impl MemoryUsed for Cache {
 // File Index
 ///////////////////////////////////////////////////////////
 
-// Simple struct to keep the content of the file in memory.
+// Simple struct to keep the content of the file in memory along with symbols defined in it.
+// Content is only kept for files within policy size limits.
 pub struct FileIndex {
-  content: Vec<u8>
+  content: Option<Vec<u8>>,
+  symbols: Vec<Symbol>
 }
 
 impl FileIndex {
-  // Creates new file index from content.
-  pub fn new(content: Vec<u8>) -> Self {
-    Self { content }
+  // Creates new file index from content and symbols.
+  pub fn new(content: Option<Vec<u8>>, symbols: Vec<Symbol>) -> Self {
+    Self { content, symbols }
+  }
+
+  // Returns content as slice of bytes, or None if content is not cached.
+  pub fn content(&self) -> Option<&[u8]> {
+    self.content.as_deref()
   }
 
-  // Returns content as slice of bytes.
-  pub fn content(&self) -> &[u8] {
-    self.content.as_slice()
+  // Returns symbols defined in the file.
+  pub fn symbols(&self) -> &[Symbol] {
+    self.symbols.as_slice()
   }
 }
 
@@ -199,6 +227,7 @@ impl FileIndex {
 pub struct FileIndexTreeStatistics {
   txid: usize,
   memory_used: usize,
+  names_memory_used: usize,
   num_entries: usize,
   indexed_fraction: f32
 }
@@ -208,10 +237,11 @@ impl FileIndexTreeStatistics {
   pub fn new(
     txid: usize,
     memory_used: usize,
+    names_memory_used: usize,
     num_entries: usize,
     indexed_fraction: f32
   ) -> Self {
-    Self { txid, memory_used, num_entries, indexed_fraction }
+    Self { txid, memory_used, names_memory_used, num_entries, indexed_fraction }
   }
 
   // Returns txid of the file index tree.
@@ -219,11 +249,16 @@ impl FileIndexTreeStatistics {
     self.txid
   }
 
-  // Memory used by file index tree.
+  // Memory used by file index tree, including file name index.
   pub fn memory_used(&self) -> usize {
     self.memory_used
   }
 
+  // Memory used by file name index.
+  pub fn names_memory_used(&self) -> usize {
+    self.names_memory_used
+  }
+
   // Number of entries in the file index tree.
   pub fn num_entries(&self) -> usize {
     self.num_entries
@@ -235,18 +270,29 @@ impl FileIndexTreeStatistics {
   }
 }
 
+// Entry of the index tree: path of the file, its language detected once when the file is
+// indexed, and its index, if available.
+pub type TreeEntry = (String, Extension, Option<FileIndex>);
+
 // In-memory append-only index of the project.
 // Keeps track of the list of files for the project and their corresponding file index,
-// if available.
+// if available, and suffix tree of lowercase file names that maps to positions in the list.
 pub enum FileIndexTree {
   Null(usize),
-  List(usize, Arc<Vec<(String, Option<FileIndex>)>>)
+  List(usize, Arc<Vec<TreeEntry>>, Arc<SuffixTree>)
 }
 
 impl FileIndexTree {
   // Creates new index tree as list.
-  pub fn new(info: Vec<(String, Option<FileIndex>)>) -> Self {
-    FileIndexTree::List(GLOBAL_INDEX_SEQ.fetch_add(1, Ordering::SeqCst), Arc::new(info))
+  pub fn new(info: Vec<TreeEntry>) -> Self {
+    let mut names = SuffixTree::new();
+    for (i, (path, _, _)) in info.iter().enumerate() {
+      if let Some(fname) = Path::new(path).file_name().and_then(|os| os.to_str()) {
+        names.put(fname.to_lowercase().as_bytes(), i);
+      }
+    }
+    let txid = GLOBAL_INDEX_SEQ.fetch_add(1, Ordering::SeqCst);
+    FileIndexTree::List(txid, Arc::new(info), Arc::new(names))
   }
 
   // Creates new index tree as no-op.
@@ -258,7 +304,7 @@ impl FileIndexTree {
   pub fn is_empty(&self) -> bool {
     match self {
       FileIndexTree::Null(_) => true,
-      FileIndexTree::List(_, vec) => vec.is_empty()
+      FileIndexTree::List(_, vec, _) => vec.is_empty()
     }
   }
 
@@ -266,7 +312,7 @@ impl FileIndexTree {
   pub fn txid(&self) -> usize {
     match self {
       FileIndexTree::Null(txid) => *txid,
-      FileIndexTree::List(txid, _) => *txid
+      FileIndexTree::List(txid, _, _) => *txid
     }
   }
 
@@ -274,24 +320,431 @@ impl FileIndexTree {
   pub fn stats(&self) -> FileIndexTreeStatistics {
     match self {
       FileIndexTree::Null(txid) => {
-        FileIndexTreeStatistics::new(*txid, self.memory_used(), 0, 0f32)
+        FileIndexTreeStatistics::new(*txid, self.memory_used(), 0, 0, 0f32)
       },
-      FileIndexTree::List(txid, ref vec) => {
-        let indexed = vec.iter().filter(|(_, entry)| entry.is_some()).count();
+      FileIndexTree::List(txid, ref vec, ref names) => {
+        let indexed = vec.iter()
+          .filter(|(_, _, entry)| entry.as_ref().and_then(|idx| idx.content()).is_some())
+          .count();
         let total = vec.len();
         let fraction = if total == 0 { 0f32 } else { indexed as f32 / total as f32 };
-        FileIndexTreeStatistics::new(*txid, self.memory_used(), total, fraction)
+        FileIndexTreeStatistics::new(
+          *txid, self.memory_used(), names.memory_used(), total, fraction)
       }
     }
   }
 
   // List of entries.
-  pub fn entries(&self) -> Option<Arc<Vec<(String, Option<FileIndex>)>>> {
+  pub fn entries(&self) -> Option<Arc<Vec<TreeEntry>>> {
     match self {
       FileIndexTree::Null(_) => None,
-      FileIndexTree::List(_, vec) => Some(vec.clone())
+      FileIndexTree::List(_, vec, _) => Some(vec.clone())
+    }
+  }
+
+  // Suffix tree of lowercase file names.
+  pub fn names(&self) -> Option<Arc<SuffixTree>> {
+    match self {
+      FileIndexTree::Null(_) => None,
+      FileIndexTree::List(_, _, names) => Some(names.clone())
+    }
+  }
+}
+
+///////////////////////////////////////////////////////////
+// Cache policy
+///////////////////////////////////////////////////////////
+
+// Settings that control how a project is indexed and refreshed.
+#[derive(Clone, Debug)]
+pub struct CachePolicy {
+  min_file_size: u64,
+  max_file_size: Option<u64>,
+  extensions: Extensions,
+  refresh_interval: Duration,
+  ignore: Vec<String>,
+  follow_links: bool,
+  revision: Option<Revision>,
+  encoding: Option<&'static Encoding>,
+  mappings: Mappings,
+  filters: WalkFilters
+}
+
+impl CachePolicy {
+  // Creates policy from cache parameters, unset values are taken from the project config or
+  // replaced with defaults.
+  pub fn from_params(params: &CacheParams) -> Result<Self, errors::Error> {
+    let config = config::load(&params.dir()?)?;
+    let defaults = config.cache();
+    let mut policy = Self {
+      mappings: config.mappings().clone(),
+      filters: WalkFilters::new(params.include_hidden(), params.include_ignored()),
+      ..Self::default()
+    };
+    if let Some(min_file_size) = params.min_file_size().or(defaults.min_file_size()) {
+      policy.min_file_size = min_file_size;
+    }
+    if let Some(max_file_size) = params.max_file_size().or(defaults.max_file_size()) {
+      if max_file_size < policy.min_file_size {
+        return err!("Max file size {} is less than min file size {}",
+          max_file_size, policy.min_file_size);
+      }
+      policy.max_file_size = Some(max_file_size);
+    }
+    if let Some(extensions) = params.extensions().or(defaults.extensions()) {
+      let mut vec = Vec::with_capacity(extensions.len());
+      for name in extensions {
+        let ext = name.trim_start_matches('.').parse::<Extension>()?;
+        if ext == Extension::UNKNOWN {
+          return err!("Unknown extension {}", name);
+        }
+        vec.push(ext);
+      }
+      policy.extensions = Extensions::with_extensions(vec);
+    }
+    if let Some(secs) = params.refresh_interval_secs().or(defaults.refresh_interval_secs()) {
+      if secs == 0 {
+        return err!("Refresh interval must be positive");
+      }
+      policy.refresh_interval = Duration::from_secs(secs);
+    }
+    // Ignore globs of the project are always applied.
+    policy.ignore = config.ignore().to_vec();
+    if let Some(ignore) = params.ignore() {
+      policy.ignore.extend_from_slice(ignore);
+    }
+    if let Some(follow_links) = params.follow_links().or(defaults.follow_links()) {
+      policy.follow_links = follow_links;
+    }
+    if let Some(rev) = params.rev() {
+      if rev.trim().is_empty() {
+        return err!("Empty revision, expected a branch, tag or commit");
+      }
+      policy.revision = Some(Revision { root: params.dir()?, rev: rev.trim().to_owned() });
+    }
+    if let Some(label) = params.encoding().or(defaults.encoding()) {
+      policy.encoding = Some(encoding::for_label(label)?);
+    }
+    Ok(policy)
+  }
+
+  // Returns encoding of files without byte order mark, None for UTF-8.
+  pub fn encoding(&self) -> Option<&'static Encoding> {
+    self.encoding
+  }
+
+  // Returns filters used to walk the directory.
+  pub fn filters(&self) -> WalkFilters {
+    self.filters
+  }
+
+  // Returns interval between refreshes.
+  pub fn refresh_interval(&self) -> Duration {
+    self.refresh_interval
+  }
+
+  // Returns true if file of the provided size should be kept in memory.
+  #[inline]
+  fn should_cache_content(&self, len: u64) -> bool {
+    len >= self.min_file_size && self.is_within_max_size(len)
+  }
+
+  // Returns true if file of the provided size does not exceed max file size.
+  #[inline]
+  fn is_within_max_size(&self, len: u64) -> bool {
+    self.max_file_size.map(|max| len <= max).unwrap_or(true)
+  }
+
+  // Builds overrides to exclude ignore globs when walking the root directory.
+  fn overrides(&self, root: &Path) -> Result<Override, errors::Error> {
+    let mut builder = OverrideBuilder::new(root);
+    for glob in &self.ignore {
+      builder.add(&format!("!{}", glob))?;
+    }
+    Ok(builder.build()?)
+  }
+
+  // Returns walk builder for the root directory with the filters, symbolic links and ignore
+  // globs of the policy.
+  fn walk_builder(&self, root: &Path, filters: WalkFilters) -> Result<WalkBuilder, errors::Error> {
+    let mut builder = filters.walk_builder(root, self.follow_links);
+    builder.overrides(self.overrides(root)?);
+    Ok(builder)
+  }
+}
+
+impl Default for CachePolicy {
+  fn default() -> Self {
+    Self {
+      min_file_size: MIN_BYTES_TO_CACHE,
+      max_file_size: None,
+      // Cache all extensions.
+      extensions: Extensions::all(),
+      refresh_interval: Duration::from_secs(CACHE_POLL_INTERVAL_SECS),
+      ignore: Vec::new(),
+      follow_links: false,
+      revision: None,
+      encoding: None,
+      mappings: Mappings::empty(),
+      filters: WalkFilters::default()
+    }
+  }
+}
+
+// Git revision of the repository that contains the root directory.
+// Revision is indexed from the object database, so working directory is left untouched.
+#[derive(Clone, Debug)]
+pub struct Revision {
+  root: PathBuf,
+  rev: String
+}
+
+/// Returns path under which the directory is cached, revisions are cached as `dir@rev`
+/// alongside the working directory.
+pub fn index_path(root: &Path, rev: Option<&str>) -> PathBuf {
+  match rev {
+    Some(rev) => PathBuf::from(format!("{}@{}", root.to_str().unwrap_or(""), rev.trim())),
+    None => root.to_path_buf()
+  }
+}
+
+///////////////////////////////////////////////////////////
+// Index status
+///////////////////////////////////////////////////////////
+
+/// State of the file index tree for a cached path.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IndexState {
+  Pending,
+  Indexing,
+  Ready,
+  Failed
+}
+
+
+/// Counters collected while refreshing a single path.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub struct RefreshCounters {
+  files_indexed: usize,
+  files_skipped: usize,
+  files_errored: usize,
+  bytes_read: u64
+}
+
+impl RefreshCounters {
+  // Number of files that were loaded into memory.
+  pub fn files_indexed(&self) -> usize {
+    self.files_indexed
+  }
+
+  // Number of files that were listed, but not loaded into memory.
+  pub fn files_skipped(&self) -> usize {
+    self.files_skipped
+  }
+
+  // Number of files or directory entries that could not be read.
+  pub fn files_errored(&self) -> usize {
+    self.files_errored
+  }
+
+  // Total number of bytes read into memory.
+  pub fn bytes_read(&self) -> u64 {
+    self.bytes_read
+  }
+}
+
+/// Detailed status of the cached path.
+/// Timestamps are milliseconds since Unix epoch.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct IndexStatus {
+  path: String,
+  state: IndexState,
+  txid: Option<usize>,
+  refresh_start: Option<u64>,
+  refresh_end: Option<u64>,
+  refresh_duration_sec: Option<f64>,
+  counters: RefreshCounters,
+  last_error: Option<String>,
+  commit: Option<String> // commit of the indexed revision, None for working directory
+}
+
+impl IndexStatus {
+  // Creates new status for the path in pending state.
+  pub fn new(path: String) -> Self {
+    Self {
+      path,
+      state: IndexState::Pending,
+      txid: None,
+      refresh_start: None,
+      refresh_end: None,
+      refresh_duration_sec: None,
+      counters: RefreshCounters::default(),
+      last_error: None,
+      commit: None
     }
   }
+
+  // Returns path for the status.
+  pub fn path(&self) -> &str {
+    &self.path
+  }
+
+  // Returns current state.
+  pub fn state(&self) -> IndexState {
+    self.state
+  }
+
+  // Returns txid of the latest tree built for the path, if any.
+  pub fn txid(&self) -> Option<usize> {
+    self.txid
+  }
+
+  // Returns counters of the latest finished refresh.
+  pub fn counters(&self) -> &RefreshCounters {
+    &self.counters
+  }
+
+  // Returns message of the error of the latest refresh, None if it succeeded.
+  pub fn last_error(&self) -> Option<&str> {
+    self.last_error.as_ref().map(|msg| msg.as_ref())
+  }
+
+  // Returns commit of the indexed revision, if any.
+  pub fn commit(&self) -> Option<&str> {
+    self.commit.as_deref()
+  }
+
+  // Returns true if path has never been refreshed, or interval has passed since the last
+  // refresh start.
+  // If the last refresh took longer than the interval, waits for a multiple of refresh
+  // duration instead, capped at max backoff.
+  fn is_due(&self, interval: Duration) -> bool {
+    let start = match self.refresh_start {
+      Some(start) => start,
+      None => return true
+    };
+    let interval_millis = interval.as_secs() * 1000 + interval.subsec_millis() as u64;
+    let duration_millis = self.refresh_duration_sec.map(|sec| (sec * 1e3) as u64).unwrap_or(0);
+    let wait_millis = if duration_millis > interval_millis {
+      let backoff = cmp::min(
+        duration_millis * REFRESH_BACKOFF_FACTOR,
+        MAX_REFRESH_BACKOFF_SECS * 1000
+      );
+      cmp::max(backoff, interval_millis)
+    } else {
+      interval_millis
+    };
+    epoch_millis().saturating_sub(start) >= wait_millis
+  }
+
+  // Marks refresh as started.
+  fn start_refresh(&mut self) {
+    self.state = IndexState::Indexing;
+    self.refresh_start = Some(epoch_millis());
+  }
+
+  // Marks refresh as finished successfully.
+  fn finish_refresh(&mut self, txid: usize, counters: RefreshCounters, duration: Duration) {
+    self.state = IndexState::Ready;
+    self.txid = Some(txid);
+    self.refresh_end = Some(epoch_millis());
+    self.refresh_duration_sec = Some(duration_sec(duration));
+    self.counters = counters;
+    self.last_error = None;
+  }
+
+  // Marks refresh as failed with error.
+  fn fail_refresh(&mut self, error: &errors::Error, duration: Duration) {
+    self.state = IndexState::Failed;
+    self.refresh_end = Some(epoch_millis());
+    self.refresh_duration_sec = Some(duration_sec(duration));
+    self.last_error = Some(error.to_string());
+  }
+}
+
+// Returns current time as milliseconds since Unix epoch.
+fn epoch_millis() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|d| d.as_secs() * 1000 + d.subsec_millis() as u64)
+    .unwrap_or(0)
+}
+
+// Converts duration into seconds.
+fn duration_sec(duration: Duration) -> f64 {
+  duration.as_secs() as f64 + duration.subsec_nanos() as f64 * 1e-9
+}
+
+///////////////////////////////////////////////////////////
+// Refresh progress
+///////////////////////////////////////////////////////////
+
+// Live progress of the currently running refresh for a path.
+// Counters are updated by the refresh thread without holding the cache lock.
+pub struct RefreshProgress {
+  start_time: Instant,
+  discovery_done: AtomicBool,
+  files_discovered: AtomicUsize,
+  files_read: AtomicUsize,
+  bytes_read: AtomicUsize
+}
+
+impl RefreshProgress {
+  // Creates new progress, start time is set to now.
+  fn new() -> Self {
+    Self {
+      start_time: Instant::now(),
+      discovery_done: AtomicBool::new(false),
+      files_discovered: AtomicUsize::new(0),
+      files_read: AtomicUsize::new(0),
+      bytes_read: AtomicUsize::new(0)
+    }
+  }
+
+  // Returns point-in-time snapshot of the progress.
+  // Remaining time is only estimated once all files have been discovered.
+  pub fn snapshot(&self, path: String, state: IndexState) -> ProgressSnapshot {
+    let elapsed = duration_sec(self.start_time.elapsed());
+    let discovery_done = self.discovery_done.load(Ordering::Relaxed);
+    let files_discovered = self.files_discovered.load(Ordering::Relaxed);
+    let files_read = self.files_read.load(Ordering::Relaxed);
+    let bytes_read = self.bytes_read.load(Ordering::Relaxed);
+
+    let estimated_remaining_sec = match state {
+      IndexState::Ready => Some(0f64),
+      IndexState::Indexing if discovery_done && files_read > 0 => {
+        let remaining = files_discovered.saturating_sub(files_read);
+        Some(elapsed / files_read as f64 * remaining as f64)
+      },
+      _ => None
+    };
+
+    ProgressSnapshot {
+      path,
+      state,
+      discovery_done,
+      files_discovered,
+      files_read,
+      bytes_read,
+      elapsed_sec: elapsed,
+      estimated_remaining_sec
+    }
+  }
+}
+
+/// Progress of the latest refresh of a cached path.
+/// `files_read` includes files that were listed without loading their content.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ProgressSnapshot {
+  path: String,
+  state: IndexState,
+  discovery_done: bool,
+  files_discovered: usize,
+  files_read: usize,
+  bytes_read: usize,
+  elapsed_sec: f64,
+  estimated_remaining_sec: Option<f64>
 }
 
 ///////////////////////////////////////////////////////////
@@ -321,28 +774,143 @@ impl CacheStatistics {
   }
 }
 
-pub type SharedCache = Arc<Mutex<Cache>>;
+// Map of path to content of the unsaved editor buffer.
+pub type Buffers = HashMap<String, Arc<Vec<u8>>>;
+
+// Cache shared between server and refresh threads.
+// Trees are immutable once built: readers clone the `Arc` of the tree under a short read
+// lock and search it without holding the lock, refreshes build a new tree outside of the
+// lock and publish it with a write lock.
+pub type SharedCache = Arc<RwLock<Cache>>;
 
 // Global cache that keeps track of paths and their corresponding index trees.
 pub struct Cache {
   // Map of path to index.
-  index: HashMap<String, Arc<FileIndexTree>>
+  index: HashMap<String, Arc<FileIndexTree>>,
+  // Map of path to index status.
+  status: HashMap<String, IndexStatus>,
+  // Map of path to progress of the latest refresh.
+  progress: HashMap<String, Arc<RefreshProgress>>,
+  // Map of path to cache policy.
+  policy: HashMap<String, CachePolicy>,
+  // Paths that are currently being refreshed.
+  in_flight: HashSet<String>,
+  // Paths that were requested to refresh regardless of their interval.
+  forced: HashSet<String>,
+  // Unsaved editor buffers keyed by path, searched instead of the file content.
+  buffers: Arc<Buffers>
+}
+
+impl Default for Cache {
+  fn default() -> Self {
+    Self::new()
+  }
 }
 
 impl Cache {
   // Creates new instance of cache.
   pub fn new() -> Self {
     Self {
-      index: HashMap::with_capacity(DEFAULT_HASH_MAP_CAPACITY)
+      index: HashMap::with_capacity(DEFAULT_HASH_MAP_CAPACITY),
+      status: HashMap::with_capacity(DEFAULT_HASH_MAP_CAPACITY),
+      progress: HashMap::with_capacity(DEFAULT_HASH_MAP_CAPACITY),
+      policy: HashMap::with_capacity(DEFAULT_HASH_MAP_CAPACITY),
+      in_flight: HashSet::with_capacity(DEFAULT_HASH_MAP_CAPACITY),
+      forced: HashSet::with_capacity(DEFAULT_HASH_MAP_CAPACITY),
+      buffers: Arc::new(Buffers::new())
+    }
+  }
+
+  // Sets content of the unsaved buffer for the path.
+  // Buffers are copied on write, so searches keep the snapshot they started with.
+  pub fn push_buffer(&mut self, path: &Path, content: Vec<u8>) {
+    if let Some(p) = path.to_str() {
+      Arc::make_mut(&mut self.buffers).insert(p.to_owned(), Arc::new(content));
     }
   }
 
+  // Removes unsaved buffer for the path, or all buffers if path is not provided.
+  pub fn clear_buffer(&mut self, path: Option<&Path>) {
+    match path.and_then(|path| path.to_str()) {
+      Some(p) => {
+        Arc::make_mut(&mut self.buffers).remove(p);
+      },
+      None => self.buffers = Arc::new(Buffers::new())
+    }
+  }
+
+  // Returns snapshot of unsaved buffers.
+  pub fn buffers(&self) -> Arc<Buffers> {
+    self.buffers.clone()
+  }
+
   // Adds index to the cache with deferred execution.
   // This does not block the thread to build an index.
-  pub fn add_index(&mut self, path: &Path) -> Result<(), errors::Error> {
+  // If path is already cached, only policy is updated and applied on the next refresh.
+  pub fn add_index(&mut self, path: &Path, policy: CachePolicy) -> Result<(), errors::Error> {
+    if let Some(p) = path.to_str() {
+      self.policy.insert(p.to_owned(), policy);
+      if let Some(status) = self.status.get_mut(p) {
+        // Revision is rebuilt on the next refresh even if commit has not changed.
+        status.commit = None;
+        return Ok(());
+      }
+      self.status.insert(p.to_owned(), IndexStatus::new(p.to_owned()));
+    }
     self.upsert_index(path, Arc::new(FileIndexTree::null()))
   }
 
+  // Returns cache policy for the path, or default policy if none was set.
+  pub fn get_policy(&self, path: &Path) -> CachePolicy {
+    path.to_str()
+      .and_then(|p| self.policy.get(p))
+      .cloned()
+      .unwrap_or_default()
+  }
+
+  // Returns list of paths that are due for refresh according to their policy, or were
+  // forced to refresh, and marks them as in-flight.
+  // Paths that are already being refreshed are skipped, so each path has at most one
+  // refresh running at a time.
+  fn schedule_refreshes(&mut self) -> Vec<String> {
+    let mut paths = Vec::with_capacity(self.index.len());
+    for key in self.index.keys() {
+      if self.in_flight.contains(key) {
+        continue;
+      }
+      let interval = self.get_policy(Path::new(key)).refresh_interval();
+      let is_due = self.forced.contains(key) ||
+        self.status.get(key).map(|status| status.is_due(interval)).unwrap_or(true);
+      if is_due {
+        paths.push(key.clone());
+      }
+    }
+    for path in &paths {
+      self.forced.remove(path);
+      self.in_flight.insert(path.clone());
+    }
+    paths
+  }
+
+  // Requests refresh of the path regardless of its interval.
+  // Refresh is dispatched on the next scheduler tick, or once the running refresh finishes.
+  fn request_refresh(&mut self, path: &Path) -> Result<(), errors::Error> {
+    match path.to_str() {
+      Some(p) if self.index.contains_key(p) => {
+        self.forced.insert(p.to_owned());
+        Ok(())
+      },
+      _ => err!("Path {} is not cached", path.to_str().unwrap_or(""))
+    }
+  }
+
+  // Marks refresh of the path as finished.
+  fn end_refresh(&mut self, path: &Path) {
+    if let Some(p) = path.to_str() {
+      self.in_flight.remove(p);
+    }
+  }
+
   // Adds new index, or updates existing one.
   // Update is based on index timestamp, we only keep the value with the latest timestamp.
   #[inline]
@@ -366,7 +934,7 @@ impl Cache {
   // Returns optional index if available, otherwise None.
   pub fn get_index(&self, path: &Path) -> Option<Arc<FileIndexTree>> {
     match path.to_str() {
-      Some(p) => self.index.get(p).map(|rf| rf.clone()),
+      Some(p) => self.index.get(p).cloned(),
       None => None
     }
   }
@@ -379,26 +947,53 @@ impl Cache {
     }
   }
 
-  // Removes index if it exists, otherwise is no-op.
-  pub fn remove_index(&mut self, path: &Path) -> Result<(), errors::Error> {
-    self.upsert_index(path, Arc::new(FileIndexTree::null()))
-  }
-
   // Returns list of paths in the cache.
   pub fn paths(&self) -> Vec<String> {
     let mut paths = Vec::with_capacity(self.index.len());
-    let mut iter = self.index.keys();
-    while let Some(key) = iter.next() {
+    for key in self.index.keys() {
       paths.push(key.clone());
     }
     paths
   }
 
+  // Applies function to the status of the path, if path is cached.
+  fn update_status<F>(&mut self, path: &Path, func: F) where F: FnOnce(&mut IndexStatus) {
+    if let Some(status) = path.to_str().and_then(|p| self.status.get_mut(p)) {
+      func(status);
+    }
+  }
+
+  // Registers progress for a new refresh of the path, replacing the previous one.
+  fn start_progress(&mut self, path: &Path) -> Arc<RefreshProgress> {
+    let progress = Arc::new(RefreshProgress::new());
+    if let Some(p) = path.to_str() {
+      self.progress.insert(p.to_owned(), progress.clone());
+    }
+    progress
+  }
+
+  // Returns progress of the latest refresh of the path.
+  // Returns None if path is not cached, progress is empty if refresh has not started yet.
+  pub fn progress(&self, path: &Path) -> Option<ProgressSnapshot> {
+    let p = path.to_str()?;
+    let status = self.status.get(p)?;
+    match self.progress.get(p) {
+      Some(progress) => Some(progress.snapshot(p.to_owned(), status.state())),
+      None => Some(RefreshProgress::new().snapshot(p.to_owned(), status.state()))
+    }
+  }
+
+  // Returns list of statuses for all cached paths, sorted by path.
+  pub fn status(&self) -> Vec<IndexStatus> {
+    let mut statuses = self.status.values().cloned().collect::<Vec<_>>();
+    statuses.sort_by(|a, b| a.path.cmp(&b.path));
+    statuses
+  }
+
   // Returns statistics of the cache
   pub fn stats(&self) -> CacheStatistics {
     let mut stats = Vec::with_capacity(self.index.len());
-    let mut iter = self.index.values();
-    while let Some(value) = iter.next() {
+    for value in self.index.values() {
       stats.push(value.stats());
     }
     CacheStatistics::new(self.memory_used(), stats)
@@ -407,131 +1002,292 @@ impl Cache {
 
 // Creates shared cache.
 pub fn create_cache() -> SharedCache {
-  Arc::new(Mutex::new(Cache::new()))
+  Arc::new(RwLock::new(Cache::new()))
 }
 
 // Returns true if path contains in the cache.
 pub fn contains_cache(cache: &SharedCache, path: &Path) -> Result<bool, errors::Error> {
-  let cache = cache.lock()?;
+  let cache = cache.read()?;
   Ok(cache.contains(path))
 }
 
 // Returns cache statistics.
 pub fn cache_stats(cache: &SharedCache) -> Result<CacheStatistics, errors::Error> {
-  let cache = cache.lock()?;
+  let cache = cache.read()?;
   Ok(cache.stats())
 }
 
-// Adds entry to the cache.
-pub fn update_cache(cache: &SharedCache, path: &Path) -> Result<(), errors::Error> {
-  let mut cache = cache.lock()?;
-  cache.add_index(path)
+// Returns status for every cached path.
+pub fn cache_status(cache: &SharedCache) -> Result<Vec<IndexStatus>, errors::Error> {
+  let cache = cache.read()?;
+  Ok(cache.status())
 }
 
-// Internal function to start search.
-pub fn search(
+// Returns progress of the latest refresh for the path, or None if path is not cached.
+pub fn cache_progress(
+  cache: &SharedCache,
+  path: &Path
+) -> Result<Option<ProgressSnapshot>, errors::Error> {
+  let cache = cache.read()?;
+  Ok(cache.progress(path))
+}
+
+// Forces refresh of the cached path.
+// Refresh runs on the refresh pool of the scheduler, so it is subject to the same
+// single-flight rule as periodic refreshes.
+pub fn force_refresh(cache: &SharedCache, path: &Path) -> Result<(), errors::Error> {
+  let mut cache = cache.write()?;
+  cache.request_refresh(path)
+}
+
+// Sets content of the unsaved buffer for the path.
+pub fn push_buffer(
   cache: &SharedCache,
-  searcher: Searcher,
-  content_matcher: MatcherSpec,
   path: &Path,
-  ext_check: Extensions,
+  content: Vec<u8>
+) -> Result<(), errors::Error> {
+  let mut cache = cache.write()?;
+  cache.push_buffer(path, content);
+  Ok(())
+}
+
+// Removes unsaved buffer for the path, or all buffers if path is not provided.
+pub fn clear_buffer(cache: &SharedCache, path: Option<&Path>) -> Result<(), errors::Error> {
+  let mut cache = cache.write()?;
+  cache.clear_buffer(path);
+  Ok(())
+}
+
+// Returns cache policy for the path, or default policy if path is not cached.
+pub fn policy(cache: &SharedCache, path: &Path) -> Result<CachePolicy, errors::Error> {
+  let cache = cache.read()?;
+  Ok(cache.get_policy(path))
+}
+
+/// Returns walk builder for the root directory with the filters.
+/// Symbolic links and ignore globs follow the cache policy of the root, so that search and
+/// cache refresh see the same files, project config is used if the root is not cached.
+pub fn walk_builder(
+  cache: &SharedCache,
+  root: &Path,
+  filters: WalkFilters,
+  config: &ProjectConfig
+) -> Result<WalkBuilder, errors::Error> {
+  let policy = {
+    let cache = cache.read()?;
+    root.to_str().and_then(|path| cache.policy.get(path)).cloned()
+  };
+  match policy {
+    Some(policy) => policy.walk_builder(root, filters),
+    None => {
+      let follow_links = config.cache().follow_links().unwrap_or(false);
+      let mut builder = filters.walk_builder(root, follow_links);
+      builder.overrides(config.overrides());
+      Ok(builder)
+    }
+  }
+}
+
+// Returns snapshot of unsaved buffers.
+pub fn buffers(cache: &SharedCache) -> Result<Arc<Buffers>, errors::Error> {
+  let cache = cache.read()?;
+  Ok(cache.buffers())
+}
+
+// Adds entry to the cache with provided policy.
+pub fn update_cache(
+  cache: &SharedCache,
+  path: &Path,
+  policy: CachePolicy
+) -> Result<(), errors::Error> {
+  if !path.is_dir() {
+    return err!("Path {} is not a directory", path.to_str().unwrap_or(""));
+  }
+  // Validate ignore globs and revision before adding the path.
+  policy.overrides(path)?;
+  if let Some(ref revision) = policy.revision {
+    resolve_revision(revision)?;
+  }
+  let key = index_path(path, policy.revision.as_ref().map(|revision| revision.rev.as_str()));
+  let mut cache = cache.write()?;
+  cache.add_index(&key, policy)
+}
+
+// Returns snapshot of the index tree for the path.
+// Returns None if path is not cached or tree has not been built yet.
+pub fn snapshot(
+  cache: &SharedCache,
+  path: &Path
+) -> Result<Option<Arc<FileIndexTree>>, errors::Error> {
+  let cache = cache.read()?;
+  Ok(cache.get_index(path).filter(|tree| tree.entries().is_some()))
+}
+
+// Internal function to start search.
+// Searches pinned snapshot of the index tree, concurrent refreshes do not affect results.
+pub fn search(
+  index: &FileIndexTree,
+  opts: &Arc<SearchOptions>,
   file_counter: Arc<AtomicUsize>,
   content_counter: Arc<AtomicUsize>,
   fsx: &mpsc::Sender<FileItem>,
   csx: &mpsc::Sender<ContentItem>
 ) -> Result<(), errors::Error> {
-  let index_opt = {
-    let arc = cache.lock()?;
-    arc.get_index(path)
+  let (limits, match_path) = (opts.limits(), opts.match_path());
+  // File names are looked up in the suffix tree for substring patterns, regular expressions,
+  // relative paths, scoped searches and archive entries are matched while scanning entries.
+  let match_names = match opts.matcher().direct_ref() {
+    Some(direct) if !match_path && !opts.has_scope() && !opts.archives() => {
+      search_names(index, opts.root(), direct, limits, &file_counter, fsx);
+      false
+    },
+    _ => true
   };
 
   // Start search
-  if let Some(index) = index_opt {
-    if let Some(arc) = index.entries() {
-      let splits = split(&arc, DEFAULT_THREAD_POOL_SIZE);
-      let tp = ThreadPool::new(DEFAULT_THREAD_POOL_SIZE);
-      for i in 0..splits.len() {
-        let start = splits[i];
-        let end = if i < splits.len() - 1 { splits[i + 1] } else { arc.len() };
-
-        let arc = arc.clone();
-        let fsx = fsx.clone();
-        let csx = csx.clone();
-        let mut searcher = searcher.clone();
-        let content_matcher = content_matcher.clone();
-        let file_counter = file_counter.clone();
-        let content_counter = content_counter.clone();
-        let ext_check = ext_check.clone();
-
-        tp.execute(move || {
-          for (path_str, file_index) in &arc[start..end] {
-            let path = Path::new(path_str);
-            let fname = path.file_name().and_then(|os| os.to_str()).unwrap_or("");
-
-            let ext = path.extension()
-              .and_then(|os| os.to_str())
-              .unwrap_or("")
-              .parse::<Extension>()
-              .unwrap();
-
-            // Search if file name matches pattern.
-            if fname.len() > 0 && content_matcher.is_match(fname) {
-              if file_counter.fetch_add(1, Ordering::Relaxed) <= FILE_MAX_MATCHES {
-                let _ = fsx.send(FileItem::new(path_str.to_owned(), ext));
-              }
-            }
+  if let Some(arc) = index.entries() {
+    let splits = split(&arc, DEFAULT_THREAD_POOL_SIZE);
+    let tp = ThreadPool::new(DEFAULT_THREAD_POOL_SIZE);
+    for i in 0..splits.len() {
+      let start = splits[i];
+      let end = if i < splits.len() - 1 { splits[i + 1] } else { arc.len() };
+
+      let arc = arc.clone();
+      let fsx = fsx.clone();
+      let csx = csx.clone();
+      let opts = opts.clone();
+      let file_counter = file_counter.clone();
+      let content_counter = content_counter.clone();
+
+      tp.execute(move || {
+        let mut searcher = opts.searcher();
+        let content_matcher = opts.matcher();
+        let root = opts.root();
+        for (path_str, ext, file_index) in &arc[start..end] {
+          let ext = *ext;
+          if !opts.in_scope(path_str) {
+            continue;
+          }
+          let path = Path::new(path_str);
+          let fname = file_match_target(root, path_str, match_path);
+
+
+          // Search if file name matches pattern.
+          if match_names && !fname.is_empty() && content_matcher.is_match(fname) &&
+              file_counter.fetch_add(1, Ordering::Relaxed) <= limits.files() {
+            let rel_path = relative_path(root, path_str).to_owned();
+            let _ = fsx.send(FileItem::new(path_str.to_owned(), rel_path, ext));
+          }
 
-            if ext_check.is_supported_extension(ext) {
-              if content_counter.load(Ordering::Relaxed) <= CONTENT_MAX_MATCHES {
+          // Archives are expanded into entries that are searched instead of the raw bytes.
+          let format = if opts.archives() { archive::Format::from_path(path_str) } else { None };
+          if let Some(format) = format {
+            let content = file_index.as_ref().and_then(|idx| idx.content());
+            let _ = archive::for_each_entry(path_str, format, content, |entry| {
+              let fname = file_match_target(root, entry.path(), match_path);
+              if match_names && entry.path() != path_str && content_matcher.is_match(fname) &&
+                  file_counter.fetch_add(1, Ordering::Relaxed) <= limits.files() {
+                let rel_path = relative_path(root, entry.path()).to_owned();
+                let _ = fsx.send(FileItem::new(entry.path().to_owned(), rel_path, entry.ext()));
+              }
+              if opts.ext_check().is_supported_extension(entry.ext()) &&
+                  content_counter.load(Ordering::Relaxed) <= limits.content() {
                 let collector = Collector::new(
                   csx.clone(),
                   content_counter.clone(),
-                  path_str.to_owned(),
+                  limits.content(),
+                  entry.path().to_owned(),
                   content_matcher.clone(),
-                  ext
-                );
-                if content_matcher.is_regex() {
-                  let matcher = content_matcher.clone().as_regex();
-                  if let Some(idx) = file_index {
-                    searcher.search_slice(matcher, idx.content(), collector).unwrap();
-                  } else {
-                    searcher.search_path(matcher, path, collector).unwrap();
-                  }
-                } else {
-                  let matcher = content_matcher.clone().as_direct();
-                  if let Some(idx) = file_index {
-                    searcher.search_slice(matcher, idx.content(), collector).unwrap();
-                  } else {
-                    searcher.search_path(matcher, path, collector).unwrap();
-                  }
-                }
+                  entry.ext(),
+                  false
+                ).with_encoding(encoding::detect(entry.content(), opts.default_encoding()));
+                search_slice(&mut searcher, content_matcher, entry.content(), collector);
+              }
+            });
+          } else if opts.ext_check().is_supported_extension(ext) &&
+              content_counter.load(Ordering::Relaxed) <= limits.content() {
+            // Unsaved buffer takes precedence over cached content.
+            let buffer = opts.buffers().get(path_str).map(|buffer| buffer.as_slice());
+            let content = file_index.as_ref().and_then(|idx| idx.content());
+            let collector = Collector::new(
+              csx.clone(),
+              content_counter.clone(),
+              limits.content(),
+              path_str.to_owned(),
+              content_matcher.clone(),
+              ext,
+              buffer.is_some()
+            );
+            match (buffer, content) {
+              (Some(buffer), _) => {
+                let collector = collector.with_encoding(encoding::detect(buffer, None));
+                search_buffer(&searcher, content_matcher, buffer, collector)
+              },
+              (None, Some(content)) => {
+                let encoding = encoding::detect(content, opts.default_encoding());
+                let collector = collector.with_encoding(encoding);
+                search_slice(&mut searcher, content_matcher, content, collector)
+              },
+              (None, None) => {
+                let encoding = encoding::detect_file(path_str, opts.default_encoding());
+                let collector = collector.with_encoding(encoding);
+                search_path(&mut searcher, content_matcher, path, collector)
               }
             }
+          }
 
-            if file_counter.load(Ordering::Relaxed) > FILE_MAX_MATCHES &&
-                content_counter.load(Ordering::Relaxed) > CONTENT_MAX_MATCHES {
-              break;
-            }
+          if file_counter.load(Ordering::Relaxed) > limits.files() &&
+              content_counter.load(Ordering::Relaxed) > limits.content() {
+            break;
           }
-        });
-      }
+        }
+      });
     }
   }
   Ok(())
 }
 
+// Finds file names that contain the pattern using suffix tree of the index.
+fn search_names(
+  index: &FileIndexTree,
+  root: &Path,
+  matcher: &DirectMatcher,
+  limits: Limits,
+  file_counter: &AtomicUsize,
+  fsx: &mpsc::Sender<FileItem>
+) {
+  if let (Some(entries), Some(names)) = (index.entries(), index.names()) {
+    // Suffix tree contains lowercase names, so candidates are exact matches only when
+    // matcher ignores case, otherwise every candidate needs to be checked.
+    let limit = if matcher.ignores_case() { limits.files().saturating_add(1) } else { usize::MAX };
+    let pattern = matcher.pattern().to_lowercase();
+    let mut ids = names.search(pattern.as_bytes(), limit).into_iter().collect::<Vec<_>>();
+    ids.sort();
+
+    for id in ids {
+      let (path_str, ext, _) = &entries[id];
+      let fname = Path::new(path_str).file_name().and_then(|os| os.to_str()).unwrap_or("");
+      if matcher.is_match(fname.as_bytes()).unwrap_or(false) {
+        if file_counter.fetch_add(1, Ordering::Relaxed) <= limits.files() {
+          let rel_path = relative_path(root, path_str).to_owned();
+          let _ = fsx.send(FileItem::new(path_str.to_owned(), rel_path, *ext));
+        } else {
+          break;
+        }
+      }
+    }
+  }
+}
+
 // Splits slice into number of splits of roughly equal length.
 // Returns vector with starting positions of each split.
 fn split<T>(slice: &[T], splits: usize) -> Vec<usize> {
   let mut buckets = vec![0; splits];
-  if splits > 0 {
-    let size = slice.len() / splits;
+  if let Some(size) = slice.len().checked_div(splits) {
     let mut left = slice.len() % splits;
     for i in 1..splits {
       buckets[i] = buckets[i - 1] + size + (if left > 0 { 1 } else { 0 });
-      if left > 0 {
-        left -= 1;
-      }
+      left = left.saturating_sub(1);
     }
   }
   buckets
@@ -541,82 +1297,478 @@ pub fn periodic_refresh(cache: &SharedCache) -> ThreadPool {
   let thread_pool = ThreadPool::new(1);
   let arc = cache.clone();
   thread_pool.execute(move || {
+    // Pool is kept for the lifetime of the scheduler, so refreshes can span several ticks.
+    let refresh_pool = ThreadPool::new(DEFAULT_THREAD_POOL_SIZE);
     loop {
-      let res = refresh_sync(&arc);
+      let res = refresh_sync(&arc, &refresh_pool);
       if let Err(error) = res {
         eprintln!("# ERROR Error during periodic refresh: {}", error);
       }
-      thread::sleep(Duration::from_secs(CACHE_POLL_INTERVAL_SECS));
+      thread::sleep(Duration::from_secs(CACHE_TICK_INTERVAL_SECS));
     }
   });
   thread_pool
 }
 
-// Refresh cache entries.
-pub fn refresh_sync(cache: &SharedCache) -> Result<(), errors::Error> {
+// Dispatches refresh of cache entries that are due for refresh.
+// Does not wait for refreshes to finish.
+pub fn refresh_sync(cache: &SharedCache, thread_pool: &ThreadPool) -> Result<(), errors::Error> {
   let paths = {
-    let cache = cache.lock()?;
-    cache.paths()
+    let mut cache = cache.write()?;
+    cache.schedule_refreshes()
   };
 
-  let thread_pool = ThreadPool::new(DEFAULT_THREAD_POOL_SIZE);
   for path in paths {
     let arc = cache.clone();
-    thread_pool.execute(move || {
-      let path_ref = Path::new(&path);
-      if let Err(error) = refresh_func(arc, path_ref) {
-        eprintln!("# ERROR Error during refresh: {}", error);
-      }
-    });
+    thread_pool.execute(move || run_refresh(arc, Path::new(&path)));
   }
 
   Ok(())
 }
 
+// Runs refresh for the in-flight path and marks it as finished.
+fn run_refresh(arc: SharedCache, path: &Path) {
+  if let Err(error) = refresh_func(arc.clone(), path) {
+    eprintln!("# ERROR Error during refresh: {}", error);
+  }
+  match arc.write() {
+    Ok(mut cache) => cache.end_refresh(path),
+    Err(error) => eprintln!("# ERROR Error during refresh: {}", error)
+  }
+}
+
 // Closure for refreshing cache entries.
+// Records refresh progress and outcome in the index status of the path.
+// Revisions are only rebuilt when they point to a different commit.
 fn refresh_func(arc: SharedCache, path: &Path) -> Result<(), errors::Error> {
+  let (policy, progress, indexed) = {
+    let mut cache = arc.write()?;
+    let indexed = path.to_str()
+      .and_then(|p| cache.status.get(p))
+      .and_then(|status| status.commit.clone());
+    cache.update_status(path, |status| status.start_refresh());
+    (cache.get_policy(path), cache.start_progress(path), indexed)
+  };
+
+  let start_time = Instant::now();
+  let res = match policy.revision {
+    Some(ref revision) => {
+      build_revision_tree(revision, &policy, &progress, indexed.as_deref())
+    },
+    None => {
+      build_tree(path, &policy, &progress).map(|(tree, counters)| Some((tree, counters, None)))
+    }
+  };
+  match res {
+    Ok(Some((tree, counters, commit))) => {
+      let txid = tree.txid();
+      let mut cache = arc.write()?;
+      // Path could have been removed while the tree was being built.
+      if !cache.contains(path) {
+        return Ok(());
+      }
+      cache.update_status(path, |status| {
+        status.finish_refresh(txid, counters, start_time.elapsed());
+        status.commit = commit;
+      });
+      cache.upsert_index(path, Arc::new(tree))
+    },
+    Ok(None) => {
+      let mut cache = arc.write()?;
+      cache.update_status(path, |status| {
+        let txid = status.txid.unwrap_or(0);
+        let counters = status.counters;
+        status.finish_refresh(txid, counters, start_time.elapsed())
+      });
+      Ok(())
+    },
+    Err(error) => {
+      let mut cache = arc.write()?;
+      cache.update_status(path, |status| status.fail_refresh(&error, start_time.elapsed()));
+      Err(error)
+    }
+  }
+}
+
+// Walks the directory and builds file index tree.
+// Files are discovered first, so that progress can report the total number of files to read.
+// Files that fail to be read are counted as errored and do not abort the refresh.
+fn build_tree(
+  path: &Path,
+  policy: &CachePolicy,
+  progress: &RefreshProgress
+) -> Result<(FileIndexTree, RefreshCounters), errors::Error> {
+  if !path.is_dir() {
+    return err!("Path {} is not a directory", path.to_str().unwrap_or(""));
+  }
+
   // This should match files similar to search module.
-  let mut walk = WalkBuilder::new(path)
-    .follow_links(false)
-    .standard_filters(true)
-    .same_file_system(true)
-    .build();
-
-  // Cache all extensions.
-  let extensions = Extensions::all();
-
-  let mut paths = Vec::with_capacity(DEFAULT_HASH_MAP_CAPACITY);
-  while let Some(res) = walk.next() {
-    if let Ok(entry) = res {
-      if entry.path().is_file() {
-        let path = entry.path();
-
-        let ext = path.extension()
-          .and_then(|os| os.to_str())
-          .unwrap_or("")
-          .parse::<Extension>()
-          .unwrap();
-
-        if extensions.is_supported_extension(ext) {
-          // Adds path to the file index.
-          // Does not check if path already exists in the cache.
-          if let Some(p) = path.to_str() {
-            let mut file = File::open(path)?;
-            if file.metadata()?.len() >= MIN_BYTES_TO_CACHE {
-              let mut content = Vec::with_capacity(MIN_BYTES_TO_CACHE as usize);
-              file.read_to_end(&mut content)?;
-              paths.push((p.to_owned(), Some(FileIndex::new(content))));
-            } else {
-              paths.push((p.to_owned(), None));
-            }
+  let walk = policy.walk_builder(path, policy.filters)?.build();
+
+  let extensions = &policy.extensions;
+
+  let mut counters = RefreshCounters::default();
+  let mut files = Vec::with_capacity(DEFAULT_HASH_MAP_CAPACITY);
+  for res in walk {
+    let entry = match res {
+      Ok(entry) => entry,
+      Err(_) => {
+        counters.files_errored += 1;
+        continue;
+      }
+    };
+
+    if entry.path().is_file() {
+      let path = entry.path();
+
+      let ext = policy.mappings.detect(path, None);
+
+      if extensions.is_supported_extension(ext) && path.to_str().is_some() {
+        files.push((path.to_path_buf(), ext));
+        progress.files_discovered.fetch_add(1, Ordering::Relaxed);
+      }
+    }
+  }
+  progress.discovery_done.store(true, Ordering::Relaxed);
+
+  let mut extractor = SymbolExtractor::new();
+  let mut paths = Vec::with_capacity(files.len());
+  for (file, ext) in files {
+    // Adds path to the file index.
+    // Does not check if path already exists in the cache.
+    let p = file.to_str().expect("valid path").to_owned();
+    match read_file_index(&file, ext, policy, &mut extractor) {
+      Ok(Some(file_index)) => {
+        if let Some(len) = file_index.content().map(|content| content.len()) {
+          counters.files_indexed += 1;
+          counters.bytes_read += len as u64;
+          progress.bytes_read.fetch_add(len, Ordering::Relaxed);
+        } else {
+          counters.files_skipped += 1;
+        }
+        paths.push((p, ext, Some(file_index)));
+      },
+      Ok(None) => {
+        counters.files_skipped += 1;
+        paths.push((p, ext, None));
+      },
+      Err(_) => {
+        counters.files_errored += 1;
+      }
+    }
+    progress.files_read.fetch_add(1, Ordering::Relaxed);
+  }
+
+  Ok((FileIndexTree::new(paths), counters))
+}
+
+// Reads file content and extracts symbols from it, language is detected by the caller.
+// Content is kept if file size is within policy limits, files below the minimum size are still
+// read to extract symbols. Returns None if there is nothing to keep for the file.
+fn read_file_index(
+  path: &Path,
+  ext: Extension,
+  policy: &CachePolicy,
+  extractor: &mut SymbolExtractor
+) -> Result<Option<FileIndex>, errors::Error> {
+  let mut file = File::open(path)?;
+  let len = file.metadata()?.len();
+
+  let cache_content = policy.should_cache_content(len);
+  let extract_symbols = extractor.supports(ext)? && policy.is_within_max_size(len);
+  if !cache_content && !extract_symbols {
+    return Ok(None);
+  }
+
+  let mut content = Vec::with_capacity(len as usize);
+  file.read_to_end(&mut content)?;
+  file_index(ext, content, policy, cache_content, extract_symbols, extractor)
+}
+
+// Builds file index from the content, returns None if there is nothing to keep for the file.
+// Binary content, i.e. content with NUL bytes that is not UTF-16 text, is never kept.
+fn file_index(
+  ext: Extension,
+  content: Vec<u8>,
+  policy: &CachePolicy,
+  cache_content: bool,
+  extract_symbols: bool,
+  extractor: &mut SymbolExtractor
+) -> Result<Option<FileIndex>, errors::Error> {
+  if encoding::is_binary(&content, policy.encoding) {
+    return Ok(None);
+  }
+  let symbols = if extract_symbols { extractor.extract(ext, &content)? } else { Vec::new() };
+  if cache_content {
+    Ok(Some(FileIndex::new(Some(content), symbols)))
+  } else if !symbols.is_empty() {
+    Ok(Some(FileIndex::new(None, symbols)))
+  } else {
+    Ok(None)
+  }
+}
+
+// Resolves revision to a commit of the repository that contains the root directory.
+fn resolve_revision(revision: &Revision) -> Result<(Repository, Oid), errors::Error> {
+  let repo = Repository::discover(&revision.root)?;
+  let commit = repo.revparse_single(&revision.rev)?.peel_to_commit()?.id();
+  Ok((repo, commit))
+}
+
+// Builds file index tree from the git object database at the revision.
+// Returns None if revision still points to the indexed commit.
+// Working directory can not be used as a fallback for revision content, so every file within
+// max size is kept in memory regardless of min size, larger and binary files are left out of
+// the tree.
+fn build_revision_tree(
+  revision: &Revision,
+  policy: &CachePolicy,
+  progress: &RefreshProgress,
+  indexed: Option<&str>
+) -> Result<Option<(FileIndexTree, RefreshCounters, Option<String>)>, errors::Error> {
+  let (repo, commit) = resolve_revision(revision)?;
+  let commit_id = commit.to_string();
+  if indexed == Some(commit_id.as_str()) {
+    return Ok(None);
+  }
+
+  let root = &revision.root;
+  let workdir = match repo.workdir() {
+    Some(workdir) => workdir.canonicalize()?,
+    None => return err!("Repository for {} has no working directory", root.display())
+  };
+  let prefix = match root.strip_prefix(&workdir) {
+    Ok(prefix) => prefix.to_path_buf(),
+    Err(_) => return err!("Path {} is not in the repository", root.display())
+  };
+  let mut tree = repo.find_commit(commit)?.tree()?;
+  if prefix.components().next().is_some() {
+    tree = tree.get_path(&prefix)?.to_object(&repo)?.peel_to_tree()?;
+  }
+
+  let overrides = policy.overrides(root)?;
+  let extensions = &policy.extensions;
+
+  let mut blobs = Vec::with_capacity(DEFAULT_HASH_MAP_CAPACITY);
+  tree.walk(TreeWalkMode::PreOrder, |dir, entry| {
+    let path = match entry.name() {
+      Some(name) => root.join(dir).join(name),
+      None => return TreeWalkResult::Skip
+    };
+    match entry.kind() {
+      Some(ObjectType::Tree) if overrides.matched(&path, true).is_ignore() => {
+        TreeWalkResult::Skip
+      },
+      // Symbolic links are stored as blobs with the link target as content.
+      Some(ObjectType::Blob) if entry.filemode() != 0o120_000 => {
+        let mut ext = policy.mappings.from_path(&path);
+        if ext == Extension::UNKNOWN && path.extension().is_none() {
+          if let Ok(blob) = repo.find_blob(entry.id()) {
+            ext = policy.mappings.detect(&path, Some(blob.content()));
           }
         }
+        if extensions.is_supported_extension(ext) && path.to_str().is_some() &&
+            !overrides.matched(&path, false).is_ignore() {
+          blobs.push((path, ext, entry.id()));
+          progress.files_discovered.fetch_add(1, Ordering::Relaxed);
+        }
+        TreeWalkResult::Ok
+      },
+      _ => TreeWalkResult::Ok
+    }
+  })?;
+  progress.discovery_done.store(true, Ordering::Relaxed);
+
+  let mut extractor = SymbolExtractor::new();
+  let mut counters = RefreshCounters::default();
+  let mut paths = Vec::with_capacity(blobs.len());
+  for (path, ext, id) in blobs {
+    let p = path.to_str().expect("valid path").to_owned();
+    match repo.find_blob(id) {
+      Ok(ref blob) if policy.is_within_max_size(blob.size() as u64) => {
+        let len = blob.size();
+        let extract_symbols = extractor.supports(ext)?;
+        let content = blob.content().to_vec();
+        match file_index(ext, content, policy, true, extract_symbols, &mut extractor) {
+          Ok(Some(file_index)) => {
+            counters.files_indexed += 1;
+            counters.bytes_read += len as u64;
+            progress.bytes_read.fetch_add(len, Ordering::Relaxed);
+            paths.push((p, ext, Some(file_index)));
+          },
+          Ok(None) => {
+            counters.files_skipped += 1;
+          },
+          Err(_) => {
+            counters.files_errored += 1;
+          }
+        }
+      },
+      Ok(_) => {
+        counters.files_skipped += 1;
+      },
+      Err(_) => {
+        counters.files_errored += 1;
       }
     }
+    progress.files_read.fetch_add(1, Ordering::Relaxed);
   }
 
-  let tree = FileIndexTree::new(paths);
-  // Update index for a path.
-  let mut cache = arc.lock()?;
-  cache.upsert_index(path, Arc::new(tree))
+  Ok(Some((FileIndexTree::new(paths), counters, Some(commit_id))))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use json;
+  use testutil::TempDir;
+
+  // Returns cache parameters for the directory merged with the fields.
+  fn cache_params(dir: &Path, fields: json::Value) -> CacheParams {
+    let mut value = json::json!({ "dir": dir.to_str().unwrap() });
+    for (key, field) in fields.as_object().unwrap() {
+      value[key] = field.clone();
+    }
+    json::from_value::<CacheParams>(value).unwrap()
+  }
+
+  #[test]
+  fn test_policy_extensions() {
+    let dir = TempDir::new(&[]);
+    let params = cache_params(&dir, json::json!({ "extensions": ["rs", ".toml"] }));
+    let policy = CachePolicy::from_params(&params).unwrap();
+    assert!(policy.extensions.is_supported_extension(Extension::RS));
+    assert!(policy.extensions.is_supported_extension(Extension::TOML));
+    assert!(!policy.extensions.is_supported_extension(Extension::UNKNOWN));
+
+    let params = cache_params(&dir, json::json!({ "extensions": ["rs", "rss"] }));
+    assert!(CachePolicy::from_params(&params).is_err());
+  }
+
+  #[test]
+  fn test_index_state_serde() {
+    let states = [IndexState::Pending, IndexState::Indexing, IndexState::Ready, IndexState::Failed];
+    let value = json::to_value(states).unwrap();
+    assert_eq!(value, json::json!(["pending", "indexing", "ready", "failed"]));
+    assert_eq!(json::from_value::<Vec<IndexState>>(value).unwrap(), states);
+  }
+
+  #[test]
+  fn test_schedule_refreshes_single_flight() {
+    let dir = TempDir::new(&[]);
+    let mut cache = Cache::new();
+    cache.add_index(&dir, CachePolicy::default()).unwrap();
+    let key = dir.to_str().unwrap().to_owned();
+    assert_eq!(cache.schedule_refreshes(), vec![key.clone()]);
+    assert!(cache.schedule_refreshes().is_empty());
+
+    // Forced refresh waits for the running one to finish.
+    cache.request_refresh(&dir).unwrap();
+    assert!(cache.schedule_refreshes().is_empty());
+    cache.update_status(&dir, |status| status.start_refresh());
+    cache.end_refresh(&dir);
+    assert_eq!(cache.schedule_refreshes(), vec![key.clone()]);
+    cache.end_refresh(&dir);
+
+    // Refresh has just started, so path is not due until the interval passes.
+    assert!(cache.schedule_refreshes().is_empty());
+    assert!(cache.request_refresh(&dir.join("missing")).is_err());
+  }
+
+  #[test]
+  fn test_index_status_is_due_backoff() {
+    let mut status = IndexStatus::new("dir".to_owned());
+    let interval = Duration::from_secs(1);
+    assert!(status.is_due(interval));
+
+    status.refresh_start = Some(epoch_millis() - 2_000);
+    status.refresh_duration_sec = Some(0.5);
+    assert!(status.is_due(interval));
+    // Refresh took longer than the interval, next one waits for twice its duration.
+    status.refresh_duration_sec = Some(1.5);
+    assert!(!status.is_due(interval));
+    status.refresh_start = Some(epoch_millis() - 3_000);
+    assert!(status.is_due(interval));
+  }
+
+  #[test]
+  fn test_upsert_index_keeps_latest_snapshot() {
+    let dir = TempDir::new(&[]);
+    let mut cache = Cache::new();
+    let older = Arc::new(FileIndexTree::new(vec![("a.rs".to_owned(), Extension::RS, None)]));
+    let newer = Arc::new(FileIndexTree::new(Vec::new()));
+    cache.upsert_index(&dir, newer.clone()).unwrap();
+    let pinned = cache.get_index(&dir).unwrap();
+    cache.upsert_index(&dir, older.clone()).unwrap();
+    assert_eq!(cache.get_index(&dir).unwrap().txid(), newer.txid());
+
+    // Search keeps the snapshot it pinned while a newer tree is published.
+    let latest = Arc::new(FileIndexTree::new(vec![("b.rs".to_owned(), Extension::RS, None)]));
+    cache.upsert_index(&dir, latest.clone()).unwrap();
+    assert_eq!(pinned.txid(), newer.txid());
+    assert!(pinned.is_empty());
+    assert_eq!(cache.get_index(&dir).unwrap().txid(), latest.txid());
+  }
+
+  #[test]
+  fn test_build_revision_tree() {
+    let dir = TempDir::new(&[("src/lib.rs", "fn committed() {}\n")]);
+    let root = dir.canonicalize().unwrap();
+    let repo = Repository::init(&root).unwrap();
+    let mut index = repo.index().unwrap();
+    index.add_path(Path::new("src/lib.rs")).unwrap();
+    let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+    let signature = git2::Signature::now("test", "test@example.com").unwrap();
+    repo.commit(Some("HEAD"), &signature, &signature, "init", &tree, &[]).unwrap();
+    dir.write("src/lib.rs", b"fn changed() {}\n");
+    dir.write("src/new.rs", b"fn untracked() {}\n");
+
+    let revision = Revision { root: root.clone(), rev: "HEAD".to_owned() };
+    let policy = CachePolicy::default();
+    let progress = RefreshProgress::new();
+    let (tree, counters, commit) = build_revision_tree(&revision, &policy, &progress, None)
+      .unwrap()
+      .unwrap();
+    assert_eq!(counters.files_indexed, 1);
+    match tree {
+      FileIndexTree::List(_, ref entries, _) => {
+        assert_eq!(entries.len(), 1);
+        let (ref path, ext, ref idx) = entries[0];
+        assert_eq!(Path::new(path), root.join("src/lib.rs"));
+        assert_eq!(ext, Extension::RS);
+        let content = idx.as_ref().and_then(|idx| idx.content());
+        assert_eq!(content, Some(&b"fn committed() {}\n"[..]));
+      },
+      FileIndexTree::Null(_) => panic!("Expected revision tree")
+    }
+
+    // Tree is not rebuilt while revision points to the indexed commit.
+    let indexed = build_revision_tree(&revision, &policy, &progress, commit.as_deref()).unwrap();
+    assert!(indexed.is_none());
+  }
+
+  // Returns sorted relative paths of files seen by the walk.
+  fn walk_files(cache: &SharedCache, root: &Path, config: &ProjectConfig) -> Vec<String> {
+    let walk = walk_builder(cache, root, WalkFilters::default(), config).unwrap();
+    let mut files = walk.build()
+      .filter_map(|entry| entry.ok())
+      .filter(|entry| entry.file_type().is_some_and(|t| t.is_file()))
+      .map(|entry| entry.path().strip_prefix(root).unwrap().to_str().unwrap().to_owned())
+      .collect::<Vec<_>>();
+    files.sort();
+    files
+  }
+
+  #[test]
+  fn test_walk_builder_policy() {
+    let dir = TempDir::new(&[("a.rs", "fn a() {}"), ("gen/b.rs", "fn b() {}")]);
+    let config = config::load(&dir).unwrap();
+    let cache: SharedCache = Arc::new(RwLock::new(Cache::new()));
+    assert_eq!(walk_files(&cache, &dir, &config), vec!["a.rs", "gen/b.rs"]);
+
+    let params = cache_params(&dir, json::json!({ "ignore": ["gen/**"] }));
+    let policy = CachePolicy::from_params(&params).unwrap();
+    cache.write().unwrap().policy.insert(dir.to_str().unwrap().to_owned(), policy);
+    assert_eq!(walk_files(&cache, &dir, &config), vec!["a.rs"]);
+  }
 }
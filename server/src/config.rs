@@ -0,0 +1,384 @@
+//! Project configuration.
+//! Settings are read from the user-level default `~/.omnisearch/config.toml` (or the path in
+//! `OMNISEARCH_CONFIG`) and `.omnisearch.toml` at the project root, project values take
+//! precedence, ignore globs and mappings of both files are combined. Both files are optional.
+//!
+//! ```toml
+//! ignore = ["target/", "node_modules/", "*.min.js"]
+//! case = "smart" # smart, sensitive or insensitive
+//!
+//! [languages]
+//! "*.jsm" = "js"
+//! "Tiltfile" = "bzl"
+//!
+//! [limits]
+//! files = 20
+//! content = 200
+//! sorted = 10000 # max number of files and lines collected before sorting or ranking
+//!
+//! [cache]
+//! extensions = ["rs", "toml"]
+//! max_file_size = 1000000
+//! refresh_interval_secs = 30
+//!
+//! [ranking]
+//! test = 10
+//! [ranking.paths]
+//! "legacy/**" = 40
+//! ```
+//!
+//! Globs follow gitignore conventions: globs without `/` match the file name at any depth,
+//! other globs are relative to the project root. Cache settings apply when the directory is
+//! added to the cache.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use errors;
+use ext::Extension;
+use globset::{self, Glob, GlobBuilder, GlobSetBuilder};
+use ignore::overrides::{Override, OverrideBuilder};
+use lang::Mappings;
+use params::CaseMode;
+use rank::PathPenalties;
+use search::Limits;
+use toml;
+
+/// Name of the project config file.
+pub const PROJECT_FILE: &str = ".omnisearch.toml";
+
+// Result limits section.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct LimitsConfig {
+  files: Option<usize>,
+  content: Option<usize>,
+  sorted: Option<usize>
+}
+
+/// Cache policy section, values are defaults for `/cache/add` parameters.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheConfig {
+  extensions: Option<Vec<String>>,
+  min_file_size: Option<u64>,
+  max_file_size: Option<u64>,
+  refresh_interval_secs: Option<u64>,
+  follow_links: Option<bool>,
+  encoding: Option<String>
+}
+
+impl CacheConfig {
+  /// Returns extensions to cache.
+  pub fn extensions(&self) -> Option<&[String]> {
+    self.extensions.as_deref()
+  }
+
+  /// Returns min file size to keep content in memory.
+  pub fn min_file_size(&self) -> Option<u64> {
+    self.min_file_size
+  }
+
+  /// Returns max file size to keep content in memory.
+  pub fn max_file_size(&self) -> Option<u64> {
+    self.max_file_size
+  }
+
+  /// Returns interval between refreshes in seconds.
+  pub fn refresh_interval_secs(&self) -> Option<u64> {
+    self.refresh_interval_secs
+  }
+
+  /// Returns true if symbolic links are followed.
+  pub fn follow_links(&self) -> Option<bool> {
+    self.follow_links
+  }
+
+  /// Returns encoding label of files without byte order mark.
+  pub fn encoding(&self) -> Option<&str> {
+    self.encoding.as_deref()
+  }
+
+  // Overrides values with the values set in other config.
+  fn merge(&mut self, other: CacheConfig) {
+    if other.extensions.is_some() {
+      self.extensions = other.extensions;
+    }
+    self.min_file_size = other.min_file_size.or(self.min_file_size);
+    self.max_file_size = other.max_file_size.or(self.max_file_size);
+    self.refresh_interval_secs = other.refresh_interval_secs.or(self.refresh_interval_secs);
+    self.follow_links = other.follow_links.or(self.follow_links);
+    if other.encoding.is_some() {
+      self.encoding = other.encoding;
+    }
+  }
+}
+
+// Ranking section.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RankingConfig {
+  depth: Option<i64>,
+  test: Option<i64>,
+  vendor: Option<i64>,
+  generated: Option<i64>,
+  paths: BTreeMap<String, i64>
+}
+
+// Content of the config file.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigFile {
+  ignore: Vec<String>,
+  languages: BTreeMap<String, String>,
+  case: Option<CaseMode>,
+  limits: LimitsConfig,
+  cache: CacheConfig,
+  ranking: RankingConfig
+}
+
+impl ConfigFile {
+  // Reads config file, returns default config if file does not exist.
+  fn read(path: &Path) -> Result<Self, errors::Error> {
+    if !path.is_file() {
+      return Ok(Self::default());
+    }
+    match toml::from_str(&fs::read_to_string(path)?) {
+      Ok(config) => Ok(config),
+      Err(error) => err!("Invalid config {}: {}", path.display(), error)
+    }
+  }
+}
+
+/// Project configuration with globs resolved against the project root.
+#[derive(Clone, Debug)]
+pub struct ProjectConfig {
+  ignore: Vec<String>,
+  overrides: Override,
+  mappings: Mappings,
+  case: CaseMode,
+  limits: Limits,
+  cache: CacheConfig,
+  penalties: PathPenalties
+}
+
+impl ProjectConfig {
+  /// Returns extra ignore globs.
+  pub fn ignore(&self) -> &[String] {
+    &self.ignore
+  }
+
+  /// Returns overrides that exclude ignore globs when walking the project root.
+  pub fn overrides(&self) -> Override {
+    self.overrides.clone()
+  }
+
+  /// Returns language mappings.
+  pub fn mappings(&self) -> &Mappings {
+    &self.mappings
+  }
+
+  /// Returns default case mode of the pattern.
+  pub fn case(&self) -> CaseMode {
+    self.case
+  }
+
+  /// Returns result limits.
+  pub fn limits(&self) -> Limits {
+    self.limits
+  }
+
+  /// Returns cache policy defaults.
+  pub fn cache(&self) -> &CacheConfig {
+    &self.cache
+  }
+
+  /// Returns ranking path penalties.
+  pub fn penalties(&self) -> &PathPenalties {
+    &self.penalties
+  }
+}
+
+// Returns path to the user-level config.
+fn user_path() -> Option<PathBuf> {
+  match env::var_os("OMNISEARCH_CONFIG") {
+    Some(path) => Some(PathBuf::from(path)),
+    None => env::var_os("HOME").map(|home| Path::new(&home).join(".omnisearch/config.toml"))
+  }
+}
+
+// Builds glob relative to the root, globs without `/` match file name at any depth.
+fn glob(root: &Path, glob: &str) -> Result<Glob, errors::Error> {
+  let glob = glob.trim().trim_end_matches('/');
+  let pattern = if glob.contains('/') {
+    let root = globset::escape(root.to_str().unwrap_or(""));
+    format!("{}/{}", root.trim_end_matches('/'), glob.trim_start_matches('/'))
+  } else {
+    format!("**/{}", glob)
+  };
+  Ok(GlobBuilder::new(&pattern).literal_separator(true).build()?)
+}
+
+/// Loads configuration of the project root.
+pub fn load(root: &Path) -> Result<ProjectConfig, errors::Error> {
+  load_with(root, user_path().as_deref())
+}
+
+// Loads configuration of the project root with the user-level config at the path, if any.
+fn load_with(root: &Path, user: Option<&Path>) -> Result<ProjectConfig, errors::Error> {
+  let mut files = Vec::with_capacity(2);
+  if let Some(path) = user {
+    files.push(ConfigFile::read(path)?);
+  }
+  files.push(ConfigFile::read(&root.join(PROJECT_FILE))?);
+
+  let mut ignore = Vec::new();
+  let mut case = CaseMode::Smart;
+  let mut limits = Limits::default();
+  let mut cache = CacheConfig::default();
+  let mut ranking = RankingConfig::default();
+  let mut mapping_globs = GlobSetBuilder::new();
+  let mut mapping_exts = Vec::new();
+  for file in files {
+    ignore.extend(file.ignore);
+    // Project mappings are added after user mappings, so they take precedence.
+    for (pattern, name) in &file.languages {
+      let ext = name.parse::<Extension>()?;
+      if ext == Extension::UNKNOWN {
+        return err!("Unknown language {} for {}", name, pattern);
+      }
+      mapping_globs.add(glob(root, pattern)?);
+      mapping_exts.push(ext);
+    }
+    case = file.case.unwrap_or(case);
+    limits = Limits::new(
+      file.limits.files.unwrap_or(limits.files()),
+      file.limits.content.unwrap_or(limits.content())
+    ).with_sorted(file.limits.sorted.unwrap_or(limits.sorted()));
+    cache.merge(file.cache);
+    ranking.depth = file.ranking.depth.or(ranking.depth);
+    ranking.test = file.ranking.test.or(ranking.test);
+    ranking.vendor = file.ranking.vendor.or(ranking.vendor);
+    ranking.generated = file.ranking.generated.or(ranking.generated);
+    ranking.paths.extend(file.ranking.paths);
+  }
+
+  let mut overrides = OverrideBuilder::new(root);
+  for pattern in &ignore {
+    overrides.add(&format!("!{}", pattern))?;
+  }
+
+  let mut penalty_globs = GlobSetBuilder::new();
+  let mut glob_penalties = Vec::with_capacity(ranking.paths.len());
+  for (pattern, penalty) in &ranking.paths {
+    penalty_globs.add(glob(root, pattern)?);
+    glob_penalties.push(*penalty);
+  }
+  let penalties = PathPenalties::new(ranking.depth, ranking.test, ranking.vendor,
+    ranking.generated, penalty_globs.build()?, glob_penalties);
+
+  Ok(ProjectConfig {
+    ignore,
+    overrides: overrides.build()?,
+    mappings: Mappings::new(mapping_globs.build()?, mapping_exts),
+    case,
+    limits,
+    cache,
+    penalties
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use testutil::TempDir;
+
+  // Creates directory with the user config and the project root with the project config.
+  fn create_dir(user: &str, project: &str) -> TempDir {
+    let project_path = format!("project/{}", PROJECT_FILE);
+    TempDir::new(&[("config.toml", user), (&project_path, project)])
+  }
+
+  // Loads config of the project root with the user config.
+  fn load_dir(dir: &TempDir) -> Result<ProjectConfig, errors::Error> {
+    load_with(&dir.join("project"), Some(&dir.join("config.toml")))
+  }
+
+  #[test]
+  fn test_load_defaults() {
+    let dir = create_dir("", "");
+    let config = load_with(&dir.join("project"), Some(&dir.join("missing.toml"))).unwrap();
+    assert_eq!(config.case(), CaseMode::Smart);
+    assert_eq!(config.limits().files(), Limits::default().files());
+    assert!(config.ignore().is_empty());
+    assert!(config.cache().extensions().is_none());
+  }
+
+  #[test]
+  fn test_load_project_precedence() {
+    let user = r#"
+      ignore = ["target/"]
+      case = "insensitive"
+      [limits]
+      files = 5
+      content = 50
+      [cache]
+      extensions = ["rs"]
+      max_file_size = 100
+    "#;
+    let project = r#"
+      ignore = ["*.min.js"]
+      case = "sensitive"
+      [limits]
+      files = 7
+      [cache]
+      refresh_interval_secs = 30
+    "#;
+    let dir = create_dir(user, project);
+    let root = dir.join("project");
+    let config = load_dir(&dir).unwrap();
+    assert_eq!(config.case(), CaseMode::Sensitive);
+    assert_eq!(config.limits().files(), 7);
+    assert_eq!(config.limits().content(), 50);
+    assert_eq!(config.ignore(), &["target/".to_owned(), "*.min.js".to_owned()][..]);
+    assert_eq!(config.cache().extensions(), Some(&["rs".to_owned()][..]));
+    assert_eq!(config.cache().max_file_size(), Some(100));
+    assert_eq!(config.cache().refresh_interval_secs(), Some(30));
+
+    let overrides = config.overrides();
+    assert!(overrides.matched(root.join("target"), true).is_ignore());
+    assert!(overrides.matched(root.join("web/app.min.js"), false).is_ignore());
+    assert!(!overrides.matched(root.join("web/app.js"), false).is_ignore());
+  }
+
+  #[test]
+  fn test_load_mappings() {
+    let user = r#"
+      [languages]
+      "*.jsm" = "js"
+      "Tiltfile" = "py"
+    "#;
+    let project = r#"
+      [languages]
+      "Tiltfile" = "bzl"
+      "tools/*.in" = "sh"
+    "#;
+    let dir = create_dir(user, project);
+    let root = dir.join("project");
+    let config = load_dir(&dir).unwrap();
+    let mappings = config.mappings();
+    assert_eq!(mappings.from_path(&root.join("lib/a.jsm")), Extension::JS);
+    assert_eq!(mappings.from_path(&root.join("deploy/Tiltfile")), Extension::BZL);
+    assert_eq!(mappings.from_path(&root.join("tools/run.in")), Extension::SH);
+    assert_eq!(mappings.from_path(&root.join("other/tools/run.in")), Extension::UNKNOWN);
+  }
+
+  #[test]
+  fn test_load_errors() {
+    assert!(load_dir(&create_dir("case = 1", "")).is_err());
+    assert!(load_dir(&create_dir("", "[languages]\n\"*.x\" = \"nope\"\n")).is_err());
+    assert!(load_dir(&create_dir("", "unknown = true")).is_err());
+  }
+}
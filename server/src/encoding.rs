@@ -0,0 +1,52 @@
+//! Text encoding detection.
+//! Byte order mark takes precedence over the explicit encoding of the project or query, content
+//! without either is searched as UTF-8. Transcoding itself is done by the searcher, so that
+//! files on disk and cached content are decoded the same way.
+
+use std::fs::File;
+use std::io::Read;
+
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8};
+use errors;
+use grep::searcher::{Encoding as SearcherEncoding};
+
+// Max length of the byte order mark.
+const MAX_BOM_BYTES: usize = 3;
+
+/// Returns encoding for the label, e.g. `utf-16le`, `latin1` or `windows-1252`.
+pub fn for_label(label: &str) -> Result<&'static Encoding, errors::Error> {
+  match Encoding::for_label_no_replacement(label.trim().as_bytes()) {
+    Some(encoding) => Ok(encoding),
+    None => err!("Unknown encoding {}", label)
+  }
+}
+
+/// Returns encoding to configure the searcher with, None to search content as UTF-8.
+pub fn searcher_encoding(encoding: Option<&'static Encoding>) -> Option<SearcherEncoding> {
+  // Encoding name is always a valid label.
+  encoding.and_then(|encoding| SearcherEncoding::new(encoding.name()).ok())
+}
+
+/// Returns encoding of the content: encoding of the byte order mark if content starts with one,
+/// otherwise the default encoding or UTF-8.
+pub fn detect(content: &[u8], default: Option<&'static Encoding>) -> &'static Encoding {
+  match Encoding::for_bom(content) {
+    Some((encoding, _)) => encoding,
+    None => default.unwrap_or(UTF_8)
+  }
+}
+
+/// Returns encoding of the file, reads only the byte order mark.
+pub fn detect_file(path: &str, default: Option<&'static Encoding>) -> &'static Encoding {
+  let mut head = Vec::with_capacity(MAX_BOM_BYTES);
+  if let Ok(file) = File::open(path) {
+    let _ = file.take(MAX_BOM_BYTES as u64).read_to_end(&mut head);
+  }
+  detect(&head, default)
+}
+
+/// Returns true if content has NUL bytes that are not part of UTF-16 text.
+pub fn is_binary(content: &[u8], default: Option<&'static Encoding>) -> bool {
+  let encoding = detect(content, default);
+  encoding != UTF_16LE && encoding != UTF_16BE && content.contains(&b'\x00')
+}
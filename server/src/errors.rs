@@ -6,10 +6,15 @@ use std::io;
 use std::str::Utf8Error;
 use std::sync::{mpsc, PoisonError};
 
+use git2::{Error as GitError};
+use globset::{Error as GlobError};
 use grep::matcher::{NoError as MatchError};
 use grep::regex::{Error as GrepRegexError};
 use grep::searcher::SinkError;
+use ignore::{Error as IgnoreError};
 use json::{Error as JsonError};
+use toml::de::{Error as TomlError};
+use zip::result::{ZipError};
 
 /// General error struct.
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
@@ -21,7 +26,7 @@ pub struct Error {
 impl Error {
   /// Creates a new error with provided message.
   pub fn new(msg: String) -> Self {
-    Self { err: true, msg: msg }
+    Self { err: true, msg }
   }
 }
 
@@ -54,12 +59,30 @@ impl convert::From<io::Error> for Error {
   }
 }
 
+impl convert::From<GitError> for Error {
+  fn from(value: GitError) -> Self {
+    Error::new(format!("Git error: {}", value.message()))
+  }
+}
+
+impl convert::From<GlobError> for Error {
+  fn from(value: GlobError) -> Self {
+    Error::new(format!("Glob error: {}", value))
+  }
+}
+
 impl convert::From<GrepRegexError> for Error {
   fn from(value: GrepRegexError) -> Self {
     Error::new(format!("Regex error: {}", value))
   }
 }
 
+impl convert::From<IgnoreError> for Error {
+  fn from(value: IgnoreError) -> Self {
+    Error::new(format!("Ignore error: {}", value))
+  }
+}
+
 impl convert::From<JsonError> for Error {
   fn from(value: JsonError) -> Self {
     Error::new(format!("JSON error: {}", value))
@@ -78,8 +101,20 @@ impl<T> convert::From<PoisonError<T>> for Error {
   }
 }
 
+impl convert::From<TomlError> for Error {
+  fn from(value: TomlError) -> Self {
+    Error::new(format!("TOML error: {}", value))
+  }
+}
+
 impl convert::From<Utf8Error> for Error {
   fn from(value: Utf8Error) -> Self {
     Error::new(format!("UTF8 error: {}", value))
   }
 }
+
+impl convert::From<ZipError> for Error {
+  fn from(value: ZipError) -> Self {
+    Error::new(format!("Zip error: {}", value))
+  }
+}
@@ -1,86 +1,70 @@
-use std::collections;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
+use std::fs;
+use std::path::Path;
 use std::str;
+use std::sync::OnceLock;
 
 use errors;
+use serde::de::{Deserialize, Deserializer};
 use serde::ser::{Serialize, Serializer};
+use toml;
 
-/// Enumeration for all text-based supported file extensions.
-#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Deserialize)]
-pub enum Extension {
-  BZL,
-  C,
-  COFFEE,
-  CPP,
-  CSS,
-  GO,
-  H,
-  HTML,
-  JAVA,
-  JS,
-  JSON,
-  JSX,
-  M,
-  MARKDOWN,
-  MD,
-  PHP,
-  PL,
-  PROTO,
-  PY,
-  PYST,
-  RB,
-  RS,
-  SCALA,
-  SCSS,
-  SH,
-  SQL,
-  SWIFT,
-  THRIFT,
-  TOML,
-  TSX,
-  XML,
-  YAML,
-  YML,
-  UNKNOWN
+/// Language of the file, used for language filters, symbols and result icons.
+/// Extension is an id in the language registry: built-in languages have constants below,
+/// languages added in the config file get ids after the built-in ones.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Extension(u16);
+
+impl Extension {
+  pub const BZL: Extension = Extension(0);
+  pub const C: Extension = Extension(1);
+  pub const COFFEE: Extension = Extension(2);
+  pub const CPP: Extension = Extension(3);
+  pub const CSS: Extension = Extension(4);
+  pub const DOCKERFILE: Extension = Extension(5);
+  pub const GO: Extension = Extension(6);
+  pub const GRADLE: Extension = Extension(7);
+  pub const GROOVY: Extension = Extension(8);
+  pub const H: Extension = Extension(9);
+  pub const HTML: Extension = Extension(10);
+  pub const JAVA: Extension = Extension(11);
+  pub const JS: Extension = Extension(12);
+  pub const JSON: Extension = Extension(13);
+  pub const JSX: Extension = Extension(14);
+  pub const KT: Extension = Extension(15);
+  pub const M: Extension = Extension(16);
+  pub const MAKEFILE: Extension = Extension(17);
+  pub const MARKDOWN: Extension = Extension(18);
+  pub const MD: Extension = Extension(19);
+  pub const PHP: Extension = Extension(20);
+  pub const PL: Extension = Extension(21);
+  pub const PROTO: Extension = Extension(22);
+  pub const PY: Extension = Extension(23);
+  pub const PYST: Extension = Extension(24);
+  pub const RB: Extension = Extension(25);
+  pub const RS: Extension = Extension(26);
+  pub const SCALA: Extension = Extension(27);
+  pub const SCSS: Extension = Extension(28);
+  pub const SH: Extension = Extension(29);
+  pub const SQL: Extension = Extension(30);
+  pub const SWIFT: Extension = Extension(31);
+  pub const TF: Extension = Extension(32);
+  pub const THRIFT: Extension = Extension(33);
+  pub const TOML: Extension = Extension(34);
+  pub const TS: Extension = Extension(35);
+  pub const TSX: Extension = Extension(36);
+  pub const XML: Extension = Extension(37);
+  pub const YAML: Extension = Extension(38);
+  pub const YML: Extension = Extension(39);
+  pub const UNKNOWN: Extension = Extension(u16::MAX);
 }
 
 impl fmt::Display for Extension {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-    match self {
-      Extension::BZL => write!(f, "bzl"),
-      Extension::C => write!(f, "c"),
-      Extension::COFFEE => write!(f, "coffee"),
-      Extension::CPP => write!(f, "cpp"),
-      Extension::CSS => write!(f, "css"),
-      Extension::GO => write!(f, "go"),
-      Extension::H => write!(f, "h"),
-      Extension::HTML => write!(f, "html"),
-      Extension::JAVA => write!(f, "java"),
-      Extension::JS => write!(f, "js"),
-      Extension::JSON => write!(f, "json"),
-      Extension::JSX => write!(f, "jsx"),
-      Extension::M => write!(f, "m"),
-      Extension::MARKDOWN => write!(f, "markdown"),
-      Extension::MD => write!(f, "md"),
-      Extension::PHP => write!(f, "php"),
-      Extension::PL => write!(f, "pl"),
-      Extension::PROTO => write!(f, "proto"),
-      Extension::PY => write!(f, "py"),
-      Extension::PYST => write!(f, "pyst"),
-      Extension::RB => write!(f, "rb"),
-      Extension::RS => write!(f, "rs"),
-      Extension::SCALA => write!(f, "scala"),
-      Extension::SCSS => write!(f, "scss"),
-      Extension::SH => write!(f, "sh"),
-      Extension::SQL => write!(f, "sql"),
-      Extension::SWIFT => write!(f, "swift"),
-      Extension::THRIFT => write!(f, "thrift"),
-      Extension::TOML => write!(f, "toml"),
-      Extension::TSX => write!(f, "tsx"),
-      Extension::XML => write!(f, "xml"),
-      Extension::YAML => write!(f, "yaml"),
-      Extension::YML => write!(f, "yml"),
-      Extension::UNKNOWN => write!(f, "<unknown>")
+    match registry().language(*self) {
+      Some(lang) => write!(f, "{}", lang.name()),
+      None => write!(f, "<unknown>")
     }
   }
 }
@@ -89,42 +73,7 @@ impl str::FromStr for Extension {
   type Err = errors::Error;
 
   fn from_str(s: &str) -> Result<Self, Self::Err> {
-    match s.trim().to_lowercase().as_ref() {
-      "bzl" => Ok(Extension::BZL),
-      "c" => Ok(Extension::C),
-      "coffee" => Ok(Extension::COFFEE),
-      "cpp" => Ok(Extension::CPP),
-      "css" => Ok(Extension::CSS),
-      "go" => Ok(Extension::GO),
-      "h" => Ok(Extension::H),
-      "html" => Ok(Extension::HTML),
-      "java" => Ok(Extension::JAVA),
-      "js" => Ok(Extension::JS),
-      "json" => Ok(Extension::JSON),
-      "jsx" => Ok(Extension::JSX),
-      "m" => Ok(Extension::M),
-      "markdown" => Ok(Extension::MARKDOWN),
-      "md" => Ok(Extension::MD),
-      "php" => Ok(Extension::PHP),
-      "pl" => Ok(Extension::PL),
-      "proto" => Ok(Extension::PROTO),
-      "py" => Ok(Extension::PY),
-      "pyst" => Ok(Extension::PYST),
-      "rb" => Ok(Extension::RB),
-      "rs" => Ok(Extension::RS),
-      "scala" => Ok(Extension::SCALA),
-      "scss" => Ok(Extension::SCSS),
-      "sh" => Ok(Extension::SH),
-      "sql" => Ok(Extension::SQL),
-      "swift" => Ok(Extension::SWIFT),
-      "thrift" => Ok(Extension::THRIFT),
-      "toml" => Ok(Extension::TOML),
-      "tsx" => Ok(Extension::TSX),
-      "xml" => Ok(Extension::XML),
-      "yaml" => Ok(Extension::YAML),
-      "yml" => Ok(Extension::YML),
-      _ => Ok(Extension::UNKNOWN)
-    }
+    Ok(registry().find(s))
   }
 }
 
@@ -134,57 +83,323 @@ impl Serialize for Extension {
   }
 }
 
+impl<'de> Deserialize<'de> for Extension {
+  fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where D: Deserializer<'de> {
+    let name = String::deserialize(deserializer)?;
+    Ok(registry().find(&name))
+  }
+}
+
+/// Language in the registry.
+#[derive(Clone, Debug)]
+pub struct Language {
+  ext: Extension,
+  name: String, // name in results and filters, e.g. `rs`
+  extensions: Vec<String>, // file extensions without dot, lowercase
+  filenames: Vec<String>, // well-known file names, e.g. `Makefile`
+  aliases: Vec<String>, // interpreter and editor mode names, e.g. `python3` or `rust`
+  line_comment: Option<String>,
+  block_comment: Option<(String, String)>
+}
+
+impl Language {
+  // Creates language without lookup keys in the registry.
+  fn new(
+    ext: Extension,
+    name: &str,
+    extensions: &[&str],
+    filenames: &[&str],
+    aliases: &[&str],
+    line_comment: Option<&str>,
+    block_comment: Option<(&str, &str)>
+  ) -> Self {
+    let strings = |values: &[&str]| values.iter().map(|value| value.to_string()).collect();
+    Self {
+      ext,
+      name: name.to_owned(),
+      extensions: strings(extensions),
+      filenames: strings(filenames),
+      aliases: strings(aliases),
+      line_comment: line_comment.map(|start| start.to_owned()),
+      block_comment: block_comment.map(|(start, end)| (start.to_owned(), end.to_owned()))
+    }
+  }
+
+  /// Returns extension of the language.
+  pub fn ext(&self) -> Extension {
+    self.ext
+  }
+
+  /// Returns name of the language.
+  pub fn name(&self) -> &str {
+    &self.name
+  }
+
+  /// Returns file extensions of the language.
+  pub fn extensions(&self) -> &[String] {
+    &self.extensions
+  }
+
+  /// Returns well-known file names of the language.
+  pub fn filenames(&self) -> &[String] {
+    &self.filenames
+  }
+
+  /// Returns start of the line comment, if any.
+  pub fn line_comment(&self) -> Option<&str> {
+    self.line_comment.as_deref()
+  }
+
+  /// Returns start and end of the block comment, if any.
+  pub fn block_comment(&self) -> Option<(&str, &str)> {
+    self.block_comment.as_ref().map(|(start, end)| (start.as_str(), end.as_str()))
+  }
+}
+
+// Language entry of the config file.
+#[derive(Debug, Deserialize)]
+struct LanguageConfig {
+  name: String,
+  #[serde(default)]
+  extensions: Vec<String>,
+  #[serde(default)]
+  filenames: Vec<String>,
+  #[serde(default)]
+  aliases: Vec<String>,
+  line_comment: Option<String>,
+  block_comment: Option<(String, String)>
+}
+
+// Config file with `[[language]]` entries.
+#[derive(Debug, Deserialize)]
+struct RegistryConfig {
+  #[serde(default)]
+  language: Vec<LanguageConfig>
+}
+
+/// Registry of languages with lookup by name, extension, file name and alias.
+#[derive(Clone, Debug)]
+pub struct Registry {
+  languages: Vec<Language>, // index is the id of the extension
+  names: HashMap<String, Extension>,
+  extensions: HashMap<String, Extension>,
+  filenames: HashMap<String, Extension>,
+  aliases: HashMap<String, Extension>
+}
+
+impl Registry {
+  /// Creates registry with built-in languages.
+  pub fn builtin() -> Self {
+    let c_block = Some(("/*", "*/"));
+    let html_block = Some(("<!--", "-->"));
+    let languages = vec![
+      Language::new(Extension::BZL, "bzl", &["bzl", "bazel"], &["BUILD", "WORKSPACE"],
+        &["starlark", "bazel"], Some("#"), None),
+      Language::new(Extension::C, "c", &["c"], &[], &[], Some("//"), c_block),
+      Language::new(Extension::COFFEE, "coffee", &["coffee"], &[], &["coffeescript"],
+        Some("#"), Some(("###", "###"))),
+      Language::new(Extension::CPP, "cpp", &["cpp"], &[], &["c++"], Some("//"), c_block),
+      Language::new(Extension::CSS, "css", &["css"], &[], &[], None, c_block),
+      Language::new(Extension::DOCKERFILE, "dockerfile", &["dockerfile"],
+        &["Dockerfile", "Containerfile"], &["docker"], Some("#"), None),
+      Language::new(Extension::GO, "go", &["go"], &[], &["golang"], Some("//"), c_block),
+      Language::new(Extension::GRADLE, "gradle", &["gradle"], &[], &[], Some("//"), c_block),
+      Language::new(Extension::GROOVY, "groovy", &["groovy"], &["Jenkinsfile"], &[],
+        Some("//"), c_block),
+      Language::new(Extension::H, "h", &["h"], &[], &[], Some("//"), c_block),
+      Language::new(Extension::HTML, "html", &["html"], &[], &[], None, html_block),
+      Language::new(Extension::JAVA, "java", &["java"], &[], &[], Some("//"), c_block),
+      Language::new(Extension::JS, "js", &["js"], &[], &["node", "nodejs", "javascript"],
+        Some("//"), c_block),
+      Language::new(Extension::JSON, "json", &["json"], &[], &[], None, None),
+      Language::new(Extension::JSX, "jsx", &["jsx"], &[], &["javascriptreact"], Some("//"),
+        c_block),
+      Language::new(Extension::KT, "kt", &["kt", "kts"], &[], &["kotlin"], Some("//"),
+        c_block),
+      Language::new(Extension::M, "m", &["m"], &[], &["objc"], Some("//"), c_block),
+      Language::new(Extension::MAKEFILE, "makefile", &["makefile", "mk"],
+        &["Makefile", "makefile", "GNUmakefile"], &["make"], Some("#"), None),
+      Language::new(Extension::MARKDOWN, "markdown", &["markdown"], &[], &[], None,
+        html_block),
+      Language::new(Extension::MD, "md", &["md"], &[], &[], None, html_block),
+      Language::new(Extension::PHP, "php", &["php"], &[], &[], Some("//"), c_block),
+      Language::new(Extension::PL, "pl", &["pl"], &[], &["perl"], Some("#"), None),
+      Language::new(Extension::PROTO, "proto", &["proto"], &[], &["protobuf"], Some("//"),
+        c_block),
+      Language::new(Extension::PY, "py", &["py"], &[], &["python", "pypy"], Some("#"), None),
+      Language::new(Extension::PYST, "pyst", &["pyst"], &[], &[], Some("#"), None),
+      Language::new(Extension::RB, "rb", &["rb"],
+        &["Gemfile", "Rakefile", "Vagrantfile", "Podfile"], &["ruby"], Some("#"),
+        Some(("=begin", "=end"))),
+      Language::new(Extension::RS, "rs", &["rs"], &[], &["rust"], Some("//"), c_block),
+      Language::new(Extension::SCALA, "scala", &["scala"], &[], &[], Some("//"), c_block),
+      Language::new(Extension::SCSS, "scss", &["scss"], &[], &[], Some("//"), c_block),
+      Language::new(Extension::SH, "sh", &["sh"],
+        &[".bashrc", ".bash_profile", ".bash_logout", ".profile", ".zshrc", ".zprofile",
+          ".kshrc"],
+        &["bash", "zsh", "ksh", "dash", "ash", "shell-script"], Some("#"), None),
+      Language::new(Extension::SQL, "sql", &["sql"], &[], &[], Some("--"), c_block),
+      Language::new(Extension::SWIFT, "swift", &["swift"], &[], &[], Some("//"), c_block),
+      Language::new(Extension::TF, "tf", &["tf", "tfvars"], &[], &["terraform", "hcl"],
+        Some("#"), c_block),
+      Language::new(Extension::THRIFT, "thrift", &["thrift"], &[], &[], Some("//"),
+        c_block),
+      Language::new(Extension::TOML, "toml", &["toml"], &[], &[], Some("#"), None),
+      Language::new(Extension::TS, "ts", &["ts"], &[], &["typescript", "ts-node", "deno"],
+        Some("//"), c_block),
+      Language::new(Extension::TSX, "tsx", &["tsx"], &[], &["typescriptreact"], Some("//"),
+        c_block),
+      Language::new(Extension::XML, "xml", &["xml"], &[], &[], None, html_block),
+      Language::new(Extension::YAML, "yaml", &["yaml"], &[], &[], Some("#"), None),
+      Language::new(Extension::YML, "yml", &["yml"], &[], &[], Some("#"), None)
+    ];
+
+    let mut registry = Self {
+      languages: Vec::with_capacity(languages.len()),
+      names: HashMap::new(),
+      extensions: HashMap::new(),
+      filenames: HashMap::new(),
+      aliases: HashMap::new()
+    };
+    for lang in languages {
+      debug_assert_eq!(lang.ext.0 as usize, registry.languages.len());
+      registry.languages.push(lang);
+    }
+    registry.reindex();
+    registry
+  }
+
+  // Rebuilds lookup maps, later languages take precedence for duplicate keys.
+  fn reindex(&mut self) {
+    self.names.clear();
+    self.extensions.clear();
+    self.filenames.clear();
+    self.aliases.clear();
+    for lang in &self.languages {
+      self.names.insert(lang.name.clone(), lang.ext);
+      for ext in &lang.extensions {
+        self.extensions.insert(ext.clone(), lang.ext);
+      }
+      for filename in &lang.filenames {
+        self.filenames.insert(filename.clone(), lang.ext);
+      }
+      for alias in &lang.aliases {
+        self.aliases.insert(alias.clone(), lang.ext);
+      }
+    }
+  }
+
+  /// Extends registry with languages from the TOML config.
+  /// Entries with the name of an existing language add extensions, file names and aliases to
+  /// it and replace comment syntax if set, other entries add new languages.
+  pub fn extend(&mut self, config: &str) -> Result<(), errors::Error> {
+    let config: RegistryConfig = toml::from_str(config)?;
+    for entry in config.language {
+      let name = entry.name.trim().to_lowercase();
+      if name.is_empty() || name == "<unknown>" {
+        return err!("Invalid language name {:?}", entry.name);
+      }
+      let index = match self.names.get(&name) {
+        Some(ext) => ext.0 as usize,
+        None => {
+          if self.languages.len() >= Extension::UNKNOWN.0 as usize {
+            return err!("Too many languages, cannot add {}", name);
+          }
+          let ext = Extension(self.languages.len() as u16);
+          self.languages.push(Language::new(ext, &name, &[], &[], &[], None, None));
+          self.names.insert(name, ext);
+          self.languages.len() - 1
+        }
+      };
+      let lang = &mut self.languages[index];
+      lang.extensions.extend(entry.extensions.iter()
+        .map(|ext| ext.trim().trim_start_matches('.').to_lowercase()));
+      lang.filenames.extend(entry.filenames);
+      lang.aliases.extend(entry.aliases.iter().map(|alias| alias.trim().to_lowercase()));
+      if entry.line_comment.is_some() {
+        lang.line_comment = entry.line_comment;
+      }
+      if entry.block_comment.is_some() {
+        lang.block_comment = entry.block_comment;
+      }
+    }
+    self.reindex();
+    Ok(())
+  }
+
+  /// Returns all languages in the registry.
+  pub fn languages(&self) -> &[Language] {
+    &self.languages
+  }
+
+  /// Returns language for the extension, or None for UNKNOWN.
+  pub fn language(&self, ext: Extension) -> Option<&Language> {
+    self.languages.get(ext.0 as usize)
+  }
+
+  /// Returns language by name or file extension, e.g. `rs` or `.kts`.
+  pub fn find(&self, s: &str) -> Extension {
+    let s = s.trim().trim_start_matches('.').to_lowercase();
+    match self.names.get(&s) {
+      Some(ext) => *ext,
+      None => self.extensions.get(&s).cloned().unwrap_or(Extension::UNKNOWN)
+    }
+  }
+
+  /// Returns language of the file extension (without dot).
+  pub fn find_extension(&self, ext: &str) -> Extension {
+    self.extensions.get(&ext.to_lowercase()).cloned().unwrap_or(Extension::UNKNOWN)
+  }
+
+  /// Returns language of the well-known file name, names are case sensitive.
+  pub fn find_filename(&self, name: &str) -> Extension {
+    self.filenames.get(name).cloned().unwrap_or(Extension::UNKNOWN)
+  }
+
+  /// Returns language of the interpreter or editor mode alias.
+  pub fn find_alias(&self, alias: &str) -> Extension {
+    self.aliases.get(&alias.to_lowercase()).cloned().unwrap_or(Extension::UNKNOWN)
+  }
+}
+
+// Global registry, initialised once at startup.
+static REGISTRY: OnceLock<Registry> = OnceLock::new();
+
+/// Returns global registry, built-in languages are used if registry was not initialised.
+pub fn registry() -> &'static Registry {
+  REGISTRY.get_or_init(Registry::builtin)
+}
+
+/// Initialises global registry with built-in languages and languages from the config file.
+/// Missing config file is not an error. Must be called before the registry is used.
+pub fn init_registry(path: &Path) -> Result<(), errors::Error> {
+  let mut registry = Registry::builtin();
+  if path.is_file() {
+    registry.extend(&fs::read_to_string(path)?)?;
+  }
+  if REGISTRY.set(registry).is_err() {
+    return err!("Language registry is already initialised");
+  }
+  Ok(())
+}
+
 /// Container struct to provide methods for checking supported extensions.
 #[derive(Clone, Debug)]
 pub struct Extensions {
-  set: collections::HashSet<Extension>
+  set: HashSet<Extension>
 }
 
 impl Extensions {
-  /// Creates new set with all supported extensions, except UNKNOWN.
+  /// Creates new set with all languages in the registry, including UNKNOWN.
   pub fn all() -> Self {
-    let extensions = vec![
-      Extension::BZL,
-      Extension::C,
-      Extension::COFFEE,
-      Extension::CPP,
-      Extension::CSS,
-      Extension::GO,
-      Extension::H,
-      Extension::HTML,
-      Extension::JAVA,
-      Extension::JS,
-      Extension::JSON,
-      Extension::JSX,
-      Extension::M,
-      Extension::MARKDOWN,
-      Extension::MD,
-      Extension::PHP,
-      Extension::PL,
-      Extension::PROTO,
-      Extension::PY,
-      Extension::PYST,
-      Extension::RB,
-      Extension::RS,
-      Extension::SCALA,
-      Extension::SCSS,
-      Extension::SH,
-      Extension::SQL,
-      Extension::SWIFT,
-      Extension::THRIFT,
-      Extension::TOML,
-      Extension::TSX,
-      Extension::XML,
-      Extension::YAML,
-      Extension::YML,
-      Extension::UNKNOWN
-    ];
+    let mut extensions = registry().languages().iter().map(|lang| lang.ext()).collect::<Vec<_>>();
+    extensions.push(Extension::UNKNOWN);
     Self::with_extensions(extensions)
   }
 
   /// Creates set with provided extensions.
   pub fn with_extensions(extensions: Vec<Extension>) -> Self {
-    let mut set = collections::HashSet::new();
+    let mut set = HashSet::new();
     for ext in extensions {
       set.insert(ext);
     }
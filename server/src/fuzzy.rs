@@ -0,0 +1,374 @@
+//! Fuzzy matching of file paths, similar to fzf and Ctrl-P finders.
+
+use std::cmp;
+use std::path::Path;
+use std::sync::mpsc;
+use std::thread;
+use std::time;
+
+use cache2;
+use config;
+use errors;
+use ignore::WalkState;
+use params;
+use result::{FileItem, FilesResult, Matched};
+use search::{relative_path, WalkFilters};
+
+// Default number of files to return.
+const FILES_DEFAULT_LIMIT: usize = 50;
+// Score for each matched character.
+const SCORE_MATCH: i64 = 16;
+// Penalty for each skipped character between two matched characters.
+const SCORE_GAP: i64 = 1;
+// Bonus for the match that immediately follows the previous match.
+const BONUS_CONSECUTIVE: i64 = 8;
+// Bonus for the match at the start of the path segment.
+const BONUS_PATH_SEPARATOR: i64 = 10;
+// Bonus for the match at the start of the word, e.g. after `_`, `-` or `.`.
+const BONUS_BOUNDARY: i64 = 8;
+// Bonus for the match at the camelCase hump or start of the number.
+const BONUS_CAMEL: i64 = 7;
+// Bonus for the match within the file name, so that names win over directories.
+const BONUS_FILE_NAME: i64 = 1;
+
+/// Result of matching pattern against a candidate.
+/// Positions are character indices of matched characters in the candidate.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FuzzyMatch {
+  score: i64,
+  positions: Vec<usize>
+}
+
+impl FuzzyMatch {
+  /// Returns score of the match, higher is better.
+  pub fn score(&self) -> i64 {
+    self.score
+  }
+
+  /// Returns character positions of the match.
+  pub fn positions(&self) -> &[usize] {
+    &self.positions
+  }
+}
+
+// Returns bonus for matching character at position `j`.
+#[inline]
+fn bonus_at(chars: &[char], j: usize, name_start: usize) -> i64 {
+  let name_bonus = if j >= name_start { BONUS_FILE_NAME } else { 0 };
+  if j == 0 {
+    return BONUS_PATH_SEPARATOR + name_bonus;
+  }
+  let (prev, curr) = (chars[j - 1], chars[j]);
+  let bonus = if prev == '/' {
+    BONUS_PATH_SEPARATOR
+  } else if prev == '_' || prev == '-' || prev == '.' || prev.is_whitespace() {
+    BONUS_BOUNDARY
+  } else if (prev.is_lowercase() && curr.is_uppercase()) ||
+      (!prev.is_numeric() && curr.is_numeric()) {
+    BONUS_CAMEL
+  } else {
+    0
+  };
+  bonus + name_bonus
+}
+
+/// Matches pattern as a subsequence of the candidate and returns the best scoring alignment.
+/// Whitespace in pattern is ignored. Pattern without uppercase letters matches any case.
+/// Returns None if pattern is empty or is not a subsequence of the candidate.
+pub fn fuzzy_match(pattern: &str, candidate: &str) -> Option<FuzzyMatch> {
+  let pattern = pattern.chars().filter(|c| !c.is_whitespace()).collect::<Vec<_>>();
+  let chars = candidate.chars().collect::<Vec<_>>();
+  let (m, n) = (pattern.len(), chars.len());
+  if m == 0 || m > n {
+    return None;
+  }
+
+  let ignore_case = pattern.iter().all(|c| !c.is_uppercase());
+  let is_equal = |p: char, c: char| {
+    if ignore_case { c.to_lowercase().eq(p.to_lowercase()) } else { p == c }
+  };
+
+  // Quick check that pattern is a subsequence before running full alignment.
+  let mut iter = chars.iter();
+  if !pattern.iter().all(|&p| iter.any(|&c| is_equal(p, c))) {
+    return None;
+  }
+
+  let name_start = chars.iter().rposition(|&c| c == '/').map(|pos| pos + 1).unwrap_or(0);
+  let bonus = (0..n).map(|j| bonus_at(&chars, j, name_start)).collect::<Vec<_>>();
+
+  // `score[i * n + j]` is the best score of matching `pattern[..=i]` with `pattern[i]`
+  // matched at `chars[j]`, `from[i * n + j]` is the position of `pattern[i - 1]`.
+  let none = i64::MIN / 2;
+  let mut score = vec![none; m * n];
+  let mut from = vec![0; m * n];
+
+  for i in 0..m {
+    // Best value of `score[i - 1][k] + SCORE_GAP * k` for all `k < j - 1`, this allows to
+    // compute gap penalty `SCORE_GAP * (j - k - 1)` for all previous matches in one pass.
+    let mut best = none;
+    let mut best_k = 0;
+    for j in i..n {
+      if i > 0 && j >= 2 {
+        let prev = score[(i - 1) * n + j - 2];
+        if prev > none && prev + SCORE_GAP * (j - 2) as i64 > best {
+          best = prev + SCORE_GAP * (j - 2) as i64;
+          best_k = j - 2;
+        }
+      }
+
+      if !is_equal(pattern[i], chars[j]) {
+        continue;
+      }
+
+      let value = SCORE_MATCH + bonus[j];
+      if i == 0 {
+        score[j] = value;
+        continue;
+      }
+
+      let mut curr = none;
+      if best > none {
+        curr = best - SCORE_GAP * (j - 1) as i64 + value;
+        from[i * n + j] = best_k;
+      }
+      let prev = score[(i - 1) * n + j - 1];
+      if prev > none && prev + value + BONUS_CONSECUTIVE >= curr {
+        curr = prev + value + BONUS_CONSECUTIVE;
+        from[i * n + j] = j - 1;
+      }
+      score[i * n + j] = curr;
+    }
+  }
+
+  // Find the best end position and restore matched positions.
+  let last_row = &score[(m - 1) * n..];
+  let (end, &best_score) = last_row.iter().enumerate()
+    .max_by_key(|&(j, value)| (*value, cmp::Reverse(j)))?;
+  if best_score <= none {
+    return None;
+  }
+
+  let mut positions = vec![0; m];
+  let mut j = end;
+  for i in (0..m).rev() {
+    positions[i] = j;
+    if i > 0 {
+      j = from[i * n + j];
+    }
+  }
+
+  Some(FuzzyMatch { score: best_score, positions })
+}
+
+// Matches path relative to the root and returns match with positions in the full path.
+fn match_path(root: &Path, path: &str, pattern: &str) -> Option<FuzzyMatch> {
+  let rel_path = Path::new(path).strip_prefix(root).ok()?.to_str()?;
+  let mut res = fuzzy_match(pattern, rel_path)?;
+  let offset = path.chars().count() - rel_path.chars().count();
+  for pos in res.positions.iter_mut() {
+    *pos += offset;
+  }
+  Some(res)
+}
+
+/// Finds files whose path relative to the directory fuzzy matches the pattern.
+/// Files are ranked by score, then by path length and path.
+pub fn find(
+  cache: &cache2::SharedCache,
+  params: params::FilesParams
+) -> Result<FilesResult, errors::Error> {
+  let start_time = time::Instant::now();
+
+  let path_buf = params.dir()?;
+  let root = path_buf.as_path();
+  if !root.is_dir() {
+    return err!("Path {} is not a directory", root.to_str().unwrap_or(""));
+  }
+
+  if params.pattern().trim().is_empty() {
+    return err!("Empty pattern, expected a valid file name or path");
+  }
+
+  let config = config::load(root)?;
+  // Cache is only used if it was built with the same walk filters, otherwise the directory is
+  // walked instead, similar to search.
+  let filters = WalkFilters::new(params.include_hidden(), params.include_ignored());
+  let snapshot = if params.use_cache() && cache2::policy(cache, root)?.filters() == filters {
+    cache2::snapshot(cache, root)?
+  } else {
+    None
+  };
+
+  let mut matches = Vec::new();
+  if let Some(entries) = snapshot.as_ref().and_then(|index| index.entries()) {
+    for (path, _, _) in entries.iter() {
+      if let Some(res) = match_path(root, path, params.pattern()) {
+        matches.push((res, path.to_owned()));
+      }
+    }
+  } else {
+    let (sx, rx) = mpsc::channel::<(FuzzyMatch, String)>();
+    let collector = thread::spawn(move || rx.into_iter().collect::<Vec<_>>());
+
+    let walker = cache2::walk_builder(cache, root, filters, &config)?.build_parallel();
+
+    walker.run(|| {
+      let sx = sx.clone();
+      let pattern = params.pattern().to_owned();
+      Box::new(move |res| {
+        if let Ok(inode) = res {
+          let is_file = inode.file_type().map(|ftype| ftype.is_file()).unwrap_or(false);
+          if let (true, Some(path)) = (is_file, inode.path().to_str()) {
+            if let Some(res) = match_path(root, path, &pattern) {
+              let _ = sx.send((res, path.to_owned()));
+            }
+          }
+        }
+        WalkState::Continue
+      })
+    });
+
+    drop(sx);
+    matches = collector.join().unwrap();
+  }
+
+  matches.sort_by(|a, b| {
+    b.0.score.cmp(&a.0.score)
+      .then_with(|| a.1.len().cmp(&b.1.len()))
+      .then_with(|| a.1.cmp(&b.1))
+  });
+
+  // Every candidate is scored, so the number of matches is known even if it exceeds the limit.
+  let limit = params.limit().unwrap_or(FILES_DEFAULT_LIMIT);
+  let file_matches = Matched::Exact(matches.len());
+
+  let files = matches.into_iter().take(limit).map(|(res, path)| {
+    let ext = config.mappings().detect(Path::new(&path), None);
+    let rel_path = relative_path(root, &path).to_owned();
+    FileItem::with_match(path, rel_path, ext, res.score, res.positions)
+  }).collect::<Vec<_>>();
+
+  let duration = start_time.elapsed();
+  let exec_time = duration.as_secs() as f64 + duration.subsec_nanos() as f64 * 1e-9;
+
+  Ok(FilesResult::new(
+    exec_time,
+    snapshot.is_some(),
+    snapshot.map(|index| index.txid()),
+    files,
+    file_matches
+  ))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use json;
+  use search::IGNORE_FILE;
+  use testutil::TempDir;
+
+  // Returns positions of the match, or None if pattern does not match.
+  fn positions(pattern: &str, candidate: &str) -> Option<Vec<usize>> {
+    fuzzy_match(pattern, candidate).map(|res| res.positions().to_vec())
+  }
+
+  // Returns score of the match, panics if pattern does not match.
+  fn score(pattern: &str, candidate: &str) -> i64 {
+    fuzzy_match(pattern, candidate).expect("pattern matches").score()
+  }
+
+  #[test]
+  fn test_fuzzy_match_subsequence() {
+    assert_eq!(positions("abc", "abc"), Some(vec![0, 1, 2]));
+    assert_eq!(positions("src/main", "src/main.rs"), Some(vec![0, 1, 2, 3, 4, 5, 6, 7]));
+    assert_eq!(positions("mrs", "src/main.rs"), Some(vec![4, 9, 10]));
+    assert_eq!(positions("acb", "abc"), None);
+    assert_eq!(positions("abcd", "abc"), None);
+  }
+
+  #[test]
+  fn test_fuzzy_match_empty() {
+    assert_eq!(positions("", "abc"), None);
+    assert_eq!(positions("  ", "abc"), None);
+    assert_eq!(positions("a", ""), None);
+  }
+
+  #[test]
+  fn test_fuzzy_match_whitespace_ignored() {
+    assert_eq!(positions("ma rs", "main.rs"), positions("mars", "main.rs"));
+  }
+
+  #[test]
+  fn test_fuzzy_match_smart_case() {
+    assert!(fuzzy_match("readme", "README.md").is_some());
+    assert!(fuzzy_match("ReadMe", "README.md").is_none());
+    assert!(fuzzy_match("README", "README.md").is_some());
+    assert!(fuzzy_match("ärger", "Ärger.txt").is_some());
+  }
+
+  #[test]
+  fn test_fuzzy_match_positions_are_chars() {
+    assert_eq!(positions("gr", "größe/gr.rs"), Some(vec![6, 7]));
+  }
+
+  #[test]
+  fn test_fuzzy_match_prefers_consecutive() {
+    assert!(score("main", "src/main.rs") > score("main", "src/m_a_i_n.rs"));
+  }
+
+  #[test]
+  fn test_fuzzy_match_prefers_boundaries() {
+    // Matches at the start of segments and words are preferred over matches mid-word.
+    assert_eq!(positions("fb", "foo_bar/fab"), Some(vec![0, 4]));
+    assert!(score("sm", "search_matcher.rs") > score("sm", "osmosis.rs"));
+    assert!(score("fm", "FileMatcher.java") > score("fm", "filemanager.java"));
+  }
+
+  #[test]
+  fn test_fuzzy_match_prefers_file_name() {
+    assert_eq!(positions("a", "a/a"), Some(vec![2]));
+  }
+
+  #[test]
+  fn test_match_path_offset() {
+    let root = Path::new("/tmp/größe");
+    let res = match_path(root, "/tmp/größe/src/lib.rs", "lib").expect("pattern matches");
+    let chars = "/tmp/größe/src/lib.rs".chars().collect::<Vec<_>>();
+    let matched = res.positions().iter().map(|&pos| chars[pos]).collect::<String>();
+    assert_eq!(matched, "lib");
+    assert!(match_path(root, "/tmp/other/lib.rs", "lib").is_none());
+  }
+
+  // Returns relative paths of the files found in the directory with the filters.
+  fn find_paths(dir: &Path, hidden: bool, ignored: bool) -> Vec<String> {
+    let params = json::from_value::<params::FilesParams>(json::json!({
+      "dir": dir.to_str().unwrap(),
+      "pattern": "app",
+      "include_hidden": hidden,
+      "include_ignored": ignored
+    })).unwrap();
+    let res = json::to_value(find(&cache2::create_cache(), params).unwrap()).unwrap();
+    let mut paths = res["files"].as_array().unwrap().iter()
+      .map(|file| file["rel_path"].as_str().unwrap().to_owned())
+      .collect::<Vec<_>>();
+    paths.sort();
+    paths
+  }
+
+  #[test]
+  fn test_find_walk_filters() {
+    let dir = TempDir::new(&[
+      ("src/app.rs", ""),
+      (".hidden/app.rs", ""),
+      ("build/app.rs", ""),
+      ("gen/app.rs", ""),
+      (".ignore", "build/\n"),
+      (IGNORE_FILE, "gen/\n")
+    ]);
+
+    assert_eq!(find_paths(&dir, false, false), vec!["src/app.rs"]);
+    assert_eq!(find_paths(&dir, true, false), vec![".hidden/app.rs", "src/app.rs"]);
+    assert_eq!(find_paths(&dir, false, true), vec!["build/app.rs", "src/app.rs"]);
+  }
+}
@@ -0,0 +1,338 @@
+//! History search: pattern across past revisions of the local git repository.
+//! Each commit is compared with its first parent, matched lines of the old and new blobs are
+//! collected with `search_slice` and the difference is reported as added or removed lines.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{mpsc, Arc};
+use std::sync::atomic::AtomicUsize;
+use std::time;
+
+use config;
+use errors;
+use ext::{Extension, Extensions};
+use git2::{Commit, DiffOptions, Oid, Repository, Sort};
+use grep::regex::RegexMatcher;
+use grep::searcher::{Searcher, SearcherBuilder};
+use lang::Mappings;
+use params;
+use result::{ContentItem, ContentKind, HistoryCommit, HistoryFile, HistoryLine, HistoryResult};
+use search::{self, relative_path, Collector, MatcherSpec};
+
+// Default maximum number of commits to scan.
+const MAX_COMMITS: usize = 1000;
+
+// Searches blob and returns matched lines with line numbers, line terminator is not included.
+fn matched_lines(
+  repo: &Repository,
+  searcher: &mut Searcher,
+  matcher: &RegexMatcher,
+  id: Oid,
+  ext: Extension
+) -> Result<Vec<(u64, Vec<u8>)>, errors::Error> {
+  if id.is_zero() {
+    return Ok(Vec::new());
+  }
+  let blob = repo.find_blob(id)?;
+  if blob.is_binary() {
+    return Ok(Vec::new());
+  }
+
+  let counter = Arc::new(AtomicUsize::new(0));
+  let (sx, rx) = mpsc::channel::<ContentItem>();
+  let collector = Collector::new(
+    sx,
+    counter,
+    usize::MAX,
+    id.to_string(),
+    MatcherSpec::regex(matcher.clone()),
+    ext,
+    false
+  );
+  searcher.search_slice(matcher.clone(), blob.content(), collector)?;
+
+  let mut lines = Vec::new();
+  while let Ok(item) = rx.try_recv() {
+    for line in item.matches().iter().flat_map(|mat| mat.lines()) {
+      if line.kind() == ContentKind::Match {
+        let bytes = line.bytes();
+        let len = bytes.len() - bytes.iter().rev().take_while(|&&b| b == b'\n' || b == b'\r')
+          .count();
+        lines.push((line.num(), bytes[..len].to_vec()));
+      }
+    }
+  }
+  Ok(lines)
+}
+
+// Returns lines that are in `lines` more times than in `other`, keeps the original order.
+fn difference(lines: &[(u64, Vec<u8>)], other: &[(u64, Vec<u8>)]) -> Vec<HistoryLine> {
+  let mut counts = HashMap::new();
+  for (_, line) in other {
+    *counts.entry(line.as_slice()).or_insert(0usize) += 1;
+  }
+  let mut diff = Vec::new();
+  for (num, line) in lines {
+    match counts.get_mut(line.as_slice()) {
+      Some(count) if *count > 0 => *count -= 1,
+      _ => diff.push(HistoryLine::new(*num, String::from_utf8_lossy(line).into_owned()))
+    }
+  }
+  diff
+}
+
+// Scans the commit against its first parent, returns None if no matching lines changed.
+fn scan_commit(
+  repo: &Repository,
+  commit: &Commit,
+  workdir: &Path,
+  root: &Path,
+  searcher: &mut Searcher,
+  matcher: &RegexMatcher,
+  mappings: &Mappings
+) -> Result<Option<HistoryCommit>, errors::Error> {
+  let tree = commit.tree()?;
+  let parent_tree = match commit.parents().next() {
+    Some(parent) => Some(parent.tree()?),
+    None => None
+  };
+  let mut opts = DiffOptions::new();
+  match root.strip_prefix(workdir) {
+    Ok(prefix) if !prefix.as_os_str().is_empty() => {
+      opts.pathspec(prefix);
+    },
+    _ => {}
+  }
+  let diff = repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&tree), Some(&mut opts))?;
+
+  let ext_check = Extensions::all();
+  let mut files = Vec::new();
+  for delta in diff.deltas() {
+    let file_path = match delta.new_file().path().or_else(|| delta.old_file().path()) {
+      Some(file_path) => workdir.join(file_path),
+      None => continue
+    };
+    let fpath = match file_path.to_str() {
+      Some(fpath) => fpath,
+      None => continue
+    };
+    let ext = mappings.detect(&file_path, None);
+    if !ext_check.is_supported_extension(ext) {
+      continue;
+    }
+
+    let old_lines = matched_lines(repo, searcher, matcher, delta.old_file().id(), ext)?;
+    let new_lines = matched_lines(repo, searcher, matcher, delta.new_file().id(), ext)?;
+    let added = difference(&new_lines, &old_lines);
+    let removed = difference(&old_lines, &new_lines);
+    if !added.is_empty() || !removed.is_empty() {
+      files.push(HistoryFile::new(
+        fpath.to_owned(),
+        relative_path(root, fpath).to_owned(),
+        ext,
+        added,
+        removed
+      ));
+    }
+  }
+
+  if files.is_empty() {
+    return Ok(None);
+  }
+  let author = commit.author();
+  Ok(Some(HistoryCommit::new(
+    commit.id().to_string(),
+    commit.summary().unwrap_or("").to_owned(),
+    author.name().unwrap_or("").to_owned(),
+    commit.time().seconds(),
+    files
+  )))
+}
+
+/// Searches history of the repository that contains the directory.
+/// Commits are either selected explicitly or walked from the range (`HEAD` by default), newest
+/// first, only changes within the directory are scanned.
+pub fn find(params: params::HistoryParams) -> Result<HistoryResult, errors::Error> {
+  let start_time = time::Instant::now();
+
+  let path_buf = params.dir()?;
+  let root = path_buf.as_path();
+  if !root.is_dir() {
+    return err!("Path {} is not a directory", root.to_str().unwrap_or(""));
+  }
+
+  let repo = Repository::discover(root)?;
+  let workdir = match repo.workdir() {
+    Some(workdir) => workdir.canonicalize()?,
+    None => return err!("Repository for {} has no working directory", root.display())
+  };
+  if !root.starts_with(&workdir) {
+    return err!("Path {} is not in the repository", root.display());
+  }
+
+  // Language mappings and case mode of the project apply the same way as in search.
+  let config = config::load(root)?;
+  let matcher = search::build_regex_matcher(params.pattern(), params.use_regex(),
+    config.case())?;
+  let mut searcher = SearcherBuilder::new()
+    .line_number(true)
+    .multi_line(false)
+    .build();
+
+  let max_commits = params.max_commits().unwrap_or(MAX_COMMITS);
+  let mut ids = Vec::new();
+  match params.revs() {
+    Some(revs) => {
+      for rev in revs.iter().take(max_commits) {
+        ids.push(repo.revparse_single(rev)?.peel_to_commit()?.id());
+      }
+    },
+    None => {
+      let range = params.range().unwrap_or("HEAD");
+      let mut revwalk = repo.revwalk()?;
+      revwalk.set_sorting(Sort::TOPOLOGICAL | Sort::TIME)?;
+      if range.contains("..") {
+        revwalk.push_range(range)?;
+      } else {
+        revwalk.push(repo.revparse_single(range)?.peel_to_commit()?.id())?;
+      }
+      for id in revwalk.take(max_commits) {
+        ids.push(id?);
+      }
+    }
+  }
+
+  let mut commits = Vec::new();
+  for id in &ids {
+    let commit = repo.find_commit(*id)?;
+    if let Some(res) = scan_commit(&repo, &commit, &workdir, root, &mut searcher, &matcher,
+        config.mappings())? {
+      commits.push(res);
+    }
+  }
+
+  let duration = start_time.elapsed();
+  let exec_time = duration.as_secs() as f64 + duration.subsec_nanos() as f64 * 1e-9;
+
+  Ok(HistoryResult::new(exec_time, ids.len(), commits))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use git2::Signature;
+  use json;
+  use testutil::TempDir;
+
+  // Returns lines with line numbers starting at 1.
+  fn numbered(lines: &[&str]) -> Vec<(u64, Vec<u8>)> {
+    lines.iter().enumerate().map(|(i, line)| (i as u64 + 1, line.as_bytes().to_vec())).collect()
+  }
+
+  // Returns line numbers and lines of the history lines.
+  fn history_lines(lines: &json::Value) -> Vec<(u64, String)> {
+    lines.as_array().unwrap().iter()
+      .map(|line| (line["num"].as_u64().unwrap(), line["line"].as_str().unwrap().to_owned()))
+      .collect()
+  }
+
+  // Commits content of the file with the parents, HEAD is updated only if requested.
+  fn commit(
+    repo: &Repository,
+    dir: &TempDir,
+    content: &str,
+    parents: &[Oid],
+    head: bool
+  ) -> Oid {
+    dir.write("a.rs", content.as_bytes());
+    let mut index = repo.index().unwrap();
+    index.add_path(Path::new("a.rs")).unwrap();
+    let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+    let parents = parents.iter().map(|id| repo.find_commit(*id).unwrap()).collect::<Vec<_>>();
+    let parents = parents.iter().collect::<Vec<_>>();
+    let signature = Signature::now("test", "test@example.com").unwrap();
+    let update_ref = if head { Some("HEAD") } else { None };
+    repo.commit(update_ref, &signature, &signature, content, &tree, &parents).unwrap()
+  }
+
+  // Creates repository with a merge commit, returns ids of base, main, side and merge commits.
+  fn create_repo(dir: &TempDir) -> [Oid; 4] {
+    let repo = Repository::init(dir.as_ref()).unwrap();
+    let base = commit(&repo, dir, "let key = 1;\n", &[], true);
+    let main = commit(&repo, dir, "let key = 1;\nlet key = 2;\n", &[base], true);
+    let side = commit(&repo, dir, "let key = 1;\nlet key = 3;\n", &[base], false);
+    let merge = commit(&repo, dir, "let key = 1;\nlet key = 2;\nlet key = 3;\n", &[main, side],
+      true);
+    [base, main, side, merge]
+  }
+
+  // Searches history of the directory for "key" with the extra parameters.
+  fn find_key(dir: &TempDir, fields: json::Value) -> json::Value {
+    let mut value = json::json!({ "dir": dir.to_str().unwrap(), "pattern": "key" });
+    for (key, field) in fields.as_object().unwrap() {
+      value[key] = field.clone();
+    }
+    let params = json::from_value::<params::HistoryParams>(value).unwrap();
+    json::to_value(find(params).unwrap()).unwrap()
+  }
+
+  // Returns ids of the commits in the history result.
+  fn commit_ids(res: &json::Value) -> Vec<String> {
+    res["commits"].as_array().unwrap().iter()
+      .map(|commit| commit["id"].as_str().unwrap().to_owned())
+      .collect()
+  }
+
+  #[test]
+  fn test_difference() {
+    let old = numbered(&["a", "b", "a"]);
+    let new = numbered(&["a", "c", "a", "a"]);
+    let added = json::to_value(difference(&new, &old)).unwrap();
+    // Duplicate lines are matched by count, so only the third copy is added.
+    assert_eq!(history_lines(&added), vec![(2, "c".to_owned()), (4, "a".to_owned())]);
+    let removed = json::to_value(difference(&old, &new)).unwrap();
+    assert_eq!(history_lines(&removed), vec![(2, "b".to_owned())]);
+    assert!(difference(&old, &old).is_empty());
+  }
+
+  #[test]
+  fn test_find_compares_with_first_parent() {
+    let dir = TempDir::new(&[]);
+    let [_, _, _, merge] = create_repo(&dir);
+    let res = find_key(&dir, json::json!({ "revs": [merge.to_string()] }));
+    assert_eq!(res["num_commits"], 1);
+    assert_eq!(commit_ids(&res), vec![merge.to_string()]);
+    let file = &res["commits"][0]["files"][0];
+    assert_eq!(file["rel_path"], "a.rs");
+    assert_eq!(history_lines(&file["added"]), vec![(3, "let key = 3;".to_owned())]);
+    assert!(history_lines(&file["removed"]).is_empty());
+  }
+
+  #[test]
+  fn test_find_selects_commits() {
+    let dir = TempDir::new(&[]);
+    let [base, main, side, merge] = create_repo(&dir);
+
+    let res = find_key(&dir, json::json!({}));
+    assert_eq!(res["num_commits"], 4);
+    let mut ids = commit_ids(&res);
+    ids.sort();
+    let mut expected = [base, main, side, merge].iter().map(|id| id.to_string())
+      .collect::<Vec<_>>();
+    expected.sort();
+    assert_eq!(ids, expected);
+
+    let range = format!("{}..{}", base, main);
+    let res = find_key(&dir, json::json!({ "range": range }));
+    assert_eq!(commit_ids(&res), vec![main.to_string()]);
+
+    let res = find_key(&dir, json::json!({ "revs": [side.to_string(), base.to_string()] }));
+    assert_eq!(commit_ids(&res), vec![side.to_string(), base.to_string()]);
+    let file = &res["commits"][0]["files"][0];
+    assert_eq!(history_lines(&file["added"]), vec![(2, "let key = 3;".to_owned())]);
+
+    let res = find_key(&dir, json::json!({ "max_commits": 2 }));
+    assert_eq!(res["num_commits"], 2);
+    assert_eq!(commit_ids(&res)[0], merge.to_string());
+  }
+}
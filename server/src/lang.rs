@@ -0,0 +1,229 @@
+//! Language detection on top of file extensions.
+//! Files are checked by extension first, then by well-known file names such as `Makefile` or
+//! `Dockerfile`, and only files without an extension are checked for a shebang line or an
+//! editor modeline (vim `ft=`, emacs `-*- mode: -*-`) at the beginning of the content.
+//! Project mappings from the config file take precedence over all of the above.
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use ext::{self, Extension};
+use globset::GlobSet;
+
+// Number of bytes at the beginning of the file checked for shebang and modelines.
+const HEAD_BYTES: usize = 1024;
+// Number of lines at the beginning of the file checked for modelines.
+const HEAD_LINES: usize = 5;
+
+// Returns language of the well-known file name.
+fn from_file_name(name: &str) -> Extension {
+  let ext = ext::registry().find_filename(name);
+  // Variants such as `Dockerfile.dev`.
+  if ext == Extension::UNKNOWN && name.starts_with("Dockerfile.") {
+    return Extension::DOCKERFILE;
+  }
+  ext
+}
+
+// Returns language of the interpreter or editor mode name, e.g. `python3` or `shell-script`.
+fn from_name(name: &str) -> Extension {
+  // Strip version suffix, e.g. `python3.11` or `ruby2`.
+  let name = name.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+  match ext::registry().find_alias(name) {
+    Extension::UNKNOWN => ext::registry().find(name),
+    ext => ext
+  }
+}
+
+// Returns language of the shebang line, e.g. `#!/usr/bin/env python3`.
+fn from_shebang(line: &str) -> Extension {
+  let mut words = match line.strip_prefix("#!") {
+    Some(rest) => rest.split_whitespace(),
+    None => return Extension::UNKNOWN
+  };
+  let interpreter = match words.next() {
+    Some(path) => path.rsplit('/').next().unwrap_or(path),
+    None => return Extension::UNKNOWN
+  };
+  if interpreter == "env" {
+    // Skip `env` options, e.g. `#!/usr/bin/env -S python3 -u`.
+    match words.find(|word| !word.starts_with('-')) {
+      Some(name) => from_name(name),
+      None => Extension::UNKNOWN
+    }
+  } else {
+    from_name(interpreter)
+  }
+}
+
+// Returns language of the vim or emacs modeline in the line.
+fn from_modeline(line: &str) -> Extension {
+  // Emacs: `-*- mode: python -*-` or `-*- python -*-`.
+  if let Some(start) = line.find("-*-") {
+    let rest = &line[start + 3..];
+    if let Some(end) = rest.find("-*-") {
+      let vars = rest[..end].trim();
+      if !vars.contains(':') {
+        return from_name(vars);
+      }
+      for var in vars.split(';') {
+        let mut parts = var.splitn(2, ':');
+        if let (Some(key), Some(value)) = (parts.next(), parts.next()) {
+          if key.trim().eq_ignore_ascii_case("mode") {
+            return from_name(value.trim());
+          }
+        }
+      }
+    }
+  }
+  // Vim: `vim: set ft=python:` or `vi: filetype=python`.
+  for marker in &["vim:", "vi:", "ex:"] {
+    let start = match line.find(marker) {
+      Some(start) => start,
+      None => continue
+    };
+    // Marker must be at the start of the line or follow whitespace.
+    if start > 0 && !line[..start].ends_with(char::is_whitespace) {
+      continue;
+    }
+    let options = &line[start + marker.len()..];
+    for option in options.split(|c: char| c.is_whitespace() || c == ':') {
+      let mut parts = option.splitn(2, '=');
+      if let (Some(key), Some(value)) = (parts.next(), parts.next()) {
+        if key == "ft" || key == "filetype" || key == "syntax" {
+          return from_name(value);
+        }
+      }
+    }
+  }
+  Extension::UNKNOWN
+}
+
+/// Returns language of the content based on shebang line and modelines in the first lines.
+pub fn from_content(content: &[u8]) -> Extension {
+  let head = &content[..content.len().min(HEAD_BYTES)];
+  let text = String::from_utf8_lossy(head);
+  for (i, line) in text.lines().take(HEAD_LINES).enumerate() {
+    let ext = if i == 0 && line.starts_with("#!") {
+      from_shebang(line)
+    } else {
+      from_modeline(line)
+    };
+    if ext != Extension::UNKNOWN {
+      return ext;
+    }
+  }
+  Extension::UNKNOWN
+}
+
+/// Returns language of the path based on the extension and well-known file names.
+/// Content is not checked, use `detect` when the content is available.
+pub fn from_path(path: &Path) -> Extension {
+  let ext = path.extension()
+    .and_then(|os| os.to_str())
+    .map(|ext| ext::registry().find_extension(ext))
+    .unwrap_or(Extension::UNKNOWN);
+  if ext != Extension::UNKNOWN {
+    return ext;
+  }
+  path.file_name()
+    .and_then(|os| os.to_str())
+    .map(from_file_name)
+    .unwrap_or(Extension::UNKNOWN)
+}
+
+/// Returns language of the file.
+/// Falls back to shebang and modelines if the path is not recognised and has no extension, the
+/// beginning of the file is read from disk if content is not provided.
+pub fn detect(path: &Path, content: Option<&[u8]>) -> Extension {
+  let ext = from_path(path);
+  if ext != Extension::UNKNOWN || path.extension().is_some() {
+    return ext;
+  }
+  match content {
+    Some(content) => from_content(content),
+    None => {
+      let mut head = Vec::with_capacity(HEAD_BYTES);
+      let res = File::open(path)
+        .and_then(|file| file.take(HEAD_BYTES as u64).read_to_end(&mut head));
+      match res {
+        Ok(_) => from_content(&head),
+        Err(_) => Extension::UNKNOWN
+      }
+    }
+  }
+}
+
+/// Project mappings from file globs to languages.
+#[derive(Clone, Debug)]
+pub struct Mappings {
+  globs: GlobSet,
+  exts: Vec<Extension> // language of the glob with the same index
+}
+
+impl Mappings {
+  /// Creates mappings, later globs take precedence when several globs match the path.
+  pub fn new(globs: GlobSet, exts: Vec<Extension>) -> Self {
+    Self { globs, exts }
+  }
+
+  /// Creates mappings without globs.
+  pub fn empty() -> Self {
+    Self::new(GlobSet::empty(), Vec::new())
+  }
+
+  // Returns language of the last glob that matches the path.
+  fn find(&self, path: &Path) -> Option<Extension> {
+    if self.exts.is_empty() {
+      return None;
+    }
+    self.globs.matches(path).into_iter().max().map(|i| self.exts[i])
+  }
+
+  /// Same as `from_path`, but mapped files take precedence.
+  pub fn from_path(&self, path: &Path) -> Extension {
+    self.find(path).unwrap_or_else(|| from_path(path))
+  }
+
+  /// Same as `detect`, but mapped files take precedence.
+  pub fn detect(&self, path: &Path, content: Option<&[u8]>) -> Extension {
+    self.find(path).unwrap_or_else(|| detect(path, content))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_detect_path() {
+    assert_eq!(detect(Path::new("/src/main.rs"), None), Extension::RS);
+    assert_eq!(detect(Path::new("/src/Makefile"), None), Extension::MAKEFILE);
+    assert_eq!(detect(Path::new("/src/Dockerfile.dev"), None), Extension::DOCKERFILE);
+  }
+
+  #[test]
+  fn test_detect_shebang() {
+    let content = b"#!/usr/bin/env -S python3 -u\nprint(1)\n";
+    assert_eq!(detect(Path::new("/bin/run"), Some(content)), Extension::PY);
+    assert_eq!(detect(Path::new("/bin/run"), Some(b"#!/bin/bash\n")), Extension::SH);
+    assert_eq!(detect(Path::new("/bin/run"), Some(b"echo\n")), Extension::UNKNOWN);
+  }
+
+  #[test]
+  fn test_detect_modeline() {
+    let vim = b"// header\n// vim: set ft=ruby:\n";
+    assert_eq!(detect(Path::new("/src/build"), Some(vim)), Extension::RB);
+    let emacs = b"# -*- mode: python; coding: utf-8 -*-\n";
+    assert_eq!(detect(Path::new("/src/build"), Some(emacs)), Extension::PY);
+  }
+
+  #[test]
+  fn test_detect_skips_content_with_extension() {
+    // Content is only sniffed for files without an extension.
+    let content = b"#!/usr/bin/env python3\n";
+    assert_eq!(detect(Path::new("/src/run.unknown"), Some(content)), Extension::UNKNOWN);
+    assert_eq!(detect(Path::new("/src/run.rs"), Some(content)), Extension::RS);
+  }
+}
@@ -1,20 +1,47 @@
+extern crate bzip2;
+extern crate encoding_rs;
+extern crate flate2;
 extern crate futures;
+extern crate git2;
+extern crate globset;
 extern crate grep;
 extern crate hyper;
 extern crate ignore;
+extern crate regex;
 extern crate serde;
 extern crate serde_json as json;
 #[macro_use]
 extern crate serde_derive;
+extern crate tar;
+extern crate toml;
+extern crate xz2;
+extern crate zip;
+extern crate zstd;
 
 #[macro_use]
 pub mod errors;
+pub mod archive;
 pub mod cache2;
+pub mod config;
+pub mod encoding;
 pub mod ext;
+pub mod fuzzy;
+pub mod history;
+pub mod lang;
 pub mod params;
+pub mod rank;
+pub mod references;
+pub mod replace;
+pub mod scope;
 pub mod result;
 pub mod search;
 pub mod suffix;
+pub mod symbols;
+#[cfg(test)]
+mod testutil;
+
+use std::env;
+use std::path::{Path, PathBuf};
 
 use futures::{future, Stream};
 use hyper::{Body, Method, Request, Response, Server, StatusCode};
@@ -22,7 +49,7 @@ use hyper::header::CONTENT_TYPE;
 use hyper::rt::Future;
 use hyper::service::service_fn;
 
-type BoxFuture = Box<Future<Item=Response<Body>, Error=hyper::Error> + Send>;
+type BoxFuture = Box<dyn Future<Item=Response<Body>, Error=hyper::Error> + Send>;
 
 // Function to convert error into a JSON string.
 fn err2json(error: &errors::Error) -> String {
@@ -30,6 +57,42 @@ fn err2json(error: &errors::Error) -> String {
     .unwrap_or("{\"err\":true,\"msg\":\"Server error\"}".to_owned())
 }
 
+// Builds response with JSON payload or error.
+fn json_response(res: Result<String, errors::Error>) -> Response<Body> {
+  match res {
+    Ok(payload) => {
+      let mut response = Response::new(Body::from(payload));
+      *response.status_mut() = StatusCode::OK;
+      response.headers_mut().insert(
+        CONTENT_TYPE,
+        "application/json".parse().expect("correct content type value")
+      );
+      response
+    }
+    Err(error) => {
+      let mut response = Response::new(Body::from(err2json(&error)));
+      *response.status_mut() = StatusCode::BAD_REQUEST;
+      response
+    }
+  }
+}
+
+// Builds empty response or error.
+fn empty_response(res: Result<(), errors::Error>) -> Response<Body> {
+  match res {
+    Ok(_) => {
+      let mut response = Response::new(Body::empty());
+      *response.status_mut() = StatusCode::OK;
+      response
+    }
+    Err(error) => {
+      let mut response = Response::new(Body::from(err2json(&error)));
+      *response.status_mut() = StatusCode::BAD_REQUEST;
+      response
+    }
+  }
+}
+
 fn service_inner(req: Request<Body>, cache: cache2::SharedCache) -> BoxFuture {
   match (req.method(), req.uri().path()) {
     (&Method::GET, "/ping") => {
@@ -44,22 +107,34 @@ fn service_inner(req: Request<Body>, cache: cache2::SharedCache) -> BoxFuture {
         .map(move |_| {
           let res = cache2::cache_stats(&cache)
             .and_then(|stats| json::to_string(&stats).map_err(|error| error.into()));
-          match res {
-            Ok(payload) => {
-              let mut response = Response::new(Body::from(payload));
-              *response.status_mut() = StatusCode::OK;
-              response.headers_mut().insert(
-                CONTENT_TYPE,
-                "application/json".parse().expect("correct content type value")
-              );
-              response
-            }
-            Err(error) => {
-              let mut response = Response::new(Body::from(err2json(&error)));
-              *response.status_mut() = StatusCode::BAD_REQUEST;
-              response
-            }
-          }
+          json_response(res)
+        });
+      Box::new(response)
+    },
+    (&Method::GET, "/cache/status") => {
+      let response = req
+        .into_body()
+        .concat2()
+        .map(move |_| {
+          let res = cache2::cache_status(&cache)
+            .and_then(|status| json::to_string(&status).map_err(|error| error.into()));
+          json_response(res)
+        });
+      Box::new(response)
+    },
+    (&Method::POST, "/cache/progress") => {
+      let response = req
+        .into_body()
+        .concat2()
+        .map(move |chunk| {
+          let body = chunk.iter().cloned().collect::<Vec<u8>>();
+          let res = json::from_slice::<params::CacheParams>(&body)
+            .map_err(|error| error.into())
+            .and_then(|params| {
+              cache2::cache_progress(&cache, &cache2::index_path(&params.dir()?, params.rev()))
+            })
+            .and_then(|progress| json::to_string(&progress).map_err(|error| error.into()));
+          json_response(res)
         });
       Box::new(response)
     },
@@ -71,19 +146,125 @@ fn service_inner(req: Request<Body>, cache: cache2::SharedCache) -> BoxFuture {
           let body = chunk.iter().cloned().collect::<Vec<u8>>();
           let res = json::from_slice::<params::CacheParams>(&body)
             .map_err(|error| error.into())
-            .and_then(|params| cache2::update_cache(&cache, params.dir()?.as_path()));
-          match res {
-            Ok(_) => {
-              let mut response = Response::new(Body::empty());
-              *response.status_mut() = StatusCode::OK;
-              response
-            }
-            Err(error) => {
-              let mut response = Response::new(Body::from(err2json(&error)));
-              *response.status_mut() = StatusCode::BAD_REQUEST;
-              response
-            }
-          }
+            .and_then(|params| {
+              let policy = cache2::CachePolicy::from_params(&params)?;
+              cache2::update_cache(&cache, params.dir()?.as_path(), policy)
+            });
+          empty_response(res)
+        });
+      Box::new(response)
+    },
+    (&Method::POST, "/cache/refresh") => {
+      let response = req
+        .into_body()
+        .concat2()
+        .map(move |chunk| {
+          let body = chunk.iter().cloned().collect::<Vec<u8>>();
+          let res = json::from_slice::<params::CacheParams>(&body)
+            .map_err(|error| error.into())
+            .and_then(|params| {
+              cache2::force_refresh(&cache, &cache2::index_path(&params.dir()?, params.rev()))
+            });
+          empty_response(res)
+        });
+      Box::new(response)
+    },
+    (&Method::POST, "/buffers/push") => {
+      let response = req
+        .into_body()
+        .concat2()
+        .map(move |chunk| {
+          let body = chunk.iter().cloned().collect::<Vec<u8>>();
+          let res = json::from_slice::<params::BufferParams>(&body)
+            .map_err(|error| error.into())
+            .and_then(|params| {
+              let content = params.content().as_bytes().to_vec();
+              cache2::push_buffer(&cache, params.path().as_path(), content)
+            });
+          empty_response(res)
+        });
+      Box::new(response)
+    },
+    (&Method::POST, "/buffers/clear") => {
+      let response = req
+        .into_body()
+        .concat2()
+        .map(move |chunk| {
+          let body = chunk.iter().cloned().collect::<Vec<u8>>();
+          let res = json::from_slice::<params::ClearBufferParams>(&body)
+            .map_err(|error| error.into())
+            .and_then(|params| cache2::clear_buffer(&cache, params.path().as_deref()));
+          empty_response(res)
+        });
+      Box::new(response)
+    },
+    (&Method::POST, "/files") => {
+      let response = req
+        .into_body()
+        .concat2()
+        .map(move |chunk| {
+          let body = chunk.iter().cloned().collect::<Vec<u8>>();
+          let res = json::from_slice::<params::FilesParams>(&body)
+            .map_err(|error| error.into())
+            .and_then(|params| fuzzy::find(&cache, params))
+            .and_then(|res| json::to_string(&res).map_err(|error| error.into()));
+          json_response(res)
+        });
+      Box::new(response)
+    },
+    (&Method::POST, "/symbols") => {
+      let response = req
+        .into_body()
+        .concat2()
+        .map(move |chunk| {
+          let body = chunk.iter().cloned().collect::<Vec<u8>>();
+          let res = json::from_slice::<params::SymbolsParams>(&body)
+            .map_err(|error| error.into())
+            .and_then(|params| symbols::find(&cache, params))
+            .and_then(|res| json::to_string(&res).map_err(|error| error.into()));
+          json_response(res)
+        });
+      Box::new(response)
+    },
+    (&Method::POST, "/history") => {
+      let response = req
+        .into_body()
+        .concat2()
+        .map(move |chunk| {
+          let body = chunk.iter().cloned().collect::<Vec<u8>>();
+          let res = json::from_slice::<params::HistoryParams>(&body)
+            .map_err(|error| error.into())
+            .and_then(history::find)
+            .and_then(|res| json::to_string(&res).map_err(|error| error.into()));
+          json_response(res)
+        });
+      Box::new(response)
+    },
+    (&Method::POST, "/replace") => {
+      let response = req
+        .into_body()
+        .concat2()
+        .map(move |chunk| {
+          let body = chunk.iter().cloned().collect::<Vec<u8>>();
+          let res = json::from_slice::<params::ReplaceParams>(&body)
+            .map_err(|error| error.into())
+            .and_then(|params| replace::preview(&cache, params))
+            .and_then(|res| json::to_string(&res).map_err(|error| error.into()));
+          json_response(res)
+        });
+      Box::new(response)
+    },
+    (&Method::POST, "/replace/apply") => {
+      let response = req
+        .into_body()
+        .concat2()
+        .map(move |chunk| {
+          let body = chunk.iter().cloned().collect::<Vec<u8>>();
+          let res = json::from_slice::<params::ApplyParams>(&body)
+            .map_err(|error| error.into())
+            .and_then(|params| replace::apply(&cache, params))
+            .and_then(|res| json::to_string(&res).map_err(|error| error.into()));
+          json_response(res)
         });
       Box::new(response)
     },
@@ -97,22 +278,7 @@ fn service_inner(req: Request<Body>, cache: cache2::SharedCache) -> BoxFuture {
             .map_err(|error| error.into())
             .and_then(|params| search::find(&cache, params))
             .and_then(|res| json::to_string(&res).map_err(|error| error.into()));
-          match res {
-            Ok(payload) => {
-              let mut response = Response::new(Body::from(payload));
-              *response.status_mut() = StatusCode::OK;
-              response.headers_mut().insert(
-                CONTENT_TYPE,
-                "application/json".parse().expect("correct content type value")
-              );
-              response
-            }
-            Err(error) => {
-              let mut response = Response::new(Body::from(err2json(&error)));
-              *response.status_mut() = StatusCode::BAD_REQUEST;
-              response
-            }
-          }
+          json_response(res)
         });
       Box::new(response)
     },
@@ -125,7 +291,20 @@ fn service_inner(req: Request<Body>, cache: cache2::SharedCache) -> BoxFuture {
   }
 }
 
+// Returns path to the language config, `OMNISEARCH_LANGUAGES` or `~/.omnisearch/languages.toml`.
+fn languages_path() -> Option<PathBuf> {
+  match env::var_os("OMNISEARCH_LANGUAGES") {
+    Some(path) => Some(PathBuf::from(path)),
+    None => env::var_os("HOME").map(|home| Path::new(&home).join(".omnisearch/languages.toml"))
+  }
+}
+
 fn main() {
+  if let Some(path) = languages_path() {
+    if let Err(error) = ext::init_registry(&path) {
+      eprintln!("Failed to load languages from {}: {}", path.display(), error);
+    }
+  }
   let cache = cache2::create_cache();
   let tp = cache2::periodic_refresh(&cache);
   hyper::rt::run(hyper::rt::lazy(move || {
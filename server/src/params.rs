@@ -1,6 +1,46 @@
 use std::path::{Path, PathBuf};
 
 use errors;
+use scope::Scope;
+use symbols::SymbolKind;
+
+/// Sort order of search results.
+/// Sorted search collects matches up to the sorted limit before sorting, so the same tree and
+/// query return the same results as long as the number of matches stays within the limit.
+/// Beyond the limit, matches are collected in walk order and results may differ between runs.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+  Path, // ascending by path
+  Mtime, // most recently modified files first
+  Matches // files with the most matched lines first
+}
+
+/// Search mode of the query.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchMode {
+  Text, // substring or regular expression search
+  References // whole word occurrences of the identifier, classified per language
+}
+
+/// Policy for files that contain binary data, i.e. NUL bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BinaryPolicy {
+  Skip, // binary files are not searched
+  Report, // binary files are searched, matches are reported without lines
+  Include // binary files are searched as text
+}
+
+/// Letter case handling of the search pattern.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CaseMode {
+  Smart, // case insensitive if pattern is lowercase, case sensitive otherwise
+  Sensitive,
+  Insensitive
+}
 
 /// Input struct that is deserialized from JSON payload.
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -8,7 +48,18 @@ pub struct QueryParams {
   dir: String,
   pattern: String,
   use_regex: Option<bool>,
-  use_cache: Option<bool>
+  use_cache: Option<bool>,
+  match_path: Option<bool>,
+  sort: Option<SortOrder>,
+  mode: Option<SearchMode>,
+  scope: Option<String>,
+  rev: Option<String>,
+  search_archives: Option<bool>,
+  binary: Option<BinaryPolicy>,
+  encoding: Option<String>,
+  case: Option<CaseMode>,
+  include_hidden: Option<bool>,
+  include_ignored: Option<bool>
 }
 
 impl QueryParams {
@@ -31,12 +82,313 @@ impl QueryParams {
   pub fn use_cache(&self) -> bool {
     self.use_cache.unwrap_or(false)
   }
+
+  /// Whether or not to match file pattern against path relative to the root directory
+  /// instead of file name. Enabled by default for patterns that contain `/`.
+  pub fn match_path(&self) -> bool {
+    self.match_path.unwrap_or(self.pattern.contains('/'))
+  }
+
+  /// Returns sort order of results, or None if results are ranked.
+  pub fn sort(&self) -> Option<SortOrder> {
+    self.sort
+  }
+
+  /// Returns search mode, text search by default.
+  pub fn mode(&self) -> SearchMode {
+    self.mode.unwrap_or(SearchMode::Text)
+  }
+
+  /// Returns git scope to restrict search to, or None to search all files.
+  pub fn scope(&self) -> Result<Option<Scope>, errors::Error> {
+    match self.scope {
+      Some(ref scope) => Ok(Some(scope.parse::<Scope>()?)),
+      None => Ok(None)
+    }
+  }
+
+  /// Returns git revision to search, the revision must be cached with `/cache/add`.
+  pub fn rev(&self) -> Option<&str> {
+    self.rev.as_deref()
+  }
+
+  /// Whether or not to search inside archives and compressed files.
+  pub fn search_archives(&self) -> bool {
+    self.search_archives.unwrap_or(false)
+  }
+
+  /// Returns policy for binary files, binary files are skipped by default.
+  pub fn binary(&self) -> BinaryPolicy {
+    self.binary.unwrap_or(BinaryPolicy::Skip)
+  }
+
+  /// Returns encoding label of files without byte order mark, overrides project encoding.
+  pub fn encoding(&self) -> Option<&str> {
+    self.encoding.as_deref()
+  }
+
+  /// Returns case mode of the pattern, overrides project case mode.
+  pub fn case(&self) -> Option<CaseMode> {
+    self.case
+  }
+
+  /// Whether or not to search hidden files and directories.
+  pub fn include_hidden(&self) -> bool {
+    self.include_hidden.unwrap_or(false)
+  }
+
+  /// Whether or not to search files excluded by `.gitignore` and `.ignore`.
+  pub fn include_ignored(&self) -> bool {
+    self.include_ignored.unwrap_or(false)
+  }
+}
+
+/// Input struct for fuzzy file search.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FilesParams {
+  dir: String,
+  pattern: String,
+  limit: Option<usize>,
+  use_cache: Option<bool>,
+  include_hidden: Option<bool>,
+  include_ignored: Option<bool>
+}
+
+impl FilesParams {
+  /// Returns root directory.
+  pub fn dir(&self) -> Result<PathBuf, errors::Error> {
+    Path::new(&self.dir).canonicalize().map_err(|err| err.into())
+  }
+
+  /// Returns fuzzy pattern.
+  pub fn pattern(&self) -> &str {
+    &self.pattern
+  }
+
+  /// Max number of files to return.
+  pub fn limit(&self) -> Option<usize> {
+    self.limit
+  }
+
+  // Whether or not to use cache for search.
+  pub fn use_cache(&self) -> bool {
+    self.use_cache.unwrap_or(false)
+  }
+
+  /// Whether or not to match hidden files and directories.
+  pub fn include_hidden(&self) -> bool {
+    self.include_hidden.unwrap_or(false)
+  }
+
+  /// Whether or not to match files excluded by `.gitignore` and `.ignore`.
+  pub fn include_ignored(&self) -> bool {
+    self.include_ignored.unwrap_or(false)
+  }
+}
+
+/// Input struct for symbol search.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SymbolsParams {
+  dir: String,
+  pattern: String,
+  kinds: Option<Vec<SymbolKind>>,
+  limit: Option<usize>
+}
+
+impl SymbolsParams {
+  /// Returns root directory.
+  pub fn dir(&self) -> Result<PathBuf, errors::Error> {
+    Path::new(&self.dir).canonicalize().map_err(|err| err.into())
+  }
+
+  /// Returns symbol name pattern.
+  pub fn pattern(&self) -> &str {
+    &self.pattern
+  }
+
+  /// Returns true if symbols of the kind should be returned, all kinds are returned by default.
+  pub fn has_kind(&self, kind: SymbolKind) -> bool {
+    self.kinds.as_ref().map(|kinds| kinds.contains(&kind)).unwrap_or(true)
+  }
+
+  /// Max number of symbols to return.
+  pub fn limit(&self) -> Option<usize> {
+    self.limit
+  }
+}
+
+/// Input struct for replacement preview.
+/// Matches are found with the same query fields as search: pattern, regex, mode, case, scope
+/// and walk filters, fields that do not apply to files on disk, e.g. `rev`, are rejected.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ReplaceParams {
+  #[serde(flatten)]
+  query: QueryParams,
+  replacement: String
+}
+
+impl ReplaceParams {
+  /// Returns query to find matches.
+  pub fn query(&self) -> &QueryParams {
+    &self.query
+  }
+
+  /// Returns replacement, may reference capture groups as `$1` or `${name}` in regex mode.
+  pub fn replacement(&self) -> &str {
+    &self.replacement
+  }
+}
+
+/// File selected for replacement, all matched lines are replaced if lines are not provided.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FileSelection {
+  path: String,
+  hash: String,
+  lines: Option<Vec<u64>>
+}
+
+impl FileSelection {
+  /// Returns path of the file.
+  pub fn path(&self) -> &str {
+    &self.path
+  }
+
+  /// Returns content hash returned by preview.
+  pub fn hash(&self) -> &str {
+    &self.hash
+  }
+
+  /// Returns line numbers to replace.
+  pub fn lines(&self) -> Option<&[u64]> {
+    self.lines.as_deref()
+  }
+}
+
+/// Input struct to apply replacements, query must be the same as in preview.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ApplyParams {
+  #[serde(flatten)]
+  query: QueryParams,
+  replacement: String,
+  files: Vec<FileSelection>
+}
+
+impl ApplyParams {
+  /// Returns query to find matches.
+  pub fn query(&self) -> &QueryParams {
+    &self.query
+  }
+
+  /// Returns replacement.
+  pub fn replacement(&self) -> &str {
+    &self.replacement
+  }
+
+  /// Returns files selected for replacement.
+  pub fn files(&self) -> &[FileSelection] {
+    &self.files
+  }
+}
+
+/// Input struct for history search.
+/// Either `revs` or `range` selects commits, range is `HEAD` by default.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HistoryParams {
+  dir: String,
+  pattern: String,
+  use_regex: Option<bool>,
+  revs: Option<Vec<String>>, // commits to scan, each is compared with its first parent
+  range: Option<String>, // revision or range, e.g. `v1.0..main`
+  max_commits: Option<usize>
+}
+
+impl HistoryParams {
+  /// Returns root directory.
+  pub fn dir(&self) -> Result<PathBuf, errors::Error> {
+    Path::new(&self.dir).canonicalize().map_err(|err| err.into())
+  }
+
+  /// Returns search pattern.
+  pub fn pattern(&self) -> &str {
+    &self.pattern
+  }
+
+  /// Whether or not pattern is a regular expression.
+  pub fn use_regex(&self) -> bool {
+    self.use_regex.unwrap_or(false)
+  }
+
+  /// Returns selected commits.
+  pub fn revs(&self) -> Option<&[String]> {
+    self.revs.as_deref()
+  }
+
+  /// Returns revision or commit range to walk.
+  pub fn range(&self) -> Option<&str> {
+    self.range.as_deref()
+  }
+
+  /// Returns maximum number of commits to scan.
+  pub fn max_commits(&self) -> Option<usize> {
+    self.max_commits
+  }
+}
+
+/// Input struct to push content of the unsaved buffer.
+/// Content is UTF-8 text as edited, buffers are searched as UTF-8 even if the project or query
+/// sets a different encoding for files on disk.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BufferParams {
+  path: String,
+  content: String
+}
+
+impl BufferParams {
+  /// Returns path of the buffer, canonicalized if file exists.
+  pub fn path(&self) -> PathBuf {
+    buffer_path(&self.path)
+  }
+
+  /// Returns content of the buffer as UTF-8 text.
+  pub fn content(&self) -> &str {
+    &self.content
+  }
+}
+
+/// Input struct to clear unsaved buffers, all buffers are cleared if path is not provided.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ClearBufferParams {
+  path: Option<String>
+}
+
+impl ClearBufferParams {
+  /// Returns path of the buffer, canonicalized if file exists.
+  pub fn path(&self) -> Option<PathBuf> {
+    self.path.as_ref().map(|path| buffer_path(path))
+  }
+}
+
+// Returns canonical path of the buffer, so that it matches paths found by search.
+// Buffers of new files that do not exist on disk keep the original path.
+fn buffer_path(path: &str) -> PathBuf {
+  Path::new(path).canonicalize().unwrap_or_else(|_| PathBuf::from(path))
 }
 
 /// Input struct for cache parameters.
+/// All fields except `dir` are optional and fall back to cache defaults.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct CacheParams {
-  dir: String
+  dir: String,
+  min_file_size: Option<u64>,
+  max_file_size: Option<u64>,
+  extensions: Option<Vec<String>>,
+  refresh_interval_secs: Option<u64>,
+  ignore: Option<Vec<String>>,
+  follow_links: Option<bool>,
+  rev: Option<String>,
+  encoding: Option<String>,
+  include_hidden: Option<bool>,
+  include_ignored: Option<bool>
 }
 
 impl CacheParams {
@@ -44,4 +396,54 @@ impl CacheParams {
   pub fn dir(&self) -> Result<PathBuf, errors::Error> {
     Path::new(&self.dir).canonicalize().map_err(|err| err.into())
   }
+
+  // Min file size in bytes to keep file content in memory.
+  pub fn min_file_size(&self) -> Option<u64> {
+    self.min_file_size
+  }
+
+  // Max file size in bytes to keep file content in memory.
+  pub fn max_file_size(&self) -> Option<u64> {
+    self.max_file_size
+  }
+
+  // List of file extensions to index.
+  pub fn extensions(&self) -> Option<&[String]> {
+    self.extensions.as_ref().map(|vec| vec.as_ref())
+  }
+
+  // Number of seconds between cache refreshes.
+  pub fn refresh_interval_secs(&self) -> Option<u64> {
+    self.refresh_interval_secs
+  }
+
+  // List of globs to exclude from the index, in addition to standard filters.
+  pub fn ignore(&self) -> Option<&[String]> {
+    self.ignore.as_ref().map(|vec| vec.as_ref())
+  }
+
+  // Whether or not to follow symbolic links.
+  pub fn follow_links(&self) -> Option<bool> {
+    self.follow_links
+  }
+
+  // Git revision (branch, tag or commit) to index instead of the working directory.
+  pub fn rev(&self) -> Option<&str> {
+    self.rev.as_deref()
+  }
+
+  // Encoding label of files without byte order mark, UTF-8 by default.
+  pub fn encoding(&self) -> Option<&str> {
+    self.encoding.as_deref()
+  }
+
+  // Whether or not to index hidden files and directories.
+  pub fn include_hidden(&self) -> bool {
+    self.include_hidden.unwrap_or(false)
+  }
+
+  // Whether or not to index files excluded by `.gitignore` and `.ignore`.
+  pub fn include_ignored(&self) -> bool {
+    self.include_ignored.unwrap_or(false)
+  }
 }
@@ -0,0 +1,366 @@
+//! Ranking of content search results.
+//! Each content item gets a score computed from the match lines and the file path, items are
+//! sorted by score and then by path, so the order is the same across runs.
+
+use std::cmp;
+use std::path::Path;
+
+use globset::GlobSet;
+use result::{ContentItem, ContentKind, ContentLine};
+use search::{relative_path, MatcherSpec};
+
+// Score for each matched line, capped by `MAX_SCORED_LINES`.
+const SCORE_LINE: i64 = 1;
+// Maximum number of matched lines that contribute to the score.
+const MAX_SCORED_LINES: i64 = 10;
+// Bonus for the match that has the same case as the pattern.
+const BONUS_EXACT_CASE: i64 = 10;
+// Bonus for the match that is a whole word.
+const BONUS_WHOLE_WORD: i64 = 20;
+// Bonus for the match in a line that looks like a definition, e.g. `fn name` or `class Name`.
+const BONUS_DEFINITION: i64 = 40;
+// Bonus for the file whose name also matches the pattern.
+const BONUS_FILE_NAME: i64 = 30;
+// Penalty for each directory between the root and the file.
+const PENALTY_PATH_DEPTH: i64 = 2;
+// Penalty for test files and directories.
+const PENALTY_TEST: i64 = 25;
+// Penalty for vendored and third-party code.
+const PENALTY_VENDOR: i64 = 50;
+// Penalty for generated and build output files.
+const PENALTY_GENERATED: i64 = 50;
+
+// Keywords that start a definition, modifiers are skipped before checking the keyword.
+const DEFINITION_KEYWORDS: &[&str] = &[
+  "class", "const", "def", "enum", "fn", "func", "function", "interface", "let", "macro_rules!",
+  "mod", "object", "static", "struct", "trait", "type", "val", "var"
+];
+const DEFINITION_MODIFIERS: &[&str] = &[
+  "abstract", "async", "case", "export", "final", "implicit", "lazy", "override", "private",
+  "protected", "pub", "pub(crate)", "public", "sealed", "unsafe"
+];
+
+const TEST_DIRS: &[&str] = &["test", "tests", "__tests__", "spec", "testing", "testdata"];
+const VENDOR_DIRS: &[&str] = &["vendor", "third_party", "thirdparty", "node_modules", "external"];
+const GENERATED_DIRS: &[&str] = &["generated", "gen", "target", "build", "dist", "out"];
+const GENERATED_SUFFIXES: &[&str] = &[".min.js", ".min.css", ".pb.go", "_pb2.py", ".g.dart"];
+
+/// Path penalties of the project, defaults are used for penalties that are not set.
+/// Glob penalties are added for every glob that matches the path, negative values boost files.
+#[derive(Clone, Debug)]
+pub struct PathPenalties {
+  depth: i64,
+  test: i64,
+  vendor: i64,
+  generated: i64,
+  globs: GlobSet,
+  glob_penalties: Vec<i64> // penalty of the glob with the same index
+}
+
+impl PathPenalties {
+  /// Creates path penalties.
+  pub fn new(
+    depth: Option<i64>,
+    test: Option<i64>,
+    vendor: Option<i64>,
+    generated: Option<i64>,
+    globs: GlobSet,
+    glob_penalties: Vec<i64>
+  ) -> Self {
+    Self {
+      depth: depth.unwrap_or(PENALTY_PATH_DEPTH),
+      test: test.unwrap_or(PENALTY_TEST),
+      vendor: vendor.unwrap_or(PENALTY_VENDOR),
+      generated: generated.unwrap_or(PENALTY_GENERATED),
+      globs,
+      glob_penalties
+    }
+  }
+}
+
+impl Default for PathPenalties {
+  fn default() -> Self {
+    Self::new(None, None, None, None, GlobSet::empty(), Vec::new())
+  }
+}
+
+// Returns true if byte is part of the identifier.
+#[inline]
+fn is_word_byte(byte: u8) -> bool {
+  byte.is_ascii_alphanumeric() || byte == b'_'
+}
+
+// Returns true if match at `start..end` is not surrounded by identifier characters.
+fn is_whole_word(bytes: &[u8], start: usize, end: usize) -> bool {
+  let before = start == 0 || !is_word_byte(bytes[start - 1]);
+  let after = end >= bytes.len() || !is_word_byte(bytes[end]);
+  before && after
+}
+
+// Returns end offset of the definition keyword if line looks like a definition.
+fn definition_end(bytes: &[u8]) -> Option<usize> {
+  let line = ::std::str::from_utf8(bytes).ok()?;
+  let mut offset = line.len() - line.trim_start().len();
+  for token in line[offset..].split_whitespace() {
+    // Tokens are separated by whitespace, find actual position of the token in the line.
+    offset = offset + line[offset..].find(token)? + token.len();
+    if DEFINITION_KEYWORDS.contains(&token) {
+      return Some(offset);
+    }
+    if !DEFINITION_MODIFIERS.contains(&token) {
+      return None;
+    }
+  }
+  None
+}
+
+// Returns byte range of the defined name if line looks like a definition, i.e. the first
+// identifier after the definition keyword, e.g. `x` in `let mut x = foo();`.
+fn definition_name(bytes: &[u8]) -> Option<(usize, usize)> {
+  let mut start = definition_end(bytes)?;
+  loop {
+    while start < bytes.len() && bytes[start].is_ascii_whitespace() {
+      start += 1;
+    }
+    let mut end = start;
+    while end < bytes.len() && is_word_byte(bytes[end]) {
+      end += 1;
+    }
+    match &bytes[start..end] {
+      b"" => return None,
+      b"mut" => start = end,
+      _ => return Some((start, end))
+    }
+  }
+}
+
+// Returns score of a single matched line.
+fn score_line(line: &ContentLine, pattern: Option<&[u8]>) -> i64 {
+  let bytes = line.bytes();
+  let (start, end) = match line.range() {
+    Some((start, end)) if start <= end && end <= bytes.len() => (start, end),
+    _ => return SCORE_LINE
+  };
+
+  let mut score = SCORE_LINE;
+  if pattern.map(|p| p == &bytes[start..end]).unwrap_or(false) {
+    score += BONUS_EXACT_CASE;
+  }
+  if is_whole_word(bytes, start, end) {
+    score += BONUS_WHOLE_WORD;
+  }
+  if definition_name(bytes).map(|(s, e)| s <= start && end <= e).unwrap_or(false) {
+    score += BONUS_DEFINITION;
+  }
+  score
+}
+
+// Returns penalty for the path relative to the search directory.
+fn path_penalty(penalties: &PathPenalties, path: &Path, rel_path: &Path) -> i64 {
+  let mut penalty = 0;
+  let mut is_test = false;
+  let mut is_vendor = false;
+  let mut is_generated = false;
+
+  let num_components = rel_path.components().count() as i64;
+  penalty += penalties.depth * cmp::max(num_components - 1, 0);
+
+  if let Some(parent) = rel_path.parent() {
+    for component in parent.iter().filter_map(|os| os.to_str()) {
+      let dir = component.to_lowercase();
+      is_test |= TEST_DIRS.contains(&dir.as_str());
+      is_vendor |= VENDOR_DIRS.contains(&dir.as_str());
+      is_generated |= GENERATED_DIRS.contains(&dir.as_str());
+    }
+  }
+
+  if let Some(name) = rel_path.file_name().and_then(|os| os.to_str()) {
+    let stem = name.split('.').next().unwrap_or("");
+    is_test |= stem.starts_with("test_") || stem.ends_with("_test") ||
+      stem.ends_with("Test") || stem.ends_with("Tests") || stem.ends_with("Suite") ||
+      name.contains(".test.") || name.contains(".spec.");
+    is_generated |= GENERATED_SUFFIXES.iter().any(|suffix| name.ends_with(suffix));
+  }
+
+  if is_test {
+    penalty += penalties.test;
+  }
+  if is_vendor {
+    penalty += penalties.vendor;
+  }
+  if is_generated {
+    penalty += penalties.generated;
+  }
+  if !penalties.glob_penalties.is_empty() {
+    for i in penalties.globs.matches(path) {
+      penalty += penalties.glob_penalties[i];
+    }
+  }
+  penalty
+}
+
+/// Computes score of the content item, higher is better.
+/// Item score is the best line score plus a small bonus for the number of matched lines,
+/// adjusted by file name match and path penalties.
+pub fn score_content(
+  root: &Path,
+  spec: &MatcherSpec,
+  penalties: &PathPenalties,
+  item: &ContentItem
+) -> i64 {
+  // Exact case only makes sense for substring patterns, regex can match different text.
+  let pattern = spec.direct_ref().map(|direct| direct.pattern().as_bytes());
+
+  let mut best = 0;
+  let mut num_lines = 0;
+  for mat in item.matches() {
+    for line in mat.lines().iter().filter(|line| line.kind() == ContentKind::Match) {
+      best = cmp::max(best, score_line(line, pattern));
+      num_lines += 1;
+    }
+  }
+
+  let rel_path = Path::new(relative_path(root, item.path()));
+  let mut score = best + SCORE_LINE * cmp::min(num_lines, MAX_SCORED_LINES);
+  let fname = rel_path.file_name().and_then(|os| os.to_str()).unwrap_or("");
+  if spec.is_match(fname) {
+    score += BONUS_FILE_NAME;
+  }
+  score - path_penalty(penalties, Path::new(item.path()), rel_path)
+}
+
+/// Scores content items and sorts them by score (descending), then by path.
+pub fn rank_content(
+  root: &Path,
+  spec: &MatcherSpec,
+  penalties: &PathPenalties,
+  content: &mut [ContentItem]
+) {
+  for item in content.iter_mut() {
+    let score = score_content(root, spec, penalties, item);
+    item.set_score(score);
+  }
+  content.sort_by(|a, b| {
+    b.score().cmp(&a.score()).then_with(|| a.path().cmp(b.path()))
+  });
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use ext::Extension;
+  use globset::{Glob, GlobSetBuilder};
+  use grep::regex::RegexMatcher;
+  use result::ContentMatch;
+  use search::DirectMatcher;
+
+  // Returns content item with a single matched line, match range is the first occurrence.
+  fn item(path: &str, line: &str, pattern: &str) -> ContentItem {
+    let start = line.to_lowercase().find(&pattern.to_lowercase()).expect("pattern in line");
+    let line = ContentLine::new(ContentKind::Match, 1, line.as_bytes(), Some(start),
+      Some(start + pattern.len()));
+    ContentItem::new(path.to_owned(), Extension::RS, vec![ContentMatch::new(vec![line])], false,
+      false, "UTF-8".to_owned())
+  }
+
+  // Returns direct matcher spec with smart case.
+  fn direct(pattern: &str) -> MatcherSpec {
+    MatcherSpec::direct(DirectMatcher::new(pattern, true))
+  }
+
+  #[test]
+  fn test_is_whole_word() {
+    assert!(is_whole_word(b"let foo = 1", 4, 7));
+    assert!(is_whole_word(b"foo", 0, 3));
+    assert!(!is_whole_word(b"let foobar = 1", 4, 7));
+    assert!(!is_whole_word(b"let _foo = 1", 5, 8));
+  }
+
+  #[test]
+  fn test_definition_end() {
+    assert_eq!(definition_end(b"fn foo() {}"), Some(2));
+    assert_eq!(definition_end(b"  pub(crate) struct Foo;"), Some(19));
+    assert_eq!(definition_end(b"export class Foo {}"), Some(12));
+    assert_eq!(definition_end(b"return foo();"), None);
+    assert_eq!(definition_end(b"pub"), None);
+    assert_eq!(definition_end(b""), None);
+  }
+
+  #[test]
+  fn test_definition_name() {
+    assert_eq!(definition_name(b"fn foo() {}"), Some((3, 6)));
+    assert_eq!(definition_name(b"  pub(crate) struct Foo;"), Some((20, 23)));
+    assert_eq!(definition_name(b"let mut foo = 1;"), Some((8, 11)));
+    assert_eq!(definition_name(b"let x = foo();"), Some((4, 5)));
+    assert_eq!(definition_name(b"fn"), None);
+    assert_eq!(definition_name(b"return foo();"), None);
+  }
+
+  #[test]
+  fn test_score_line() {
+    let root = Path::new("/repo");
+    let spec = direct("foo");
+    let penalties = PathPenalties::default();
+    let score = |line: &str| {
+      score_content(root, &spec, &penalties, &item("/repo/a.rs", line, "foo"))
+    };
+    let definition = score("fn foo() {}");
+    let call = score("x = foo();");
+    let assigned = score("let x = foo();");
+    let partial = score("x = foobar();");
+    let other_case = score("x = Foo();");
+    assert!(definition > call);
+    assert_eq!(assigned, call);
+    assert!(score("let foo = 1;") > call);
+    assert!(call > partial);
+    assert!(call > other_case);
+  }
+
+  #[test]
+  fn test_path_penalty() {
+    let penalties = PathPenalties::default();
+    let penalty = |path: &str| {
+      path_penalty(&penalties, &Path::new("/repo").join(path), Path::new(path))
+    };
+    assert_eq!(penalty("lib.rs"), 0);
+    assert_eq!(penalty("src/lib.rs"), PENALTY_PATH_DEPTH);
+    assert_eq!(penalty("tests/lib.rs"), PENALTY_PATH_DEPTH + PENALTY_TEST);
+    assert_eq!(penalty("lib_test.go"), PENALTY_TEST);
+    assert_eq!(penalty("vendor/lib.rs"), PENALTY_PATH_DEPTH + PENALTY_VENDOR);
+    assert_eq!(penalty("app.min.js"), PENALTY_GENERATED);
+    assert_eq!(penalty("Vendor/Gen/lib.rs"),
+      2 * PENALTY_PATH_DEPTH + PENALTY_VENDOR + PENALTY_GENERATED);
+  }
+
+  #[test]
+  fn test_path_penalty_config() {
+    let mut globs = GlobSetBuilder::new();
+    globs.add(Glob::new("/repo/legacy/**").unwrap());
+    globs.add(Glob::new("**/*.rs").unwrap());
+    let penalties = PathPenalties::new(Some(0), Some(1), None, None, globs.build().unwrap(),
+      vec![40, -5]);
+    let penalty = |path: &str| {
+      path_penalty(&penalties, &Path::new("/repo").join(path), Path::new(path))
+    };
+    assert_eq!(penalty("legacy/test/lib.rs"), 1 + 40 - 5);
+    assert_eq!(penalty("src/lib.rs"), -5);
+    assert_eq!(penalty("src/lib.go"), 0);
+  }
+
+  #[test]
+  fn test_rank_content() {
+    let root = Path::new("/repo");
+    let spec = MatcherSpec::regex(RegexMatcher::new("foo").unwrap());
+    let mut content = vec![
+      item("/repo/tests/b.rs", "fn foo() {}", "foo"),
+      item("/repo/b.rs", "x = foo();", "foo"),
+      item("/repo/a.rs", "x = foo();", "foo"),
+      item("/repo/src/foo.rs", "x = foo();", "foo")
+    ];
+    rank_content(root, &spec, &PathPenalties::default(), &mut content);
+    let paths = content.iter().map(|item| item.path()).collect::<Vec<_>>();
+    // File name match wins, ties are resolved by path.
+    assert_eq!(paths, vec!["/repo/src/foo.rs", "/repo/tests/b.rs", "/repo/a.rs", "/repo/b.rs"]);
+    assert!(content.iter().all(|item| item.score().is_some()));
+  }
+}
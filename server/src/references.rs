@@ -0,0 +1,297 @@
+//! References search: whole word occurrences of an identifier classified as definition,
+//! import/use, call or other with per-language heuristics.
+//! Occurrences in comments are classified as other, comment syntax comes from the language
+//! registry, string literals are not recognised.
+
+use std::str;
+
+use errors;
+use ext::{self, Extension, Extensions};
+use grep::regex::{RegexMatcher, RegexMatcherBuilder};
+use result::{ContentItem, ContentKind, RefKind};
+use symbols::SymbolExtractor;
+
+/// Returns extensions of languages supported by references search.
+pub fn extensions() -> Extensions {
+  Extensions::with_extensions(vec![
+    Extension::GO,
+    Extension::JAVA,
+    Extension::JS,
+    Extension::JSX,
+    Extension::PY,
+    Extension::RS,
+    Extension::SCALA,
+    Extension::TS,
+    Extension::TSX
+  ])
+}
+
+/// Returns true if pattern is a valid identifier.
+pub fn is_identifier(pattern: &str) -> bool {
+  let mut chars = pattern.chars();
+  match chars.next() {
+    Some(c) if c.is_alphabetic() || c == '_' => chars.all(|c| c.is_alphanumeric() || c == '_'),
+    _ => false
+  }
+}
+
+/// Builds case sensitive matcher for whole word occurrences of the identifier.
+pub fn matcher(identifier: &str) -> Result<RegexMatcher, errors::Error> {
+  if !is_identifier(identifier) {
+    return err!("Invalid identifier {}, expected a name for references search", identifier);
+  }
+  let pattern = format!(r"\b{}\b", identifier);
+  let matcher = RegexMatcherBuilder::new()
+    .line_terminator(Some(b'\n'))
+    .multi_line(false)
+    .build(&pattern)?;
+  Ok(matcher)
+}
+
+// Returns true if trimmed line is an import statement for the extension.
+fn is_import(ext: Extension, line: &str) -> bool {
+  match ext {
+    Extension::RS => {
+      let line = line.trim_start_matches("pub ").trim_start_matches("pub(crate) ");
+      line.starts_with("use ") || line.starts_with("extern crate ") ||
+        (line.starts_with("mod ") && line.ends_with(';'))
+    },
+    Extension::JAVA | Extension::SCALA => line.starts_with("import "),
+    Extension::PY => line.starts_with("import ") || line.starts_with("from "),
+    Extension::GO => {
+      // Lines in `import (...)` block are either `"path"` or `alias "path"`.
+      line.starts_with("import ") ||
+        (line.ends_with('"') && line.split_whitespace().count() <= 2 &&
+          line.split_whitespace().last().map(|s| s.starts_with('"')).unwrap_or(false))
+    },
+    Extension::JS | Extension::JSX | Extension::TS | Extension::TSX => {
+      line.starts_with("import ") || line.contains("require(") ||
+        (line.starts_with("export ") && line.contains(" from "))
+    },
+    _ => false
+  }
+}
+
+// Returns true if identifier at the end offset is followed by call arguments.
+fn is_call(ext: Extension, line: &str, end: usize) -> bool {
+  let rest = line[end..].trim_start();
+  if rest.starts_with('(') {
+    return true;
+  }
+  match ext {
+    // Macro invocation and turbofish call, e.g. `name!(...)` or `name::<T>(...)`.
+    Extension::RS => rest.starts_with("!(") || rest.starts_with("![") ||
+      rest.starts_with("!{") || rest.starts_with("::<"),
+    // Type arguments, e.g. `name[T](...)`, `name<T>(...)`.
+    Extension::SCALA => rest.starts_with('[') && rest.contains("]("),
+    Extension::JAVA | Extension::TS | Extension::TSX => {
+      rest.starts_with('<') && rest.contains(">(")
+    },
+    _ => false
+  }
+}
+
+// Returns true if the byte offset in the line is within a comment of the language.
+// Only the line itself is checked, block comments that span multiple lines are recognised on
+// the line that closes them.
+fn in_comment(ext: Extension, line: &str, start: usize) -> bool {
+  let lang = match ext::registry().language(ext) {
+    Some(lang) => lang,
+    None => return false
+  };
+  let bytes = line.as_bytes();
+  let mut block_end = None;
+  let mut i = 0;
+  while i < start {
+    let rest = &bytes[i..];
+    if let Some(end) = block_end {
+      if rest.starts_with(end) {
+        block_end = None;
+        i += end.len();
+        continue;
+      }
+    } else if let Some((open, close)) = lang.block_comment() {
+      if rest.starts_with(open.as_bytes()) {
+        block_end = Some(close.as_bytes());
+        i += open.len();
+        continue;
+      }
+    }
+    if block_end.is_none() &&
+        lang.line_comment().map(|token| rest.starts_with(token.as_bytes())).unwrap_or(false) {
+      return true;
+    }
+    i += 1;
+  }
+  if block_end.is_some() {
+    return true;
+  }
+  // Block comment opened on one of the previous lines and closed after the occurrence.
+  match lang.block_comment() {
+    Some((open, close)) if open != close => {
+      let rest = &line[start..];
+      match (rest.find(close), rest.find(open)) {
+        (Some(end), Some(next)) => end < next,
+        (Some(_), None) => true,
+        _ => false
+      }
+    },
+    _ => false
+  }
+}
+
+// Classifies occurrence of the identifier at `start..end` in the line.
+fn classify(
+  extractor: &mut SymbolExtractor,
+  ext: Extension,
+  identifier: &str,
+  line: &str,
+  start: usize,
+  end: usize
+) -> Result<RefKind, errors::Error> {
+  if in_comment(ext, line, start) {
+    return Ok(RefKind::Other);
+  }
+  let symbols = extractor.extract(ext, line.as_bytes())?;
+  if symbols.iter().any(|symbol| symbol.name() == identifier && symbol.column() == start) {
+    Ok(RefKind::Definition)
+  } else if is_import(ext, line.trim()) {
+    Ok(RefKind::Import)
+  } else if is_call(ext, line, end) {
+    Ok(RefKind::Call)
+  } else {
+    Ok(RefKind::Other)
+  }
+}
+
+/// Classifies matched lines of the content items.
+pub fn classify_content(
+  identifier: &str,
+  content: &mut [ContentItem]
+) -> Result<(), errors::Error> {
+  let mut extractor = SymbolExtractor::new();
+  for item in content.iter_mut() {
+    let ext = item.ext();
+    for mat in item.matches_mut() {
+      for line in mat.lines_mut().iter_mut().filter(|line| line.kind() == ContentKind::Match) {
+        let kind = match (str::from_utf8(line.bytes()), line.range()) {
+          (Ok(text), Some((start, end))) => {
+            classify(&mut extractor, ext, identifier, text, start, end)?
+          },
+          _ => RefKind::Other
+        };
+        line.set_reference(kind);
+      }
+    }
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use json;
+
+  #[test]
+  fn test_in_comment_line() {
+    assert!(in_comment(Extension::RS, "let a = 1; // call foo()", 19));
+    assert!(!in_comment(Extension::RS, "foo(); // call", 0));
+    assert!(in_comment(Extension::PY, "x = 1  # foo()", 9));
+    assert!(!in_comment(Extension::PY, "foo()  # call", 0));
+  }
+
+  #[test]
+  fn test_in_comment_block() {
+    assert!(in_comment(Extension::JAVA, "/* foo() */ bar();", 3));
+    assert!(!in_comment(Extension::JAVA, "/* bar */ foo();", 10));
+    assert!(in_comment(Extension::JAVA, "   calls foo() */", 9));
+    assert!(!in_comment(Extension::JAVA, "foo(); /* next */", 0));
+    assert!(!in_comment(Extension::JSON, "// foo", 3));
+  }
+
+  #[test]
+  fn test_ref_kind_serde() {
+    let kinds = [RefKind::Definition, RefKind::Import, RefKind::Call, RefKind::Other];
+    let value = json::to_value(kinds).unwrap();
+    assert_eq!(value, json::json!(["definition", "import", "call", "other"]));
+    assert_eq!(json::from_value::<Vec<RefKind>>(value).unwrap(), kinds);
+  }
+
+  #[test]
+  fn test_classify_comment() {
+    let mut extractor = SymbolExtractor::new();
+    let kind = classify(&mut extractor, Extension::RS, "foo", "// see foo()", 7, 10).unwrap();
+    assert_eq!(kind, RefKind::Other);
+    let kind = classify(&mut extractor, Extension::RS, "foo", "  foo(1); // x", 2, 5).unwrap();
+    assert_eq!(kind, RefKind::Call);
+  }
+
+  // Returns kind of the first occurrence of the identifier in the line.
+  fn kind(ext: Extension, identifier: &str, line: &str) -> RefKind {
+    let start = line.find(identifier).expect("identifier in line");
+    let end = start + identifier.len();
+    classify(&mut SymbolExtractor::new(), ext, identifier, line, start, end).unwrap()
+  }
+
+  #[test]
+  fn test_classify_rust() {
+    assert_eq!(kind(Extension::RS, "load", "pub fn load(path: &Path) {"), RefKind::Definition);
+    assert_eq!(kind(Extension::RS, "Cache", "pub(crate) struct Cache {"), RefKind::Definition);
+    assert_eq!(kind(Extension::RS, "load", "use cache::load;"), RefKind::Import);
+    assert_eq!(kind(Extension::RS, "cache", "pub mod cache;"), RefKind::Import);
+    assert_eq!(kind(Extension::RS, "load", "  let x = load(path);"), RefKind::Call);
+    assert_eq!(kind(Extension::RS, "err", "  return err!(\"x\");"), RefKind::Call);
+    assert_eq!(kind(Extension::RS, "load", "  load::<T>(path);"), RefKind::Call);
+    assert_eq!(kind(Extension::RS, "load", "  let f = load;"), RefKind::Other);
+  }
+
+  #[test]
+  fn test_classify_java() {
+    assert_eq!(kind(Extension::JAVA, "Search", "public class Search {"), RefKind::Definition);
+    assert_eq!(kind(Extension::JAVA, "find", "  public List<String> find(String p) {"),
+      RefKind::Definition);
+    assert_eq!(kind(Extension::JAVA, "Search", "import com.x.Search;"), RefKind::Import);
+    assert_eq!(kind(Extension::JAVA, "find", "    return find(p);"), RefKind::Call);
+    assert_eq!(kind(Extension::JAVA, "find", "    this.<T>find(p);"), RefKind::Call);
+    assert_eq!(kind(Extension::JAVA, "Search", "    Search s = null;"), RefKind::Other);
+  }
+
+  #[test]
+  fn test_classify_scala() {
+    assert_eq!(kind(Extension::SCALA, "find", "  def find(q: Query) = {"), RefKind::Definition);
+    assert_eq!(kind(Extension::SCALA, "Query", "import x.{Query, Id}"), RefKind::Import);
+    assert_eq!(kind(Extension::SCALA, "find", "    find[Int](q)"), RefKind::Call);
+    assert_eq!(kind(Extension::SCALA, "Query", "  val q: Query = null"), RefKind::Other);
+  }
+
+  #[test]
+  fn test_classify_python() {
+    assert_eq!(kind(Extension::PY, "find", "    def find(self):"), RefKind::Definition);
+    assert_eq!(kind(Extension::PY, "find", "from search import find"), RefKind::Import);
+    assert_eq!(kind(Extension::PY, "search", "import search"), RefKind::Import);
+    assert_eq!(kind(Extension::PY, "find", "    return find (x)"), RefKind::Call);
+    assert_eq!(kind(Extension::PY, "find", "    f = find"), RefKind::Other);
+  }
+
+  #[test]
+  fn test_classify_go() {
+    assert_eq!(kind(Extension::GO, "Find", "func (s *Search) Find(p string) {"),
+      RefKind::Definition);
+    assert_eq!(kind(Extension::GO, "strings", "import \"strings\""), RefKind::Import);
+    assert_eq!(kind(Extension::GO, "strings", "\tstr \"strings\""), RefKind::Import);
+    assert_eq!(kind(Extension::GO, "Find", "\ts.Find(\"x\")"), RefKind::Call);
+    assert_eq!(kind(Extension::GO, "Find", "\tf := s.Find"), RefKind::Other);
+  }
+
+  #[test]
+  fn test_classify_js() {
+    assert_eq!(kind(Extension::JS, "load", "export const load = async (p) => read(p);"),
+      RefKind::Definition);
+    assert_eq!(kind(Extension::JS, "load", "import { load } from './cache';"), RefKind::Import);
+    assert_eq!(kind(Extension::JS, "fs", "const fs = require('fs');"), RefKind::Import);
+    assert_eq!(kind(Extension::JS, "load", "export { load } from './cache';"), RefKind::Import);
+    assert_eq!(kind(Extension::JS, "load", "  await load(p);"), RefKind::Call);
+    assert_eq!(kind(Extension::TS, "load", "  load<string>(p);"), RefKind::Call);
+    assert_eq!(kind(Extension::JS, "load", "  cb(load);"), RefKind::Other);
+  }
+}
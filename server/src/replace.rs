@@ -0,0 +1,592 @@
+//! Search and replace: preview of replacements per line and atomic apply.
+//! Preview returns a content hash for each file, apply refuses to modify any file if one of
+//! the files has changed since the preview. Only UTF-8 text is replaced, files in other
+//! encodings and binary files are left out of the preview and rejected by apply.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str;
+use std::sync::{mpsc, Arc};
+use std::sync::atomic::AtomicUsize;
+use std::time;
+
+use cache2;
+use config;
+use errors;
+use ext::Extensions;
+use grep::matcher::{Captures, Matcher};
+use grep::regex::RegexMatcher;
+use grep::searcher::{BinaryDetection, SearcherBuilder};
+use params::{self, QueryParams, SearchMode};
+use references;
+use result::{AppliedItem, ApplyResult, ContentItem, ContentKind, ReplaceItem, ReplaceLine};
+use result::ReplaceResult;
+use scope;
+use search::{self, relative_path, Collector, MatcherSpec, WalkFilters};
+
+// FNV-1a parameters for the content hash.
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+/// Returns hash of the file content as a hex string.
+/// Hash is only used to detect changes between preview and apply.
+pub fn content_hash(content: &[u8]) -> String {
+  let hash = content.iter().fold(FNV_OFFSET_BASIS, |hash, &byte| {
+    (hash ^ byte as u64).wrapping_mul(FNV_PRIME)
+  });
+  format!("{:016x}", hash)
+}
+
+// Returns replacement template, capture groups such as `$1` or `${name}` are only expanded
+// for regular expressions.
+fn build_template(replacement: &str, use_regex: bool) -> Vec<u8> {
+  if use_regex {
+    replacement.as_bytes().to_vec()
+  } else {
+    replacement.replace('$', "$$").into_bytes()
+  }
+}
+
+// Splits line into content and line terminator.
+fn split_terminator(line: &[u8]) -> (&[u8], &[u8]) {
+  let len = line.len();
+  if line.ends_with(b"\r\n") {
+    (&line[..len - 2], &line[len - 2..])
+  } else if line.ends_with(b"\n") {
+    (&line[..len - 1], &line[len - 1..])
+  } else {
+    (line, &[])
+  }
+}
+
+// Replaces all matches in the line (without terminator), returns new line and number of
+// replacements.
+fn replace_line(
+  matcher: &RegexMatcher,
+  template: &[u8],
+  line: &[u8]
+) -> Result<(Vec<u8>, usize), errors::Error> {
+  let mut caps = matcher.new_captures()?;
+  let mut dst = Vec::with_capacity(line.len());
+  let mut count = 0;
+  matcher.replace_with_captures(line, &mut caps, &mut dst, |caps, dst| {
+    caps.interpolate(|name| matcher.capture_index(name), line, template, dst);
+    count += 1;
+    true
+  })?;
+  Ok((dst, count))
+}
+
+// Returns path to the file if it is within the root directory.
+fn file_in_root(root: &Path, path: &str) -> Result<PathBuf, errors::Error> {
+  let file = Path::new(path).canonicalize()?;
+  if !file.starts_with(root) || !file.is_file() {
+    return err!("Path {} is not a file in {}", path, root.to_str().unwrap_or(""));
+  }
+  Ok(file)
+}
+
+// Returns root directory of the query, checks that query can be applied to files on disk.
+fn query_root(query: &QueryParams) -> Result<PathBuf, errors::Error> {
+  let root = query.dir()?;
+  if !root.is_dir() {
+    return err!("Path {} is not a directory", root.to_str().unwrap_or(""));
+  }
+  if query.rev().is_some() {
+    return err!("Replacement is not supported for revisions");
+  }
+  if query.search_archives() {
+    return err!("Replacement is not supported for archives");
+  }
+  Ok(root)
+}
+
+/// Previews replacements in the directory.
+/// Returns every matched line with the line before and after replacement. Files are selected
+/// the same way as in search, unsaved buffers are previewed instead of the content on disk and
+/// reported as dirty, since apply only modifies files on disk.
+pub fn preview(
+  cache: &cache2::SharedCache,
+  params: params::ReplaceParams
+) -> Result<ReplaceResult, errors::Error> {
+  let start_time = time::Instant::now();
+
+  let query = params.query();
+  let path_buf = query_root(query)?;
+  let root = path_buf.as_path();
+
+  let config = config::load(root)?;
+  let matcher = search::query_matcher(query, &config)?;
+  let template = build_template(params.replacement(), query.use_regex());
+  let ext_check = match query.mode() {
+    SearchMode::References => references::extensions(),
+    SearchMode::Text => Extensions::all()
+  };
+  let buffers = cache2::buffers(cache)?;
+  let scope = match query.scope()? {
+    Some(scope) => Some(scope::resolve(root, &scope)?),
+    None => None
+  };
+
+  // Binary files are never modified, content is matched as is, since apply does not transcode.
+  let mut searcher = SearcherBuilder::new()
+    .binary_detection(BinaryDetection::quit(b'\x00'))
+    .bom_sniffing(false)
+    .line_number(true)
+    .multi_line(false)
+    .build();
+
+  // Collector is only used to get matched lines, so the match limit is never reached.
+  let counter = Arc::new(AtomicUsize::new(0));
+  let (sx, rx) = mpsc::channel::<ContentItem>();
+
+  let filters = WalkFilters::new(query.include_hidden(), query.include_ignored());
+  let walker = cache2::walk_builder(cache, root, filters, &config)?.build();
+
+  let mut files = Vec::new();
+  let mut num_replacements = 0;
+  for entry in walker.filter_map(|res| res.ok()) {
+    let is_file = entry.file_type().map(|ftype| ftype.is_file()).unwrap_or(false);
+    let fpath = match (is_file, entry.path().to_str()) {
+      (true, Some(fpath)) => fpath,
+      _ => continue
+    };
+    if !scope.as_ref().map(|scope| scope.contains(fpath)).unwrap_or(true) {
+      continue;
+    }
+
+    let buffer = buffers.get(fpath);
+    let content = match buffer {
+      Some(buffer) => buffer.as_ref().clone(),
+      None => match fs::read(entry.path()) {
+        Ok(content) => content,
+        Err(_) => continue
+      }
+    };
+    if str::from_utf8(&content).is_err() {
+      continue;
+    }
+    let ext = config.mappings().detect(entry.path(), Some(&content));
+    if !ext_check.is_supported_extension(ext) {
+      continue;
+    }
+
+    let collector = Collector::new(
+      sx.clone(),
+      counter.clone(),
+      usize::MAX,
+      fpath.to_owned(),
+      MatcherSpec::regex(matcher.clone()),
+      ext,
+      buffer.is_some()
+    );
+    searcher.search_slice(matcher.clone(), &content, collector)?;
+
+    while let Ok(item) = rx.try_recv() {
+      let mut lines = Vec::new();
+      for line in item.matches().iter().flat_map(|mat| mat.lines()) {
+        if line.kind() != ContentKind::Match {
+          continue;
+        }
+        let (before, _) = split_terminator(line.bytes());
+        let (after, count) = replace_line(&matcher, &template, before)?;
+        num_replacements += count;
+        lines.push(ReplaceLine::new(
+          line.num(),
+          String::from_utf8_lossy(before).into_owned(),
+          String::from_utf8_lossy(&after).into_owned()
+        ));
+      }
+      files.push(ReplaceItem::new(
+        fpath.to_owned(),
+        relative_path(root, fpath).to_owned(),
+        ext,
+        content_hash(&content),
+        lines,
+        buffer.is_some()
+      ));
+    }
+  }
+
+  files.sort_by(|a, b| a.path().cmp(b.path()));
+
+  let duration = start_time.elapsed();
+  let exec_time = duration.as_secs() as f64 + duration.subsec_nanos() as f64 * 1e-9;
+
+  Ok(ReplaceResult::new(exec_time, files, num_replacements))
+}
+
+// Returns content with the selected lines replaced and number of replacements, all lines are
+// replaced if lines are not selected.
+fn replace_content(
+  matcher: &RegexMatcher,
+  template: &[u8],
+  content: &[u8],
+  selected: Option<&HashSet<u64>>
+) -> Result<(Vec<u8>, usize), errors::Error> {
+  let mut updated = Vec::with_capacity(content.len());
+  let mut count = 0;
+  for (i, line) in content.split(|&byte| byte == b'\n').enumerate() {
+    let num = i as u64 + 1;
+    // Split drops the terminator, it is restored for all lines except the last one.
+    if i > 0 {
+      updated.push(b'\n');
+    }
+    if selected.map(|lines| lines.contains(&num)).unwrap_or(true) {
+      let (line, terminator) = split_terminator(line);
+      let (replaced, num_replaced) = replace_line(matcher, template, line)?;
+      updated.extend_from_slice(&replaced);
+      updated.extend_from_slice(terminator);
+      count += num_replaced;
+    } else {
+      updated.extend_from_slice(line);
+    }
+  }
+  Ok((updated, count))
+}
+
+// Returns path of the temporary file next to the file with the suffix.
+fn sibling_path(file: &Path, suffix: &str) -> PathBuf {
+  let name = file.file_name().and_then(|os| os.to_str()).unwrap_or("");
+  file.with_file_name(format!(".{}.omnisearch.{}", name, suffix))
+}
+
+// Removes files, errors are ignored since files are temporary.
+fn remove_files(files: &[PathBuf]) {
+  for file in files {
+    let _ = fs::remove_file(file);
+  }
+}
+
+// Replaces originals with the temporary files. Originals are backed up first, if any rename
+// fails, already replaced files are restored from the backups. Returns error that lists files
+// that could not be restored, if any.
+fn replace_files(files: &[PathBuf], temps: &[PathBuf]) -> Result<(), errors::Error> {
+  let mut backups = Vec::with_capacity(files.len());
+  for file in files {
+    let backup = sibling_path(file, "bak");
+    if let Err(error) = fs::copy(file, &backup) {
+      remove_files(&backups);
+      remove_files(temps);
+      let _ = fs::remove_file(&backup);
+      return Err(error.into());
+    }
+    backups.push(backup);
+  }
+
+  for (i, (file, temp)) in files.iter().zip(temps).enumerate() {
+    if let Err(error) = fs::rename(temp, file) {
+      let mut partial = Vec::new();
+      for (file, backup) in files.iter().zip(&backups).take(i) {
+        if fs::rename(backup, file).is_err() {
+          partial.push(file.to_str().unwrap_or("").to_owned());
+        }
+      }
+      remove_files(&backups);
+      remove_files(&temps[i..]);
+      if partial.is_empty() {
+        return err!("Failed to apply replacements, no files were modified: {}", error);
+      }
+      return err!("Failed to apply replacements: {}, files {} could not be restored",
+        error, partial.join(", "));
+    }
+  }
+
+  remove_files(&backups);
+  Ok(())
+}
+
+/// Applies replacements to the selected lines of the files.
+/// All files are checked against the preview hash and rewritten in temporary files first,
+/// originals are only replaced once every file is ready. Originals are backed up while they
+/// are replaced and restored if replacement fails, so either all files are updated or none
+/// of them is, unless restore fails as well, which is reported in the error. Files with
+/// unsaved buffers are skipped and reported in the result.
+pub fn apply(
+  cache: &cache2::SharedCache,
+  params: params::ApplyParams
+) -> Result<ApplyResult, errors::Error> {
+  let start_time = time::Instant::now();
+
+  let query = params.query();
+  let path_buf = query_root(query)?;
+  let root = path_buf.as_path();
+
+  let config = config::load(root)?;
+  let matcher = search::query_matcher(query, &config)?;
+  let template = build_template(params.replacement(), query.use_regex());
+  let buffers = cache2::buffers(cache)?;
+
+  // Compute new content for all files before writing anything.
+  let mut seen = HashSet::new();
+  let mut skipped = Vec::new();
+  let mut updates = Vec::with_capacity(params.files().len());
+  for selection in params.files() {
+    let file = file_in_root(root, selection.path())?;
+    if !seen.insert(file.clone()) {
+      return err!("File {} is selected more than once", selection.path());
+    }
+    if file.to_str().map(|fpath| buffers.contains_key(fpath)).unwrap_or(false) {
+      skipped.push(selection.path().to_owned());
+      continue;
+    }
+    let content = fs::read(&file)?;
+    if content_hash(&content) != selection.hash() {
+      return err!("File {} has changed since preview", selection.path());
+    }
+    if str::from_utf8(&content).is_err() || content.contains(&b'\x00') {
+      return err!("File {} is not UTF-8 text", selection.path());
+    }
+
+    let selected = selection.lines().map(|lines| lines.iter().cloned().collect::<HashSet<_>>());
+    let (updated, count) = replace_content(&matcher, &template, &content, selected.as_ref())?;
+    if count > 0 {
+      updates.push((file, selection.path().to_owned(), updated, count));
+    }
+  }
+
+  // Write temporary files next to the originals, so that rename does not cross file systems.
+  let mut temps = Vec::with_capacity(updates.len());
+  for (file, _, updated, _) in &updates {
+    let temp = sibling_path(file, "tmp");
+    let res = fs::write(&temp, updated)
+      .and_then(|_| fs::metadata(file))
+      .and_then(|meta| fs::set_permissions(&temp, meta.permissions()));
+    temps.push(temp);
+    if let Err(error) = res {
+      remove_files(&temps);
+      return Err(error.into());
+    }
+  }
+
+  let targets = updates.iter().map(|(file, _, _, _)| file.clone()).collect::<Vec<_>>();
+  replace_files(&targets, &temps)?;
+  let files = updates.into_iter()
+    .map(|(_, path, _, count)| AppliedItem::new(path, count))
+    .collect::<Vec<_>>();
+
+  let duration = start_time.elapsed();
+  let exec_time = duration.as_secs() as f64 + duration.subsec_nanos() as f64 * 1e-9;
+
+  Ok(ApplyResult::new(exec_time, files, skipped))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use json;
+  use params::CaseMode;
+  use testutil::TempDir;
+
+  // Returns JSON query for the directory merged with the fields.
+  fn query(dir: &Path, fields: json::Value) -> json::Value {
+    let mut value = json::json!({ "dir": dir.to_str().unwrap() });
+    for (key, field) in fields.as_object().unwrap() {
+      value[key] = field.clone();
+    }
+    value
+  }
+
+  // Returns preview of the replacement as JSON.
+  fn preview_json(cache: &cache2::SharedCache, params: json::Value) -> json::Value {
+    let params = json::from_value::<params::ReplaceParams>(params).unwrap();
+    json::to_value(preview(cache, params).unwrap()).unwrap()
+  }
+
+  // Applies replacements to the files with the preview hash.
+  fn apply_files(
+    cache: &cache2::SharedCache,
+    params: json::Value,
+    files: &[&Path]
+  ) -> Result<ApplyResult, errors::Error> {
+    let mut params = params;
+    params["files"] = files.iter().map(|file| {
+      let hash = content_hash(&fs::read(file).unwrap());
+      json::json!({ "path": file.to_str().unwrap(), "hash": hash })
+    }).collect::<Vec<_>>().into();
+    apply(cache, json::from_value::<params::ApplyParams>(params).unwrap())
+  }
+
+  #[test]
+  fn test_split_terminator() {
+    assert_eq!(split_terminator(b"abc\n"), (&b"abc"[..], &b"\n"[..]));
+    assert_eq!(split_terminator(b"abc\r\n"), (&b"abc"[..], &b"\r\n"[..]));
+    assert_eq!(split_terminator(b"abc"), (&b"abc"[..], &b""[..]));
+    assert_eq!(split_terminator(b"\r"), (&b"\r"[..], &b""[..]));
+    assert_eq!(split_terminator(b""), (&b""[..], &b""[..]));
+  }
+
+  #[test]
+  fn test_replace_line_literal() {
+    let matcher = search::build_regex_matcher("a.b(", false, CaseMode::Sensitive).unwrap();
+    let template = build_template("$1 x", false);
+    let (line, count) = replace_line(&matcher, &template, b"a.b( axb( a.b(").unwrap();
+    assert_eq!(line, b"$1 x axb( $1 x".to_vec());
+    assert_eq!(count, 2);
+  }
+
+  #[test]
+  fn test_replace_line_regex() {
+    let matcher = search::build_regex_matcher(r"(\w+)=(?P<v>\d+)", true, CaseMode::Smart).unwrap();
+    let template = build_template("${v}:$1", true);
+    let (line, count) = replace_line(&matcher, &template, b"a=1, b=22, c=x").unwrap();
+    assert_eq!(line, b"1:a, 22:b, c=x".to_vec());
+    assert_eq!(count, 2);
+  }
+
+  #[test]
+  fn test_replace_line_no_match() {
+    let matcher = search::build_regex_matcher("foo", false, CaseMode::Smart).unwrap();
+    let (line, count) = replace_line(&matcher, b"bar", b"no match").unwrap();
+    assert_eq!(line, b"no match".to_vec());
+    assert_eq!(count, 0);
+  }
+
+  #[test]
+  fn test_replace_content_selected_lines() {
+    let matcher = search::build_regex_matcher("foo", false, CaseMode::Smart).unwrap();
+    let content = b"foo\r\nFoo\nfoo\nfoo";
+    let (updated, count) = replace_content(&matcher, b"bar", content, None).unwrap();
+    assert_eq!(updated, b"bar\r\nbar\nbar\nbar".to_vec());
+    assert_eq!(count, 4);
+
+    let selected = vec![2, 4].into_iter().collect::<HashSet<u64>>();
+    let (updated, count) = replace_content(&matcher, b"bar", content, Some(&selected)).unwrap();
+    assert_eq!(updated, b"foo\r\nbar\nfoo\nbar".to_vec());
+    assert_eq!(count, 2);
+  }
+
+  #[test]
+  fn test_preview_filters() {
+    let dir = TempDir::new(&[
+      ("a.txt", "Foo foo\n"),
+      (".hidden/b.txt", "foo\n"),
+      ("c.rs", "fn bar() {}\n")
+    ]);
+    let cache = cache2::create_cache();
+
+    let res = preview_json(&cache, query(&dir, json::json!({
+      "pattern": "foo", "replacement": "bar", "case": "sensitive"
+    })));
+    assert_eq!(res["num_replacements"], 1);
+    assert_eq!(res["files"].as_array().unwrap().len(), 1);
+    assert_eq!(res["files"][0]["lines"][0]["after"], "Foo bar");
+
+    let res = preview_json(&cache, query(&dir, json::json!({
+      "pattern": "foo", "replacement": "bar", "include_hidden": true
+    })));
+    assert_eq!(res["num_replacements"], 3);
+    assert_eq!(res["files"].as_array().unwrap().len(), 2);
+
+    // Unsaved buffers are previewed instead of the content on disk.
+    cache2::push_buffer(&cache, &dir.join("c.rs"), b"fn foo() {}\n".to_vec()).unwrap();
+    let res = preview_json(&cache, query(&dir, json::json!({
+      "pattern": "foo", "replacement": "bar", "mode": "references"
+    })));
+    assert_eq!(res["files"].as_array().unwrap().len(), 1);
+    assert_eq!(res["files"][0]["lines"][0]["after"], "fn bar() {}");
+    assert_eq!(res["files"][0]["dirty"], true);
+  }
+
+  #[test]
+  fn test_apply() {
+    let dir = TempDir::new(&[("a.txt", "foo\nbar\n"), ("b.txt", "foo foo"), ("c.txt", "bar")]);
+    let cache = cache2::create_cache();
+    let params = query(&dir, json::json!({ "pattern": "foo", "replacement": "baz" }));
+
+    let files = [dir.join("a.txt"), dir.join("b.txt"), dir.join("c.txt")];
+    let res = apply_files(&cache, params, &[&files[0], &files[1], &files[2]]).unwrap();
+    let res = json::to_value(res).unwrap();
+    assert_eq!(res["files"].as_array().unwrap().len(), 2);
+    assert_eq!(fs::read_to_string(&files[0]).unwrap(), "baz\nbar\n");
+    assert_eq!(fs::read_to_string(&files[1]).unwrap(), "baz baz");
+    assert_eq!(fs::read_to_string(&files[2]).unwrap(), "bar");
+    // Temporary files and backups are removed.
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 3);
+  }
+
+  #[test]
+  fn test_apply_rejects_changed_and_duplicate_files() {
+    let dir = TempDir::new(&[("a.txt", "foo\n"), ("b.txt", "foo\n")]);
+    let cache = cache2::create_cache();
+    let params = query(&dir, json::json!({ "pattern": "foo", "replacement": "bar" }));
+    let (a, b) = (dir.join("a.txt"), dir.join("b.txt"));
+
+    let mut changed = params.clone();
+    changed["files"] = json::json!([
+      { "path": a.to_str().unwrap(), "hash": content_hash(b"foo\n") },
+      { "path": b.to_str().unwrap(), "hash": content_hash(b"old\n") }
+    ]);
+    let changed = json::from_value::<params::ApplyParams>(changed).unwrap();
+    assert!(apply(&cache, changed).is_err());
+
+    assert!(apply_files(&cache, params.clone(), &[&a, &b, &a]).is_err());
+
+    // No file is modified if any file is rejected.
+    assert_eq!(fs::read_to_string(&a).unwrap(), "foo\n");
+    assert_eq!(fs::read_to_string(&b).unwrap(), "foo\n");
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+  }
+
+  #[test]
+  fn test_replace_files_restores_originals() {
+    let dir = TempDir::new(&[
+      ("a.txt", "a"),
+      ("b.txt", "b"),
+      ("a.tmp", "new a"),
+      ("c.tmp", "new c")
+    ]);
+    let files = [dir.join("a.txt"), dir.join("b.txt"), dir.join("c.txt")];
+    // Temporary file of `b.txt` is missing, so its rename fails after `a.txt` is replaced.
+    let temps = [dir.join("a.tmp"), dir.join("b.tmp"), dir.join("c.tmp")];
+    fs::write(&files[2], "c").unwrap();
+
+    assert!(replace_files(&files, &temps).is_err());
+    assert_eq!(fs::read_to_string(&files[0]).unwrap(), "a");
+    assert_eq!(fs::read_to_string(&files[1]).unwrap(), "b");
+    assert_eq!(fs::read_to_string(&files[2]).unwrap(), "c");
+    // Backups and remaining temporary files are removed.
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 3);
+  }
+
+  #[test]
+  fn test_query_root_rejects_revisions() {
+    let dir = TempDir::new(&[]);
+    let params = json::from_value::<QueryParams>(
+      query(&dir, json::json!({ "pattern": "foo", "rev": "HEAD" }))).unwrap();
+    assert!(query_root(&params).is_err());
+  }
+
+  #[test]
+  fn test_apply_skips_buffers() {
+    let dir = TempDir::new(&[("a.txt", "foo\n"), ("b.txt", "foo\n")]);
+    let cache = cache2::create_cache();
+    let params = query(&dir, json::json!({ "pattern": "foo", "replacement": "bar" }));
+    let (a, b) = (dir.join("a.txt"), dir.join("b.txt"));
+
+    cache2::push_buffer(&cache, &b, b"foo foo\n".to_vec()).unwrap();
+    let res = json::to_value(apply_files(&cache, params, &[&a, &b]).unwrap()).unwrap();
+    assert_eq!(res["files"].as_array().unwrap().len(), 1);
+    assert_eq!(res["skipped"], json::json!([b.to_str().unwrap()]));
+    assert_eq!(fs::read_to_string(&a).unwrap(), "bar\n");
+    assert_eq!(fs::read_to_string(&b).unwrap(), "foo\n");
+  }
+
+  #[test]
+  fn test_utf16_files_are_not_replaced() {
+    let dir = TempDir::new(&[("a.txt", "foo\n")]);
+    let mut utf16 = vec![0xff, 0xfe];
+    utf16.extend("foo\n".encode_utf16().flat_map(|unit| unit.to_le_bytes().to_vec()));
+    let b = dir.write("b.txt", &utf16);
+    let cache = cache2::create_cache();
+    let params = query(&dir, json::json!({ "pattern": "foo", "replacement": "bar" }));
+
+    let res = preview_json(&cache, params.clone());
+    assert_eq!(res["files"].as_array().unwrap().len(), 1);
+    assert_eq!(res["files"][0]["rel_path"], "a.txt");
+
+    assert!(apply_files(&cache, params, &[&b]).is_err());
+    assert_eq!(fs::read(&b).unwrap(), utf16);
+  }
+}
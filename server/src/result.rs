@@ -1,17 +1,40 @@
 use ext::Extension;
+use symbols::SymbolKind;
 use serde::ser::{Serialize, SerializeStruct, Serializer};
 
 /// File search item where name matches user's regular expression.
+/// Fuzzy matched items also have score and character positions of the match in the path.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct FileItem {
   path: String,
-  ext: Extension
+  rel_path: String, // path relative to the search directory
+  ext: Extension,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  score: Option<i64>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  positions: Option<Vec<usize>>
 }
 
 impl FileItem {
-  /// Creates new file item from path and file extension.
-  pub fn new(path: String, ext: Extension) -> Self {
-    Self { path, ext }
+  /// Creates new file item from path, relative path and file extension.
+  pub fn new(path: String, rel_path: String, ext: Extension) -> Self {
+    Self { path, rel_path, ext, score: None, positions: None }
+  }
+
+  /// Creates new file item with fuzzy match score and positions.
+  pub fn with_match(
+    path: String,
+    rel_path: String,
+    ext: Extension,
+    score: i64,
+    positions: Vec<usize>
+  ) -> Self {
+    Self { path, rel_path, ext, score: Some(score), positions: Some(positions) }
+  }
+
+  /// Returns path of the file.
+  pub fn path(&self) -> &str {
+    &self.path
   }
 }
 
@@ -32,6 +55,17 @@ impl Serialize for ContentKind {
   }
 }
 
+/// Classification of the identifier occurrence in references search.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RefKind {
+  Definition,
+  Import,
+  Call,
+  Other
+}
+
+
 const MAX_PREFIX_LENGTH: usize = 120;
 const MAX_SUFFIX_LENGTH: usize = 17;
 // Length of 3 corresponds to the "..." bytes.
@@ -44,7 +78,8 @@ pub struct ContentLine {
   num: u64,
   bytes: Vec<u8>,
   range: Option<(usize, usize)>,
-  truncated: bool
+  truncated: bool,
+  reference: Option<RefKind> // only set in references search
 }
 
 impl ContentLine {
@@ -54,34 +89,30 @@ impl ContentLine {
     kind: ContentKind, line_number: u64, bytes: &[u8],
     start: Option<usize>, end: Option<usize>
   ) -> Self {
+    let range = start.zip(end);
     let len = bytes.len();
     let (all_bytes, is_truncated) = if len < MAX_LENGTH {
       (bytes.to_vec(), false)
     } else {
       let mut vec = Vec::with_capacity(MAX_LENGTH);
-      if start.is_some() && end.is_some() {
+      if range.is_some() {
         // TODO: Add proper truncation mechanism
         vec.extend_from_slice(bytes);
       } else {
         vec.extend_from_slice(&bytes[..MAX_PREFIX_LENGTH]);
-        vec.extend_from_slice(&[b'.', b'.', b'.']);
+        vec.extend_from_slice(b"...");
         vec.extend_from_slice(&bytes[len - MAX_SUFFIX_LENGTH..len]);
       }
       (vec, true)
     };
 
-    let range = if start.is_some() && end.is_some() {
-      Some((start.unwrap(), end.unwrap()))
-    } else {
-      None
-    };
-
     Self {
-      kind: kind,
+      kind,
       num: line_number,
       bytes: all_bytes,
-      range: range,
-      truncated: is_truncated
+      range,
+      truncated: is_truncated,
+      reference: None
     }
   }
 
@@ -89,6 +120,31 @@ impl ContentLine {
   pub fn without_match(kind: ContentKind, line_number: u64, bytes: &[u8]) -> Self {
     Self::new(kind, line_number, bytes, None, None)
   }
+
+  /// Returns kind of the line.
+  pub fn kind(&self) -> ContentKind {
+    self.kind
+  }
+
+  /// Returns line number.
+  pub fn num(&self) -> u64 {
+    self.num
+  }
+
+  /// Returns line bytes, possibly truncated.
+  pub fn bytes(&self) -> &[u8] {
+    &self.bytes
+  }
+
+  /// Returns byte range of the match within the line, if available.
+  pub fn range(&self) -> Option<(usize, usize)> {
+    self.range
+  }
+
+  /// Sets classification of the match for references search.
+  pub fn set_reference(&mut self, kind: RefKind) {
+    self.reference = Some(kind);
+  }
 }
 
 impl Serialize for ContentLine {
@@ -113,6 +169,9 @@ impl Serialize for ContentLine {
       s.serialize_field("bytes", &String::from_utf8_lossy(&self.bytes))?;
     }
     s.serialize_field("truncated", &self.truncated)?;
+    if let Some(ref reference) = self.reference {
+      s.serialize_field("reference", reference)?;
+    }
     s.end()
   }
 }
@@ -129,6 +188,16 @@ impl ContentMatch {
   pub fn new(lines: Vec<ContentLine>) -> Self {
     Self { lines }
   }
+
+  /// Returns lines of the match.
+  pub fn lines(&self) -> &[ContentLine] {
+    &self.lines
+  }
+
+  /// Returns mutable lines of the match.
+  pub fn lines_mut(&mut self) -> &mut [ContentLine] {
+    &mut self.lines
+  }
 }
 
 /// Content item that has matches for user's regular expression.
@@ -136,13 +205,62 @@ impl ContentMatch {
 pub struct ContentItem {
   path: String,
   ext: Extension,
-  matches: Vec<ContentMatch>
+  matches: Vec<ContentMatch>,
+  dirty: bool, // whether or not matches come from the unsaved buffer
+  binary: bool, // whether or not file contains binary data, matches have no lines if true
+  encoding: String, // encoding the content was decoded from
+  #[serde(skip_serializing_if = "Option::is_none")]
+  score: Option<i64> // ranking score, set once results are ranked
 }
 
 impl ContentItem {
   /// Creates a new content item.
-  pub fn new(path: String, ext: Extension, matches: Vec<ContentMatch>) -> Self {
-    Self { path, ext, matches }
+  pub fn new(
+    path: String,
+    ext: Extension,
+    matches: Vec<ContentMatch>,
+    dirty: bool,
+    binary: bool,
+    encoding: String
+  ) -> Self {
+    Self { path, ext, matches, dirty, binary, encoding, score: None }
+  }
+
+  /// Returns path of the file.
+  pub fn path(&self) -> &str {
+    &self.path
+  }
+
+  /// Returns extension of the file.
+  pub fn ext(&self) -> Extension {
+    self.ext
+  }
+
+  /// Returns matches within the file.
+  pub fn matches(&self) -> &[ContentMatch] {
+    &self.matches
+  }
+
+  /// Returns mutable matches within the file.
+  pub fn matches_mut(&mut self) -> &mut [ContentMatch] {
+    &mut self.matches
+  }
+
+  /// Returns number of matched lines within the file.
+  pub fn num_matched_lines(&self) -> usize {
+    self.matches.iter()
+      .map(|mat| mat.lines().iter().filter(|line| line.kind() == ContentKind::Match).count())
+      .sum()
+  }
+
+  /// Returns ranking score, if set.
+  pub fn score(&self) -> Option<i64> {
+    self.score
+  }
+
+  /// Sets ranking score.
+  pub fn set_score(&mut self, score: i64) {
+    self.score = Some(score);
   }
 }
 
@@ -176,6 +294,7 @@ impl Serialize for Matched {
 pub struct SearchResult {
   time_sec: f64, // time taken to execute query
   used_cache: bool, // whether or not cache was used for search
+  txid: Option<usize>, // txid of the index tree used for search
   files: Vec<FileItem>,
   file_matches: Matched,
   content: Vec<ContentItem>,
@@ -187,11 +306,248 @@ impl SearchResult {
   pub fn new(
     time_sec: f64,
     used_cache: bool,
+    txid: Option<usize>,
     files: Vec<FileItem>,
     file_matches: Matched,
     content: Vec<ContentItem>,
     content_matches: Matched
   ) -> Self {
-    Self { time_sec, used_cache, files, file_matches, content, content_matches }
+    Self { time_sec, used_cache, txid, files, file_matches, content, content_matches }
+  }
+}
+
+/// Result of the fuzzy file search, files are ranked by score.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FilesResult {
+  time_sec: f64, // time taken to execute query
+  used_cache: bool, // whether or not cache was used for search
+  txid: Option<usize>, // txid of the index tree used for search
+  files: Vec<FileItem>,
+  file_matches: Matched
+}
+
+impl FilesResult {
+  /// Creates a new files result.
+  pub fn new(
+    time_sec: f64,
+    used_cache: bool,
+    txid: Option<usize>,
+    files: Vec<FileItem>,
+    file_matches: Matched
+  ) -> Self {
+    Self { time_sec, used_cache, txid, files, file_matches }
+  }
+}
+
+/// Symbol definition that matches user's pattern.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SymbolItem {
+  name: String,
+  kind: SymbolKind,
+  path: String,
+  rel_path: String, // path relative to the search directory
+  ext: Extension,
+  line: u64,
+  column: usize // byte offset of the name in the line
+}
+
+impl SymbolItem {
+  /// Creates a new symbol item.
+  pub fn new(
+    name: String,
+    kind: SymbolKind,
+    path: String,
+    rel_path: String,
+    ext: Extension,
+    line: u64,
+    column: usize
+  ) -> Self {
+    Self { name, kind, path, rel_path, ext, line, column }
+  }
+}
+
+/// Result of the symbol search, symbols are ranked by how well name matches the pattern.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SymbolsResult {
+  time_sec: f64, // time taken to execute query
+  txid: usize, // txid of the index tree used for search
+  symbols: Vec<SymbolItem>,
+  symbol_matches: Matched
+}
+
+impl SymbolsResult {
+  /// Creates a new symbols result.
+  pub fn new(
+    time_sec: f64,
+    txid: usize,
+    symbols: Vec<SymbolItem>,
+    symbol_matches: Matched
+  ) -> Self {
+    Self { time_sec, txid, symbols, symbol_matches }
+  }
+}
+
+/// Matched line before and after replacement, line terminator is not included.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ReplaceLine {
+  num: u64,
+  before: String,
+  after: String
+}
+
+impl ReplaceLine {
+  /// Creates a new replace line.
+  pub fn new(num: u64, before: String, after: String) -> Self {
+    Self { num, before, after }
+  }
+}
+
+/// File with replacement preview, hash is checked when replacements are applied.
+/// Replacements of dirty files can not be applied until the unsaved buffer is saved.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ReplaceItem {
+  path: String,
+  rel_path: String, // path relative to the search directory
+  ext: Extension,
+  hash: String, // hash of the file content at the time of preview
+  lines: Vec<ReplaceLine>,
+  dirty: bool // whether or not lines come from the unsaved buffer
+}
+
+impl ReplaceItem {
+  /// Creates a new replace item.
+  pub fn new(
+    path: String,
+    rel_path: String,
+    ext: Extension,
+    hash: String,
+    lines: Vec<ReplaceLine>,
+    dirty: bool
+  ) -> Self {
+    Self { path, rel_path, ext, hash, lines, dirty }
+  }
+
+  /// Returns path of the file.
+  pub fn path(&self) -> &str {
+    &self.path
+  }
+}
+
+/// Result of the replacement preview.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ReplaceResult {
+  time_sec: f64, // time taken to execute query
+  files: Vec<ReplaceItem>,
+  num_replacements: usize
+}
+
+impl ReplaceResult {
+  /// Creates a new replace result.
+  pub fn new(time_sec: f64, files: Vec<ReplaceItem>, num_replacements: usize) -> Self {
+    Self { time_sec, files, num_replacements }
+  }
+}
+
+/// File that was updated when applying replacements.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AppliedItem {
+  path: String,
+  num_replacements: usize
+}
+
+impl AppliedItem {
+  /// Creates a new applied item.
+  pub fn new(path: String, num_replacements: usize) -> Self {
+    Self { path, num_replacements }
+  }
+}
+
+/// Result of applying replacements.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ApplyResult {
+  time_sec: f64, // time taken to apply replacements
+  files: Vec<AppliedItem>,
+  skipped: Vec<String> // selected files with unsaved buffers, they are not modified
+}
+
+impl ApplyResult {
+  /// Creates a new apply result.
+  pub fn new(time_sec: f64, files: Vec<AppliedItem>, skipped: Vec<String>) -> Self {
+    Self { time_sec, files, skipped }
+  }
+}
+
+/// Matched line in the history search, line terminator is not included.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HistoryLine {
+  num: u64,
+  line: String
+}
+
+impl HistoryLine {
+  /// Creates a new history line.
+  pub fn new(num: u64, line: String) -> Self {
+    Self { num, line }
+  }
+}
+
+/// File changed in the commit with matched lines that were added or removed.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HistoryFile {
+  path: String,
+  rel_path: String, // path relative to the search directory
+  ext: Extension,
+  added: Vec<HistoryLine>, // line numbers in the new revision
+  removed: Vec<HistoryLine> // line numbers in the parent revision
+}
+
+impl HistoryFile {
+  /// Creates a new history file.
+  pub fn new(
+    path: String,
+    rel_path: String,
+    ext: Extension,
+    added: Vec<HistoryLine>,
+    removed: Vec<HistoryLine>
+  ) -> Self {
+    Self { path, rel_path, ext, added, removed }
+  }
+}
+
+/// Commit that introduced or removed matching lines.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HistoryCommit {
+  id: String,
+  summary: String,
+  author: String,
+  time: i64, // commit time in seconds since epoch
+  files: Vec<HistoryFile>
+}
+
+impl HistoryCommit {
+  /// Creates a new history commit.
+  pub fn new(
+    id: String,
+    summary: String,
+    author: String,
+    time: i64,
+    files: Vec<HistoryFile>
+  ) -> Self {
+    Self { id, summary, author, time, files }
+  }
+}
+
+/// Result of the history search.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HistoryResult {
+  time_sec: f64, // time taken to execute query
+  num_commits: usize, // number of scanned commits
+  commits: Vec<HistoryCommit>
+}
+
+impl HistoryResult {
+  /// Creates a new history result.
+  pub fn new(time_sec: f64, num_commits: usize, commits: Vec<HistoryCommit>) -> Self {
+    Self { time_sec, num_commits, commits }
   }
 }
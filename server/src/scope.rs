@@ -0,0 +1,124 @@
+//! Git-aware search scopes.
+//! Scope restricts search to the files git reports as modified, staged, untracked or changed
+//! since a revision. The repository is discovered from the search directory and read locally.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::path::Path;
+use std::str;
+
+use errors;
+use git2::{Delta, DiffOptions, Repository, Status, StatusOptions};
+
+/// Search scope of the query.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Scope {
+  Modified, // tracked files changed in the working tree or the index
+  Staged, // files with changes in the index
+  Untracked, // files that are not tracked and not ignored
+  Diff(String) // files changed in the working tree since the revision
+}
+
+impl fmt::Display for Scope {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Scope::Modified => write!(f, "modified"),
+      Scope::Staged => write!(f, "staged"),
+      Scope::Untracked => write!(f, "untracked"),
+      Scope::Diff(rev) => write!(f, "diff:{}", rev)
+    }
+  }
+}
+
+impl str::FromStr for Scope {
+  type Err = errors::Error;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let s = s.trim();
+    if let Some(rev) = s.strip_prefix("diff:") {
+      let rev = rev.trim();
+      if rev.is_empty() {
+        return err!("Empty revision in scope {}, expected diff:<rev>", s);
+      }
+      return Ok(Scope::Diff(rev.to_owned()));
+    }
+    match s.to_lowercase().as_ref() {
+      "modified" => Ok(Scope::Modified),
+      "staged" => Ok(Scope::Staged),
+      "untracked" => Ok(Scope::Untracked),
+      _ => err!("Unknown scope {}, expected modified, staged, untracked or diff:<rev>", s)
+    }
+  }
+}
+
+/// Set of absolute file paths in the scope.
+#[derive(Clone, Debug)]
+pub struct ScopeFiles {
+  files: HashSet<String>
+}
+
+impl ScopeFiles {
+  /// Returns true if file is in the scope.
+  #[inline]
+  pub fn contains(&self, path: &str) -> bool {
+    self.files.contains(path)
+  }
+}
+
+// Returns status flags that belong to the scope.
+fn status_flags(scope: &Scope) -> Status {
+  let staged = Status::INDEX_NEW | Status::INDEX_MODIFIED | Status::INDEX_RENAMED |
+    Status::INDEX_TYPECHANGE;
+  match scope {
+    Scope::Modified => {
+      staged | Status::WT_MODIFIED | Status::WT_RENAMED | Status::WT_TYPECHANGE
+    },
+    Scope::Staged => staged,
+    Scope::Untracked => Status::WT_NEW,
+    Scope::Diff(_) => Status::empty()
+  }
+}
+
+/// Resolves files in the scope for the repository that contains the directory.
+/// Deleted files are not included, since there is nothing to search.
+pub fn resolve(dir: &Path, scope: &Scope) -> Result<ScopeFiles, errors::Error> {
+  let repo = Repository::discover(dir)?;
+  let workdir = match repo.workdir() {
+    Some(workdir) => workdir.canonicalize()?,
+    None => return err!("Repository for {} has no working directory", dir.display())
+  };
+
+  let mut relative = Vec::new();
+  match scope {
+    Scope::Diff(rev) => {
+      let tree = repo.revparse_single(rev)?.peel_to_tree()?;
+      let mut opts = DiffOptions::new();
+      opts.include_untracked(false);
+      let diff = repo.diff_tree_to_workdir_with_index(Some(&tree), Some(&mut opts))?;
+      for delta in diff.deltas().filter(|delta| delta.status() != Delta::Deleted) {
+        if let Some(path) = delta.new_file().path() {
+          relative.push(path.to_path_buf());
+        }
+      }
+    },
+    _ => {
+      let flags = status_flags(scope);
+      let mut opts = StatusOptions::new();
+      opts.include_untracked(*scope == Scope::Untracked)
+        .recurse_untracked_dirs(true)
+        .include_ignored(false);
+      for entry in repo.statuses(Some(&mut opts))?.iter() {
+        if entry.status().intersects(flags) && !entry.status().is_wt_deleted() {
+          if let Some(path) = entry.path() {
+            relative.push(Path::new(path).to_path_buf());
+          }
+        }
+      }
+    }
+  }
+
+  let files = relative.into_iter()
+    .filter_map(|path| workdir.join(path).to_str().map(|path| path.to_owned()))
+    .collect::<HashSet<_>>();
+  Ok(ScopeFiles { files })
+}
@@ -1,4 +1,6 @@
-use std::path::Path;
+use std::cmp;
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::sync::{mpsc, Arc};
 use std::str::from_utf8;
 use std::sync::atomic::{AtomicUsize, Ordering};
@@ -6,21 +8,120 @@ use std::thread;
 use std::time;
 
 use cache2;
+use config::{self, ProjectConfig};
+use archive;
+use encoding;
+use encoding_rs::{Encoding, UTF_8};
 use errors;
 use ext::{Extension, Extensions};
 use grep::matcher::{Match, Matcher, NoCaptures};
 use grep::regex::{RegexMatcher, RegexMatcherBuilder};
 use grep::searcher::*;
 use ignore::{WalkBuilder, WalkState};
-use params;
+use params::{self, BinaryPolicy, CaseMode, QueryParams, SearchMode, SortOrder};
+use rank;
+use references;
+use regex;
 use result::*;
+use scope::{self, ScopeFiles};
 
 // Maximum number of files we collect.
 pub const FILE_MAX_MATCHES: usize = 10;
 // Maximum number of matches we collect.
 pub const CONTENT_MAX_MATCHES: usize = 100;
+// Maximum number of files and matches we collect before sorting or ranking.
+pub const SORTED_MAX_MATCHES: usize = 10_000;
 // Number of lines of context ot fetch.
 const CONTEXT_NUM_LINES: usize = 2;
+/// Name of the project-specific ignore file, it is honoured even if ignored files are included.
+pub const IGNORE_FILE: &str = ".omnisearchignore";
+
+/// Filters applied when walking a directory.
+/// Search and cache refresh use the same filters, so that both see the same set of files.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct WalkFilters {
+  hidden: bool, // include hidden files and directories
+  ignored: bool // include files excluded by `.gitignore`, `.ignore` and git excludes
+}
+
+impl WalkFilters {
+  /// Creates walk filters.
+  pub fn new(hidden: bool, ignored: bool) -> Self {
+    Self { hidden, ignored }
+  }
+
+  /// Returns walk builder for the root directory with the filters applied.
+  pub fn walk_builder(&self, root: &Path, follow_links: bool) -> WalkBuilder {
+    let mut builder = WalkBuilder::new(root);
+    builder
+      .follow_links(follow_links)
+      .standard_filters(true)
+      .hidden(!self.hidden)
+      .ignore(!self.ignored)
+      .git_ignore(!self.ignored)
+      .git_global(!self.ignored)
+      .git_exclude(!self.ignored)
+      .same_file_system(true)
+      .add_custom_ignore_filename(IGNORE_FILE);
+    // Git directory is hidden, it is never searched even if hidden files are included.
+    if self.hidden {
+      builder.filter_entry(|entry| entry.file_name() != ".git");
+    }
+    builder
+  }
+}
+
+/// Limits on the number of file and content matches collected by search.
+/// Search stops once both limits are exceeded.
+#[derive(Clone, Copy, Debug)]
+pub struct Limits {
+  files: usize,
+  content: usize,
+  sorted: usize
+}
+
+impl Limits {
+  /// Creates limits with max number of file and content matches.
+  pub fn new(files: usize, content: usize) -> Self {
+    Self { files, content, sorted: SORTED_MAX_MATCHES }
+  }
+
+  /// Sets max number of file and content matches collected before sorting or ranking.
+  pub fn with_sorted(mut self, sorted: usize) -> Self {
+    self.sorted = sorted;
+    self
+  }
+
+  /// Limits used to collect matches before sorting or ranking, both limits are set to the
+  /// sorted limit.
+  pub fn for_sort(&self) -> Self {
+    Self { files: self.sorted, content: self.sorted, sorted: self.sorted }
+  }
+
+  /// Max number of file matches.
+  #[inline]
+  pub fn files(&self) -> usize {
+    self.files
+  }
+
+  /// Max number of content matches (lines).
+  #[inline]
+  pub fn content(&self) -> usize {
+    self.content
+  }
+
+  /// Max number of file and content matches collected before sorting or ranking.
+  #[inline]
+  pub fn sorted(&self) -> usize {
+    self.sorted
+  }
+}
+
+impl Default for Limits {
+  fn default() -> Self {
+    Self::new(FILE_MAX_MATCHES, CONTENT_MAX_MATCHES)
+  }
+}
 
 // Direct matcher to match as substring.
 #[derive(Clone, Debug)]
@@ -31,7 +132,8 @@ pub struct DirectMatcher {
 }
 
 impl DirectMatcher {
-  // Creates new direct matcher
+  // Creates new direct matcher, case is only ignored for ASCII patterns, non-ASCII patterns
+  // are matched as is.
   pub fn new(pattern: &str, case_smart: bool) -> Self {
     let is_ascii = pattern.is_ascii();
     // if case_smart is enabled, we need to check pattern to match like that.
@@ -39,13 +141,47 @@ impl DirectMatcher {
     let match_lowercase = case_smart &&
       pattern.chars().all(|c| c.is_lowercase() || c.is_ascii_punctuation());
     Self {
-      is_ascii: is_ascii,
-      match_lowercase: match_lowercase,
+      is_ascii,
+      match_lowercase,
       pattern: pattern.as_bytes().to_vec()
     }
   }
 }
 
+impl DirectMatcher {
+  // Creates direct matcher for the case mode, insensitive matcher compares lowercase pattern
+  // with the haystack ignoring case.
+  // Returns None if non-ASCII pattern needs to ignore case, Unicode case folding is left to
+  // the regex matcher.
+  pub fn with_case(pattern: &str, case: CaseMode) -> Option<Self> {
+    let matcher = match case {
+      CaseMode::Smart => Self::new(pattern, true),
+      CaseMode::Sensitive => Self::new(pattern, false),
+      CaseMode::Insensitive => {
+        let pattern = pattern.to_lowercase();
+        Self {
+          is_ascii: pattern.is_ascii(),
+          match_lowercase: true,
+          pattern: pattern.into_bytes()
+        }
+      }
+    };
+    if matcher.match_lowercase && !matcher.is_ascii { None } else { Some(matcher) }
+  }
+
+  // Returns substring pattern.
+  pub fn pattern(&self) -> &str {
+    // Pattern is always created from a valid string.
+    from_utf8(&self.pattern).unwrap_or("")
+  }
+
+  // Returns true if matcher ignores letter case, i.e. matching lowercase haystack returns
+  // the same result.
+  pub fn ignores_case(&self) -> bool {
+    self.match_lowercase && self.is_ascii
+  }
+}
+
 impl Matcher for DirectMatcher {
   type Captures = NoCaptures;
   type Error = errors::Error;
@@ -57,23 +193,15 @@ impl Matcher for DirectMatcher {
       return Ok(None);
     }
 
-    if self.match_lowercase {
-      if self.is_ascii {
-        for i in 0..hlen - plen + 1 {
-          if self.pattern[..].eq_ignore_ascii_case(&haystack[i..i + plen]) {
-            return Ok(Some(Match::new(i, i + plen)))
-          }
-        }
-      } else {
-        let p = from_utf8(&self.pattern)?;
-        let h = from_utf8(haystack)?;
-        if let Some(start_pos) = h.find(p) {
-          return Ok(Some(Match::new(start_pos, start_pos + plen)));
+    if self.ignores_case() {
+      for i in 0..hlen - plen + 1 {
+        if self.pattern[..].eq_ignore_ascii_case(&haystack[i..i + plen]) {
+          return Ok(Some(Match::new(i, i + plen)))
         }
       }
     } else {
       for i in 0..hlen - plen + 1 {
-        if &self.pattern[..] == &haystack[i..i + plen] {
+        if self.pattern[..] == haystack[i..i + plen] {
           return Ok(Some(Match::new(i, i + plen)))
         }
       }
@@ -94,12 +222,12 @@ pub struct MatcherSpec {
 
 impl MatcherSpec {
   // Creates regex matcher.
-  fn regex(matcher: RegexMatcher) -> Self {
+  pub fn regex(matcher: RegexMatcher) -> Self {
     Self { direct: None, regex: Some(matcher) }
   }
 
   // Creates direct matcher.
-  fn direct(matcher: DirectMatcher) -> Self {
+  pub fn direct(matcher: DirectMatcher) -> Self {
     Self { direct: Some(matcher), regex: None }
   }
 
@@ -115,6 +243,12 @@ impl MatcherSpec {
     self.regex.unwrap()
   }
 
+  // Returns reference to DirectMatcher, or None if regex is set.
+  #[inline]
+  pub fn direct_ref(&self) -> Option<&DirectMatcher> {
+    self.direct.as_ref()
+  }
+
   // Converts spec into DirectMatcher.
   #[inline]
   pub fn as_direct(self) -> DirectMatcher {
@@ -142,13 +276,32 @@ impl MatcherSpec {
   }
 }
 
+// Returns path relative to the root, or the path itself if it is not under the root.
+pub fn relative_path<'a>(root: &Path, path: &'a str) -> &'a str {
+  Path::new(path).strip_prefix(root).ok().and_then(|rel| rel.to_str()).unwrap_or(path)
+}
+
+// Returns part of the path to match file pattern against: either relative path or file name.
+#[inline]
+pub fn file_match_target<'a>(root: &Path, path: &'a str, match_path: bool) -> &'a str {
+  if match_path {
+    relative_path(root, path)
+  } else {
+    Path::new(path).file_name().and_then(|os| os.to_str()).unwrap_or("")
+  }
+}
+
 // Sink implementation for search.
 #[derive(Clone)]
 pub struct Collector {
   sx: mpsc::Sender<ContentItem>,
   counter: Arc<AtomicUsize>,
+  limit: usize,
   path: String,
   ext: Extension,
+  dirty: bool,
+  binary: bool, // set once binary data is detected
+  encoding: &'static Encoding, // encoding of the content, UTF-8 by default
   lines: Vec<ContentLine>,
   matches: Vec<ContentMatch>,
   // Used to find location of the match
@@ -160,21 +313,34 @@ impl Collector {
   pub fn new(
     sx: mpsc::Sender<ContentItem>,
     counter: Arc<AtomicUsize>,
+    limit: usize,
     path: String,
     spec: MatcherSpec,
-    ext: Extension
+    ext: Extension,
+    dirty: bool
   ) -> Self {
     Self {
-      sx: sx,
-      counter: counter,
-      path: path,
-      ext: ext,
+      sx,
+      counter,
+      limit,
+      path,
+      ext,
+      dirty,
+      binary: false,
+      encoding: UTF_8,
       lines: Vec::with_capacity(32),
       matches: Vec::with_capacity(32),
-      spec: spec
+      spec
     }
   }
 
+  /// Sets encoding reported with the matches, encoding is detected once per file before the
+  /// file is searched, see `encoding::detect` and `encoding::detect_file`.
+  pub fn with_encoding(mut self, encoding: &'static Encoding) -> Self {
+    self.encoding = encoding;
+    self
+  }
+
   #[inline]
   fn flush_lines(&mut self) {
     let mut match_lines = Vec::with_capacity(self.lines.len());
@@ -226,33 +392,315 @@ impl Sink for Collector {
     }
   }
 
+  fn binary_data(&mut self, searcher: &Searcher, _: u64) -> Result<bool, Self::Error> {
+    self.binary = true;
+    // Search stops for skipped binary files and continues if matches are reported.
+    Ok(searcher.binary_detection().quit_byte().is_none())
+  }
+
   fn context_break(&mut self, _: &Searcher) -> Result<bool, Self::Error> {
-    if self.counter.load(Ordering::Relaxed) > CONTENT_MAX_MATCHES {
+    if self.counter.load(Ordering::Relaxed) > self.limit {
       return Ok(false);
     }
-    if self.lines.len() > 0 {
+    if !self.lines.is_empty() {
       self.flush_lines();
     }
     Ok(true)
   }
 
-  fn finish(&mut self, _: &Searcher, _: &SinkFinish) -> Result<(), Self::Error> {
-    if self.lines.len() > 0 {
+  fn finish(&mut self, searcher: &Searcher, _: &SinkFinish) -> Result<(), Self::Error> {
+    if !self.lines.is_empty() {
       self.flush_lines();
     }
-    if self.matches.len() > 0 {
+    if self.binary {
+      // Lines of binary data are not meaningful, only the fact that file matches is reported.
+      if searcher.binary_detection().quit_byte().is_none() && !self.matches.is_empty() {
+        let item = ContentItem::new(self.path.clone(), self.ext, Vec::new(), self.dirty, true,
+          self.encoding.name().to_owned());
+        self.sx.send(item)?;
+      }
+      return Ok(());
+    }
+    if !self.matches.is_empty() {
       let mut matches = Vec::with_capacity(self.matches.len());
       while let Some(mat) = self.matches.pop() {
         matches.push(mat);
       }
       // Make sure matches are in order from top to bottom of the file
       matches.reverse();
-      self.sx.send(ContentItem::new(self.path.clone(), self.ext, matches))?;
+      let item = ContentItem::new(self.path.clone(), self.ext, matches, self.dirty, false,
+        self.encoding.name().to_owned());
+      self.sx.send(item)?;
     }
     Ok(())
   }
 }
 
+/// Returns binary detection of the searcher for the policy.
+/// NUL byte is used to detect binary data, skipped files stop searching as soon as it is found,
+/// reported files continue to be searched to find out if they match.
+pub fn binary_detection(policy: BinaryPolicy) -> BinaryDetection {
+  match policy {
+    BinaryPolicy::Skip => BinaryDetection::quit(b'\x00'),
+    BinaryPolicy::Report => BinaryDetection::convert(b'\x00'),
+    BinaryPolicy::Include => BinaryDetection::none()
+  }
+}
+
+/// Builds regex matcher for the pattern with the case mode, literal patterns are escaped so
+/// that they are matched as substrings.
+pub fn build_regex_matcher(
+  pattern: &str,
+  use_regex: bool,
+  case: CaseMode
+) -> Result<RegexMatcher, errors::Error> {
+  if pattern.is_empty() {
+    return err!("Empty pattern, expected a valid search word or regular expression");
+  }
+  let pattern = if use_regex { pattern.to_owned() } else { regex::escape(pattern) };
+  let matcher = RegexMatcherBuilder::new()
+    .line_terminator(Some(b'\n'))
+    .multi_line(false)
+    .case_smart(case == CaseMode::Smart)
+    .case_insensitive(case == CaseMode::Insensitive)
+    .build(&pattern)?;
+  Ok(matcher)
+}
+
+// Builds matcher for the literal pattern, direct matcher is used unless the pattern needs
+// Unicode case folding.
+fn literal_matcher(pattern: &str, case: CaseMode) -> Result<MatcherSpec, errors::Error> {
+  match DirectMatcher::with_case(pattern, case) {
+    Some(direct) => Ok(MatcherSpec::direct(direct)),
+    None => Ok(MatcherSpec::regex(build_regex_matcher(pattern, false, case)?))
+  }
+}
+
+/// Builds regex matcher of the query, query case mode takes precedence over the case mode of
+/// the project. Used whenever capture groups or a regex engine are required.
+pub fn query_matcher(
+  params: &QueryParams,
+  config: &ProjectConfig
+) -> Result<RegexMatcher, errors::Error> {
+  match params.mode() {
+    SearchMode::References => references::matcher(params.pattern()),
+    SearchMode::Text => {
+      let case = params.case().unwrap_or(config.case());
+      build_regex_matcher(params.pattern(), params.use_regex(), case)
+    }
+  }
+}
+
+/// Searches content in memory with the matcher, errors are reported as no matches.
+pub fn search_slice(
+  searcher: &mut Searcher,
+  spec: &MatcherSpec,
+  content: &[u8],
+  collector: Collector
+) {
+  let _ = if spec.is_regex() {
+    searcher.search_slice(spec.clone().as_regex(), content, collector)
+  } else {
+    searcher.search_slice(spec.clone().as_direct(), content, collector)
+  };
+}
+
+/// Searches unsaved buffer with the matcher, errors are reported as no matches.
+/// Buffers are UTF-8 text, so they are searched without the encoding of the searcher.
+pub fn search_buffer(
+  searcher: &Searcher,
+  spec: &MatcherSpec,
+  content: &[u8],
+  collector: Collector
+) {
+  let mut searcher = SearcherBuilder::new()
+    .binary_detection(searcher.binary_detection().clone())
+    .line_number(searcher.line_number())
+    .before_context(searcher.before_context())
+    .after_context(searcher.after_context())
+    .multi_line(searcher.multi_line())
+    .build();
+  search_slice(&mut searcher, spec, content, collector);
+}
+
+/// Searches file on disk with the matcher, errors are reported as no matches, e.g. when file
+/// was removed after it was listed or cannot be read.
+pub fn search_path(
+  searcher: &mut Searcher,
+  spec: &MatcherSpec,
+  path: &Path,
+  collector: Collector
+) {
+  let _ = if spec.is_regex() {
+    searcher.search_path(spec.clone().as_regex(), path, collector)
+  } else {
+    searcher.search_path(spec.clone().as_direct(), path, collector)
+  };
+}
+
+/// Options of the search, built once from the query and shared by all search threads.
+/// Query settings take precedence over the settings of the project.
+pub struct SearchOptions {
+  searcher: SearcherBuilder,
+  matcher: MatcherSpec,
+  root: PathBuf,
+  match_path: bool,
+  ext_check: Extensions,
+  limits: Limits,
+  buffers: Arc<cache2::Buffers>,
+  scope: Option<ScopeFiles>,
+  archives: bool,
+  default_encoding: Option<&'static Encoding>,
+  config: Arc<ProjectConfig>,
+  filters: WalkFilters,
+  walk: WalkBuilder
+}
+
+impl SearchOptions {
+  /// Creates options of the query for the root directory.
+  pub fn new(
+    cache: &cache2::SharedCache,
+    params: &QueryParams,
+    root: &Path,
+    config: Arc<ProjectConfig>
+  ) -> Result<Self, errors::Error> {
+    // References search looks for whole word identifier in supported languages only.
+    let is_references = params.mode() == SearchMode::References;
+
+    // Set of extensions to check against.
+    let ext_check = if is_references { references::extensions() } else { Extensions::all() };
+
+    // Query encoding takes precedence over the encoding of the project.
+    let default_encoding = match params.encoding() {
+      Some(label) => Some(encoding::for_label(label)?),
+      None => cache2::policy(cache, root)?.encoding()
+    };
+
+    let mut searcher = SearcherBuilder::new();
+    searcher
+      .binary_detection(binary_detection(params.binary()))
+      .encoding(encoding::searcher_encoding(default_encoding))
+      .line_number(true)
+      .before_context(CONTEXT_NUM_LINES)
+      .after_context(CONTEXT_NUM_LINES)
+      .multi_line(false);
+
+    // Query case mode takes precedence over the case mode of the project.
+    let case = params.case().unwrap_or(config.case());
+    let matcher = if is_references || params.use_regex() {
+      MatcherSpec::regex(query_matcher(params, &config)?)
+    } else {
+      literal_matcher(params.pattern(), case)?
+    };
+
+    // Sorted and ranked search collect a pool of matches up to the sorted limit and truncate
+    // it after ordering, so results do not depend on the order in which threads visit files
+    // as long as all matches fit.
+    let limits = config.limits().for_sort();
+
+    // Unsaved buffers are searched instead of the file content on disk or in cache, they do
+    // not apply to revisions.
+    let buffers = match params.rev() {
+      Some(_) => Arc::new(cache2::Buffers::new()),
+      None => cache2::buffers(cache)?
+    };
+    // Git scope restricts both file and content matches to the files reported by git.
+    let scope = match params.scope()? {
+      Some(scope) => Some(scope::resolve(root, &scope)?),
+      None => None
+    };
+
+    let filters = WalkFilters::new(params.include_hidden(), params.include_ignored());
+    let walk = cache2::walk_builder(cache, root, filters, &config)?;
+
+    Ok(Self {
+      searcher,
+      matcher,
+      root: root.to_path_buf(),
+      match_path: params.match_path(),
+      ext_check,
+      limits,
+      buffers,
+      scope,
+      archives: params.search_archives(),
+      default_encoding,
+      config,
+      filters,
+      walk
+    })
+  }
+
+  /// Returns a new searcher, each search thread needs its own searcher.
+  pub fn searcher(&self) -> Searcher {
+    self.searcher.build()
+  }
+
+  /// Returns matcher of both file names and content.
+  pub fn matcher(&self) -> &MatcherSpec {
+    &self.matcher
+  }
+
+  /// Returns root directory of the search.
+  pub fn root(&self) -> &Path {
+    &self.root
+  }
+
+  /// Whether or not to match file pattern against path relative to the root directory.
+  pub fn match_path(&self) -> bool {
+    self.match_path
+  }
+
+  /// Returns extensions of files to search content of.
+  pub fn ext_check(&self) -> &Extensions {
+    &self.ext_check
+  }
+
+  /// Returns limits on the number of collected matches.
+  pub fn limits(&self) -> Limits {
+    self.limits
+  }
+
+  /// Returns unsaved buffers to search instead of the file content.
+  pub fn buffers(&self) -> &cache2::Buffers {
+    &self.buffers
+  }
+
+  /// Returns true if path is in the git scope, all paths are in scope if scope is not set.
+  pub fn in_scope(&self, path: &str) -> bool {
+    self.scope.as_ref().map(|scope| scope.contains(path)).unwrap_or(true)
+  }
+
+  /// Returns true if search is restricted to the git scope.
+  pub fn has_scope(&self) -> bool {
+    self.scope.is_some()
+  }
+
+  /// Whether or not to search inside archives and compressed files.
+  pub fn archives(&self) -> bool {
+    self.archives
+  }
+
+  /// Returns encoding of files without byte order mark.
+  pub fn default_encoding(&self) -> Option<&'static Encoding> {
+    self.default_encoding
+  }
+
+  /// Returns project config.
+  pub fn config(&self) -> &ProjectConfig {
+    &self.config
+  }
+
+  /// Returns filters of the directory walk.
+  pub fn filters(&self) -> WalkFilters {
+    self.filters
+  }
+
+  /// Returns walk builder of the root directory, see `cache2::walk_builder`.
+  pub fn walk(&self) -> &WalkBuilder {
+    &self.walk
+  }
+}
+
 // Perform search within provided directory using provided pattern
 pub fn find(
   cache: &cache2::SharedCache,
@@ -266,33 +714,28 @@ pub fn find(
     return err!("Path {} is not a directory", path.to_str().unwrap_or(""));
   }
 
-  if params.pattern().len() == 0 {
+  if params.pattern().is_empty() {
     return err!("Empty pattern, expected a valid search word or regular expression");
   }
 
-  // Check if we can use cache
-  let use_cache = params.use_cache() && cache2::contains_cache(cache, path)?;
+  let config = Arc::new(config::load(path)?);
 
-  // Set of extensions to check against.
-  let ext_check = Extensions::all();
-
-  let searcher = SearcherBuilder::new()
-    .line_number(true)
-    .before_context(CONTEXT_NUM_LINES)
-    .after_context(CONTEXT_NUM_LINES)
-    .multi_line(false)
-    .build();
+  // References search classifies matches once search is finished.
+  let is_references = params.mode() == SearchMode::References;
+  let opts = Arc::new(SearchOptions::new(cache, &params, path, config.clone())?);
 
-  let content_matcher = if params.use_regex() {
-    MatcherSpec::regex(
-      RegexMatcherBuilder::new()
-        .line_terminator(Some(b'\n'))
-        .multi_line(false)
-        .case_smart(true)
-        .build(params.pattern())?
-    )
-  } else {
-    MatcherSpec::direct(DirectMatcher::new(params.pattern(), true))
+  // Check if we can use cache, pin snapshot of the index for the duration of the search.
+  // Revisions are only searched in cache, since they are not checked out. Cache is only used
+  // if it was built with the same walk filters, otherwise the directory is walked instead.
+  let snapshot = match params.rev() {
+    Some(rev) => match cache2::snapshot(cache, &cache2::index_path(path, Some(rev)))? {
+      Some(snapshot) => Some(snapshot),
+      None => return err!("Revision {} of {} is not cached", rev, path.to_str().unwrap_or(""))
+    },
+    None if params.use_cache() && cache2::policy(cache, path)?.filters() == opts.filters() => {
+      cache2::snapshot(cache, path)?
+    },
+    None => None
   };
 
   let (fsx, frx) = mpsc::channel::<FileItem>();
@@ -317,26 +760,43 @@ pub fn find(
   let content_counter = Arc::new(AtomicUsize::new(0));
   let file_counter = Arc::new(AtomicUsize::new(0));
 
-  if use_cache {
-    cache2::search(cache, searcher, content_matcher, path, ext_check,
-      file_counter, content_counter, &fsx, &csx)?;
+  if let Some(ref index) = snapshot {
+    cache2::search(index, &opts, file_counter, content_counter, &fsx, &csx)?;
   } else {
-    search(searcher, content_matcher, path, ext_check,
-      file_counter, content_counter, &fsx, &csx);
+    search(&opts, file_counter, content_counter, &fsx, &csx);
   }
 
   drop(fsx);
-  let files = files_thread.join().unwrap();
+  let mut files = files_thread.join().unwrap();
   drop(csx);
-  let content = content_thread.join().unwrap();
+  let mut content = content_thread.join().unwrap();
+  if is_references {
+    references::classify_content(params.pattern(), &mut content)?;
+  }
+
+  // Matches beyond the sorted limit were not collected, so counts are only lower bounds.
+  let num_lines = content.iter().map(|item| item.num_matched_lines()).sum::<usize>();
+  let is_capped = files.len() > opts.limits().files() || num_lines > opts.limits().content();
+  match params.sort() {
+    Some(order) => {
+      sort_files(order, &mut files);
+      sort_content(order, &mut content);
+    },
+    None => {
+      // File items are not ranked, they are ordered by path to be truncated the same way.
+      sort_files(SortOrder::Path, &mut files);
+      rank::rank_content(path, opts.matcher(), config.penalties(), &mut content);
+    }
+  }
+  truncate_results(config.limits(), &mut files, &mut content);
 
-  let file_matches = if files.len() <= FILE_MAX_MATCHES {
+  let file_matches = if !is_capped && files.len() <= config.limits().files() {
     Matched::Exact(files.len())
   } else {
     Matched::AtLeast(files.len())
   };
 
-  let content_matches = if content.len() <= CONTENT_MAX_MATCHES {
+  let content_matches = if !is_capped && content.len() <= config.limits().content() {
     Matched::Exact(content.len())
   } else {
     Matched::AtLeast(content.len())
@@ -347,7 +807,8 @@ pub fn find(
 
   Ok(SearchResult::new(
     exec_time,
-    use_cache,
+    snapshot.is_some(),
+    snapshot.map(|index| index.txid()),
     files,
     file_matches,
     content,
@@ -355,30 +816,65 @@ pub fn find(
   ))
 }
 
+// Returns modification time of the file, or None if metadata is not available.
+fn mtime(path: &str) -> Option<time::SystemTime> {
+  fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+// Sorts file items, files have no match count, so they are sorted by path instead.
+fn sort_files(order: SortOrder, files: &mut [FileItem]) {
+  match order {
+    SortOrder::Mtime => {
+      files.sort_by_cached_key(|item| (cmp::Reverse(mtime(item.path())), item.path().to_owned()))
+    },
+    SortOrder::Path | SortOrder::Matches => files.sort_by(|a, b| a.path().cmp(b.path()))
+  }
+}
+
+// Sorts content items, ties are resolved by path.
+fn sort_content(order: SortOrder, content: &mut [ContentItem]) {
+  match order {
+    SortOrder::Path => content.sort_by(|a, b| a.path().cmp(b.path())),
+    SortOrder::Mtime => {
+      content.sort_by_cached_key(|item| (cmp::Reverse(mtime(item.path())), item.path().to_owned()))
+    },
+    SortOrder::Matches => content.sort_by(|a, b| {
+      b.num_matched_lines().cmp(&a.num_matched_lines()).then_with(|| a.path().cmp(b.path()))
+    })
+  }
+}
+
+// Truncates sorted results the same way limits truncate unsorted search: file items up to
+// one over the limit, content items until the number of matched lines exceeds the limit.
+fn truncate_results(limits: Limits, files: &mut Vec<FileItem>, content: &mut Vec<ContentItem>) {
+  files.truncate(limits.files().saturating_add(1));
+  let mut num_lines = 0;
+  let mut len = 0;
+  while len < content.len() && num_lines <= limits.content() {
+    num_lines += content[len].num_matched_lines();
+    len += 1;
+  }
+  content.truncate(len);
+}
+
 // Internal function to start search.
 fn search(
-  searcher: Searcher,
-  content_matcher: MatcherSpec,
-  path: &Path,
-  ext_check: Extensions,
+  opts: &SearchOptions,
   file_counter: Arc<AtomicUsize>,
   content_counter: Arc<AtomicUsize>,
   fsx: &mpsc::Sender<FileItem>,
   csx: &mpsc::Sender<ContentItem>
 ) {
-  let walker = WalkBuilder::new(path)
-    .follow_links(false)
-    .standard_filters(true)
-    .same_file_system(true)
-    .build_parallel();
+  let path = opts.root();
+  let (limits, match_path) = (opts.limits(), opts.match_path());
+  let walker = opts.walk().build_parallel();
 
   walker.run(|| {
     let fsx = fsx.clone();
     let csx = csx.clone();
-    let mut searcher = searcher.clone();
-    let file_matcher = content_matcher.clone();
-    let content_matcher = content_matcher.clone();
-    let ext_check = ext_check.clone();
+    let mut searcher = opts.searcher();
+    let file_matcher = opts.matcher();
+    let content_matcher = opts.matcher();
 
     let file_counter = file_counter.clone();
     let content_counter = content_counter.clone();
@@ -386,48 +882,72 @@ fn search(
     Box::new(move |res| {
       if let Ok(inode) = res {
         let is_file = inode.file_type().map(|ftype| ftype.is_file()).unwrap_or(false);
-        if is_file && inode.path().to_str().is_some() {
-          // Path and name must exist at this point.
+        if is_file && inode.path().to_str().map(|fpath| opts.in_scope(fpath)).unwrap_or(false) {
+          // Path must exist at this point.
           let fpath = inode.path().to_str().unwrap();
-          let fname = inode.file_name().to_str().unwrap();
-          // It is okay to unwrap when parsing extension - always returns a valid enum.
-          let ext = inode.path().extension()
-            .and_then(|os| os.to_str())
-            .unwrap_or("")
-            .parse::<Extension>()
-            .unwrap();
+          let fname = file_match_target(path, fpath, match_path);
+          let ext = opts.config().mappings().detect(inode.path(), None);
 
           // Search if file name matches pattern.
-          if file_matcher.is_match(fname) {
-            if file_counter.fetch_add(1, Ordering::Relaxed) <= FILE_MAX_MATCHES {
-              let _ = fsx.send(FileItem::new(fpath.to_owned(), ext));
-            }
+          if file_matcher.is_match(fname) &&
+              file_counter.fetch_add(1, Ordering::Relaxed) <= limits.files() {
+            let rel_path = relative_path(path, fpath).to_owned();
+            let _ = fsx.send(FileItem::new(fpath.to_owned(), rel_path, ext));
           }
 
-          if ext_check.is_supported_extension(ext) {
-            if content_counter.load(Ordering::Relaxed) <= CONTENT_MAX_MATCHES {
-              let content_matcher = content_matcher.clone();
-              let collector = Collector::new(
-                csx.clone(),
-                content_counter.clone(),
-                fpath.to_owned(),
-                content_matcher.clone(),
-                ext
-              );
-              if content_matcher.is_regex() {
-                let matcher = content_matcher.as_regex();
-                searcher.search_path(matcher, inode.path(), collector).unwrap();
-              } else {
-                let matcher = content_matcher.as_direct();
-                searcher.search_path(matcher, inode.path(), collector).unwrap();
+          // Archives are expanded into entries that are searched instead of the raw bytes.
+          let format = if opts.archives() { archive::Format::from_path(fpath) } else { None };
+          if let Some(format) = format {
+            let _ = archive::for_each_entry(fpath, format, None, |entry| {
+              let fname = file_match_target(path, entry.path(), match_path);
+              if entry.path() != fpath && file_matcher.is_match(fname) &&
+                  file_counter.fetch_add(1, Ordering::Relaxed) <= limits.files() {
+                let rel_path = relative_path(path, entry.path()).to_owned();
+                let _ = fsx.send(FileItem::new(entry.path().to_owned(), rel_path, entry.ext()));
+              }
+              if opts.ext_check().is_supported_extension(entry.ext()) &&
+                  content_counter.load(Ordering::Relaxed) <= limits.content() {
+                let collector = Collector::new(
+                  csx.clone(),
+                  content_counter.clone(),
+                  limits.content(),
+                  entry.path().to_owned(),
+                  content_matcher.clone(),
+                  entry.ext(),
+                  false
+                ).with_encoding(encoding::detect(entry.content(), opts.default_encoding()));
+                search_slice(&mut searcher, content_matcher, entry.content(), collector);
+              }
+            });
+          } else if opts.ext_check().is_supported_extension(ext) &&
+              content_counter.load(Ordering::Relaxed) <= limits.content() {
+            let buffer = opts.buffers().get(fpath).map(|buffer| buffer.as_slice());
+            let collector = Collector::new(
+              csx.clone(),
+              content_counter.clone(),
+              limits.content(),
+              fpath.to_owned(),
+              content_matcher.clone(),
+              ext,
+              buffer.is_some()
+            );
+            match buffer {
+              Some(buffer) => {
+                let collector = collector.with_encoding(encoding::detect(buffer, None));
+                search_buffer(&searcher, content_matcher, buffer, collector)
+              },
+              None => {
+                let encoding = encoding::detect_file(fpath, opts.default_encoding());
+                let collector = collector.with_encoding(encoding);
+                search_path(&mut searcher, content_matcher, inode.path(), collector)
               }
             }
           }
         }
       }
 
-      if file_counter.load(Ordering::Relaxed) > FILE_MAX_MATCHES &&
-          content_counter.load(Ordering::Relaxed) > CONTENT_MAX_MATCHES {
+      if file_counter.load(Ordering::Relaxed) > limits.files() &&
+          content_counter.load(Ordering::Relaxed) > limits.content() {
         WalkState::Quit
       } else {
         WalkState::Continue
@@ -435,3 +955,113 @@ fn search(
     })
   });
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use json;
+  use testutil::TempDir;
+
+  // Searches content with the searcher and returns matched lines.
+  fn matched_lines(
+    spec: MatcherSpec,
+    content: &[u8],
+    search: fn(&mut Searcher, &MatcherSpec, &[u8], Collector)
+  ) -> Vec<String> {
+    let latin1 = encoding::for_label("latin1").unwrap();
+    let mut searcher = SearcherBuilder::new()
+      .encoding(encoding::searcher_encoding(Some(latin1)))
+      .line_number(true)
+      .build();
+    let (sx, rx) = mpsc::channel::<ContentItem>();
+    let collector = Collector::new(sx, Arc::new(AtomicUsize::new(0)), usize::MAX,
+      "buffer".to_owned(), spec.clone(), Extension::UNKNOWN, true);
+    search(&mut searcher, &spec, content, collector);
+    rx.try_iter()
+      .flat_map(|item| item.matches().to_vec())
+      .flat_map(|mat| mat.lines().to_vec())
+      .filter(|line| line.kind() == ContentKind::Match)
+      .map(|line| String::from_utf8_lossy(line.bytes()).into_owned())
+      .collect()
+  }
+
+  #[test]
+  fn test_search_buffer_ignores_searcher_encoding() {
+    let spec = MatcherSpec::regex(RegexMatcher::new("föo").unwrap());
+    let content = "föo\nbar\n".as_bytes();
+    assert_eq!(matched_lines(spec.clone(), content, |searcher, spec, content, collector| {
+      search_buffer(searcher, spec, content, collector)
+    }), vec!["föo\n"]);
+    // The same UTF-8 content is decoded as latin1 when searched as a file.
+    assert!(matched_lines(spec, content, search_slice).is_empty());
+  }
+
+  // Returns true if the literal matcher for the case mode matches the haystack.
+  fn is_literal_match(pattern: &str, case: CaseMode, haystack: &str) -> bool {
+    literal_matcher(pattern, case).unwrap().is_match(haystack)
+  }
+
+  #[test]
+  fn test_literal_matcher_ascii() {
+    assert!(is_literal_match("foo", CaseMode::Smart, "a FOO b"));
+    assert!(!is_literal_match("Foo", CaseMode::Smart, "a FOO b"));
+    assert!(is_literal_match("Foo", CaseMode::Smart, "a Foo b"));
+    assert!(!is_literal_match("foo", CaseMode::Sensitive, "a FOO b"));
+    assert!(is_literal_match("foo", CaseMode::Sensitive, "a foo b"));
+    assert!(is_literal_match("Foo", CaseMode::Insensitive, "a fOO b"));
+    assert!(literal_matcher("foo", CaseMode::Insensitive).unwrap().direct_ref().is_some());
+  }
+
+  #[test]
+  fn test_literal_matcher_non_ascii() {
+    assert!(is_literal_match("straße", CaseMode::Smart, "a STRAßE b"));
+    assert!(is_literal_match("émile", CaseMode::Smart, "a ÉMILE b"));
+    assert!(!is_literal_match("Émile", CaseMode::Smart, "a émile b"));
+    assert!(!is_literal_match("émile", CaseMode::Sensitive, "a ÉMILE b"));
+    assert!(is_literal_match("émile", CaseMode::Sensitive, "a émile b"));
+    assert!(is_literal_match("Émile", CaseMode::Insensitive, "a éMILE b"));
+    assert!(literal_matcher("Émile", CaseMode::Sensitive).unwrap().direct_ref().is_some());
+  }
+
+  // Returns paths of content items found in the directory, relative to the directory.
+  fn content_paths(dir: &TempDir, fields: json::Value) -> Vec<String> {
+    let mut query = json::json!({ "dir": dir.to_str().unwrap(), "pattern": "foo" });
+    for (key, field) in fields.as_object().unwrap() {
+      query[key] = field.clone();
+    }
+    let params = json::from_value::<QueryParams>(query).unwrap();
+    let res = json::to_value(find(&cache2::create_cache(), params).unwrap()).unwrap();
+    res["content"].as_array().unwrap().iter()
+      .map(|item| relative_path(dir, item["path"].as_str().unwrap()).to_owned())
+      .collect()
+  }
+
+  #[test]
+  fn test_find_ranks_candidate_pool() {
+    let dir = TempDir::new(&[(config::PROJECT_FILE, "[limits]\ncontent = 1\n")]);
+    for i in 0..20 {
+      dir.write(&format!("tests/t{}.rs", i), b"x = foo();\n");
+    }
+    dir.write("src/lib.rs", b"fn foo() {}\n");
+    // Definition is ranked first even if the walk reaches it after the content limit.
+    for _ in 0..5 {
+      let paths = content_paths(&dir, json::json!({}));
+      assert_eq!(paths, vec!["src/lib.rs", "tests/t0.rs"]);
+    }
+  }
+
+  #[test]
+  fn test_find_sorted_is_deterministic() {
+    let dir = TempDir::new(&[(config::PROJECT_FILE, "[limits]\ncontent = 5\n")]);
+    for i in 0..20 {
+      let content = "foo\n".repeat(i % 4 + 1);
+      dir.write(&format!("src/m{}.rs", i), content.as_bytes());
+    }
+    let fields = json::json!({ "sort": "matches" });
+    let first = content_paths(&dir, fields.clone());
+    assert_eq!(first, vec!["src/m11.rs", "src/m15.rs"]);
+    for _ in 0..5 {
+      assert_eq!(content_paths(&dir, fields.clone()), first);
+    }
+  }
+}
@@ -1,102 +1,135 @@
 use std::cmp;
 use std::collections::HashSet;
+use std::mem::size_of;
+
+use cache2::MemoryUsed;
 
 /// Represents an Edge in the Suffix Tree.
 /// It has a label and a destination node.
-#[derive(Clone, Debug)]
+///
+/// Label is a reference to the range of bytes of one of the keys stored in the tree buffer,
+/// so edges do not own any data. Positions are stored as `u32` to keep the edge small.
+#[derive(Clone, Copy, Debug)]
 pub struct Edge {
-  label: Vec<u8>,
-  dest_node_id: usize
+  key_id: u32,
+  start: u32,
+  end: u32,
+  dest_node_id: u32
 }
 
 impl Edge {
-  /// Creates a new edge.
-  pub fn new(label: Vec<u8>, dest_node_id: usize) -> Self {
-    Self { label, dest_node_id }
+  /// Creates a new edge with label `key[start..end]`.
+  pub fn new(key_id: usize, start: usize, end: usize, dest_node_id: usize) -> Self {
+    Self {
+      key_id: key_id as u32,
+      start: start as u32,
+      end: end as u32,
+      dest_node_id: dest_node_id as u32
+    }
   }
 
-  /// Sets label for the edge.
-  pub fn set_label(&mut self, label: Vec<u8>) {
-    self.label = label;
+  /// Returns destination node id.
+  pub fn get_dest_id(&self) -> usize {
+    self.dest_node_id as usize
   }
 
-  /// Sets label from slice.
-  pub fn set_label_from_slice(&mut self, label: &[u8]) {
-    self.label = label.to_vec();
+  /// Returns length of the edge label.
+  pub fn len(&self) -> usize {
+    (self.end - self.start) as usize
   }
 
-  /// Returns destination node id.
-  pub fn get_dest_id(&self) -> usize {
-    self.dest_node_id
+  /// Returns true if label is empty.
+  pub fn is_empty(&self) -> bool {
+    self.start == self.end
   }
 
-  /// Returns edge label.
-  pub fn get_label(&self) -> &[u8] {
-    &self.label
+  /// Returns edge with the first `len` bytes of the label that points to the new node.
+  fn prefix(&self, len: usize, dest_node_id: usize) -> Self {
+    Self {
+      key_id: self.key_id,
+      start: self.start,
+      end: self.start + len as u32,
+      dest_node_id: dest_node_id as u32
+    }
   }
 
-  /// Appends byte to the label.
-  pub fn add_byte(&mut self, elem: u8) {
-    self.label.push(elem);
+  /// Returns edge with the label without the first `len` bytes.
+  fn skip(&self, len: usize) -> Self {
+    Self {
+      key_id: self.key_id,
+      start: self.start + len as u32,
+      end: self.end,
+      dest_node_id: self.dest_node_id
+    }
   }
 }
 
 /// Represents a node of the generalized suffix tree graph.
-#[derive(Clone, Debug)]
+/// Both indices and edges are kept sorted, edges are sorted by the first byte of the label.
+#[derive(Clone, Debug, Default)]
 pub struct Node {
-  indices: HashSet<usize>,
-  suffix: Option<usize>,
-  edges: Vec<Option<Edge>>
+  indices: Vec<u32>,
+  suffix: Option<u32>,
+  edges: Vec<(u8, Edge)>
 }
 
 impl Node {
   /// Creates a new node.
   pub fn new() -> Self {
     Self {
-      indices: HashSet::new(),
+      indices: Vec::new(),
       suffix: None,
-      edges: vec![None; u8::max_value() as usize + 1]
+      edges: Vec::new()
     }
   }
 
   /// Add data index to the node.
-  pub fn add_index(&mut self, index: usize) {
-    self.indices.insert(index);
+  /// Returns false if index already exists.
+  pub fn add_index(&mut self, index: usize) -> bool {
+    let index = index as u32;
+    match self.indices.binary_search(&index) {
+      Ok(_) => false,
+      Err(pos) => {
+        self.indices.insert(pos, index);
+        true
+      }
+    }
   }
 
-  /// Adds edge for the key.
+  /// Adds edge for the key, replaces existing edge if any.
   pub fn add_edge(&mut self, key: u8, value: Edge) {
-    self.edges[key as usize] = Some(value);
+    match self.edges.binary_search_by_key(&key, |&(k, _)| k) {
+      Ok(pos) => self.edges[pos].1 = value,
+      Err(pos) => self.edges.insert(pos, (key, value))
+    }
   }
 
   /// Sets suffix for the node.
   pub fn set_suffix(&mut self, suffix: usize) {
-    self.suffix = Some(suffix);
+    self.suffix = Some(suffix as u32);
   }
 
   /// Returns edge for the key, or None if no such key exists.
   pub fn get_edge(&self, key: u8) -> Option<&Edge> {
-    self.edges[key as usize].as_ref()
+    self.edges
+      .binary_search_by_key(&key, |&(k, _)| k)
+      .ok()
+      .map(|pos| &self.edges[pos].1)
   }
 
-  /// Returns mutable reference for edge, or None if no such key exists.
-  pub fn get_edge_mut(&mut self, key: u8) -> Option<&mut Edge> {
-    self.edges[key as usize].as_mut()
-  }
-
-  /// Returns list of all edges in the node, including None.
-  pub fn get_edges(&self) -> &[Option<Edge>] {
+  /// Returns list of all edges in the node.
+  pub fn get_edges(&self) -> &[(u8, Edge)] {
     &self.edges[..]
   }
 
   /// Returns reference to indices for the node.
-  pub fn get_indices(&self) -> &HashSet<usize> {
-    &self.indices
+  pub fn get_indices(&self) -> &[u32] {
+    &self.indices[..]
   }
 
   /// Returns suffix of the node.
   pub fn get_suffix(&self) -> Option<usize> {
-    self.suffix
+    self.suffix.map(|suffix| suffix as usize)
   }
 
   /// Returns true if node has suffix.
@@ -105,8 +138,24 @@ impl Node {
   }
 }
 
+impl MemoryUsed for Node {
+  fn memory_used(&self) -> usize {
+    size_of::<Node>() +
+      self.indices.capacity() * size_of::<u32>() +
+      self.edges.capacity() * size_of::<(u8, Edge)>()
+  }
+}
+
+/// Generalized suffix tree.
+///
+/// All keys are appended to the shared text buffer, edge labels are references into that
+/// buffer.
 pub struct SuffixTree {
   tree_nodes: Vec<Node>,
+  // Concatenated bytes of all keys.
+  text: Vec<u8>,
+  // Offset and length of each key in the text buffer.
+  keys: Vec<(usize, usize)>,
   root: usize,
   active_leaf: usize
 }
@@ -117,7 +166,9 @@ impl SuffixTree {
     let node = Node::new();
     let tree_nodes = vec![node];
     Self {
-      tree_nodes: tree_nodes,
+      tree_nodes,
+      text: Vec::new(),
+      keys: Vec::new(),
       root: 0,
       active_leaf: 0
     }
@@ -134,14 +185,19 @@ impl SuffixTree {
   }
 
   /// Adds the specified `index` to the GST under the given `key`.
+  /// The same index can be added under several keys, and several indices under the same key.
   pub fn put(&mut self, key: &[u8], index: usize) {
+    let key_id = self.keys.len();
+    self.keys.push((self.text.len(), key.len()));
+    self.text.extend_from_slice(key);
+
     self.active_leaf = self.root;
     let mut s = self.root;
 
     let mut text = Vec::new();
-    for i in 0..key.len() {
-      text.push(key[i]);
-      let node_id = self.update(s, &mut text, &key[i..], index);
+    for (i, &byte) in key.iter().enumerate() {
+      text.push(byte);
+      let node_id = self.update(s, &mut text, key_id, i, index);
       s = self.canonize(node_id, &mut text);
     }
 
@@ -152,6 +208,11 @@ impl SuffixTree {
     }
   }
 
+  /// Returns number of nodes in the tree.
+  pub fn num_nodes(&self) -> usize {
+    self.tree_nodes.len()
+  }
+
   /// Returns reference for a node.
   fn get_node(&self, node_id: usize) -> &Node {
     &self.tree_nodes[node_id]
@@ -162,16 +223,47 @@ impl SuffixTree {
     self.tree_nodes.get_mut(node_id).expect("No node found")
   }
 
+  /// Creates a new node and returns its id.
+  fn new_node(&mut self) -> usize {
+    self.tree_nodes.push(Node::new());
+    self.tree_nodes.len() - 1
+  }
+
+  /// Returns bytes of the key starting at position `start`.
+  fn key_suffix(&self, key_id: usize, start: usize) -> &[u8] {
+    let (offset, len) = self.keys[key_id];
+    &self.text[offset + start..offset + len]
+  }
+
+  /// Returns label of the edge.
+  fn label(&self, edge: &Edge) -> &[u8] {
+    let (offset, _) = self.keys[edge.key_id as usize];
+    &self.text[offset + edge.start as usize..offset + edge.end as usize]
+  }
+
+  /// Adds index to the node and all nodes reachable through suffix links.
+  /// Stops once a node already has the index, since the rest of the chain has it as well.
+  fn add_ref(&mut self, node_id: usize, index: usize) {
+    let mut curr_node = Some(node_id);
+    while let Some(node_id) = curr_node {
+      let node = self.get_node_mut(node_id);
+      if !node.add_index(index) {
+        break;
+      }
+      curr_node = node.get_suffix();
+    }
+  }
+
   /// Returns the tree node (if present) that corresponds to the given string.
   fn search_node(&self, word: &[u8]) -> Option<&Node> {
     let mut curr_node = self.root;
     let mut i = 0;
     while i < word.len() {
       if let Some(edge) = self.get_node(curr_node).get_edge(word[i]) {
-        let label = edge.get_label();
+        let label = self.label(edge);
         let len_to_match = cmp::min(label.len(), word.len() - i);
 
-        if &word[i..i + len_to_match] != &label[0..len_to_match] {
+        if word[i..i + len_to_match] != label[0..len_to_match] {
           return None;
         }
 
@@ -180,7 +272,7 @@ impl SuffixTree {
         }
 
         curr_node = edge.get_dest_id();
-        i += len_to_match - 1;
+        i += len_to_match;
       } else {
         return None;
       }
@@ -191,31 +283,24 @@ impl SuffixTree {
 
   /// Get data recursively, first inspecting the node and then its children.
   fn get_data_recur(&self, node: &Node, num_results: usize, res: &mut HashSet<usize>) {
-    let mut num_to_take = cmp::min(num_results - res.len(), node.get_indices().len());
-    let mut iter = node.get_indices().iter();
-
-    while let Some(&value) = iter.next() {
-      if num_to_take == 0 {
-        break;
-      }
-
-      if res.insert(value) {
-        num_to_take -= 1;
+    for &value in node.get_indices() {
+      if res.len() >= num_results {
+        return;
       }
+      res.insert(value as usize);
     }
 
-    for edge in node.get_edges() {
-      if let Some(edge) = edge {
-        if num_results > res.len() {
-          self.get_data_recur(self.get_node(edge.get_dest_id()), num_results, res);
-        } else {
-          break;
-        }
+    for (_, edge) in node.get_edges() {
+      if num_results > res.len() {
+        self.get_data_recur(self.get_node(edge.get_dest_id()), num_results, res);
+      } else {
+        break;
       }
     }
   }
 
   /// Updates the tree starting from inputNode and by adding stringPart.
+  /// Rest of the key is `key[start..]` for the key with `key_id`.
   ///
   /// Returns a node_id for the string that has been added so far.
   /// This means:
@@ -224,8 +309,134 @@ impl SuffixTree {
   ///   that is a substring of the string added so far to the tree.
   /// - the String will be the remainder that must be added to S1 to get the string
   ///   added so far.
-  fn update(&self, s: usize, part: &mut Vec<u8>, rest: &[u8], index: usize) -> usize {
-    unimplemented!();
+  fn update(
+    &mut self,
+    s: usize,
+    part: &mut Vec<u8>,
+    key_id: usize,
+    start: usize,
+    index: usize
+  ) -> usize {
+    let mut s = s;
+    let new_byte = *part.last().expect("Part is not empty");
+    let mut old_root = self.root;
+
+    let (mut endpoint, mut r) =
+      self.test_and_split(s, cut_last(part), new_byte, key_id, start, index);
+
+    while !endpoint {
+      let existing_leaf = self.get_node(r).get_edge(new_byte).map(|edge| edge.get_dest_id());
+      let leaf = match existing_leaf {
+        Some(leaf) => leaf,
+        None => {
+          // Create a new leaf with the rest of the key
+          let leaf = self.new_node();
+          self.add_ref(leaf, index);
+          let (_, len) = self.keys[key_id];
+          self.get_node_mut(r).add_edge(new_byte, Edge::new(key_id, start, len, leaf));
+          leaf
+        }
+      };
+
+      // Update suffix link for newly created leaf
+      if self.active_leaf != self.root {
+        let active_leaf_id = self.active_leaf;
+        self.get_node_mut(active_leaf_id).set_suffix(leaf);
+      }
+      self.active_leaf = leaf;
+
+      if old_root != self.root {
+        self.get_node_mut(old_root).set_suffix(r);
+      }
+      old_root = r;
+
+      match self.get_node(s).get_suffix() {
+        // Only root does not have a suffix link
+        None => {
+          part.remove(0);
+        },
+        Some(suffix) => {
+          let last = part.pop().expect("Part is not empty");
+          s = self.canonize(suffix, part);
+          part.push(last);
+        }
+      }
+
+      let res = self.test_and_split(s, cut_last(part), new_byte, key_id, start, index);
+      endpoint = res.0;
+      r = res.1;
+    }
+
+    // Update suffix link for the last inner node
+    if old_root != self.root {
+      self.get_node_mut(old_root).set_suffix(r);
+    }
+
+    s
+  }
+
+  /// Tests whether the string `part` + `t` is contained in the subtree that has `s` as
+  /// root. If that's not the case, and there exists a path of edges e1, e2, ... such that
+  /// e1.e2... = `part` and there is an edge g such that g = `part` + x (x is not `t`),
+  /// splits g into a new node with edges `part` and x.
+  ///
+  /// Returns a pair of a flag whether the string is already in the tree (endpoint has been
+  /// reached) and the node for the next step.
+  fn test_and_split(
+    &mut self,
+    s: usize,
+    part: &[u8],
+    t: u8,
+    key_id: usize,
+    start: usize,
+    index: usize
+  ) -> (bool, usize) {
+    let mut part = part.to_vec();
+    let s = self.canonize(s, &mut part);
+
+    if !part.is_empty() {
+      let edge = *self.get_node(s).get_edge(part[0]).expect("Edge exists");
+      let next_byte = self.label(&edge).get(part.len()).cloned();
+      if edge.len() > part.len() && next_byte == Some(t) {
+        return (true, s);
+      }
+
+      // Split the edge at the end of the part
+      let r = self.new_node();
+      self.get_node_mut(r).add_edge(next_byte.expect("Label is longer than part"),
+        edge.skip(part.len()));
+      self.get_node_mut(s).add_edge(part[0], edge.prefix(part.len(), r));
+      (false, r)
+    } else {
+      let edge = match self.get_node(s).get_edge(t) {
+        Some(edge) => *edge,
+        None => return (false, s)
+      };
+
+      let (is_equal, rest_has_label, label_has_rest, rest_len) = {
+        let rest = self.key_suffix(key_id, start);
+        let label = self.label(&edge);
+        (rest == label, rest.starts_with(label), label.starts_with(rest), rest.len())
+      };
+
+      if is_equal {
+        self.add_ref(edge.get_dest_id(), index);
+        (true, s)
+      } else if rest_has_label {
+        (true, s)
+      } else if label_has_rest {
+        // Rest of the key ends in the middle of the edge, insert a new node for it
+        let new_node = self.new_node();
+        self.add_ref(new_node, index);
+        let next_byte = self.label(&edge)[rest_len];
+        self.get_node_mut(new_node).add_edge(next_byte, edge.skip(rest_len));
+        self.get_node_mut(s).add_edge(t, edge.prefix(rest_len, new_node));
+        (false, s)
+      } else {
+        // Rest of the key is already implicitly contained in the tree
+        (true, s)
+      }
+    }
   }
 
   /// Return a node_id (n) such that n is a farthest descendant of s (the input node)
@@ -242,8 +453,8 @@ impl SuffixTree {
     let mut g = self.get_node(curr_node_id).get_edge(input[idx]);
     // Descend the tree as long as a proper label is found
     while let Some(edge) = g {
-      let label = edge.get_label();
-      if len - idx >= label.len() && &input[idx..idx + label.len()] == &label[..] {
+      let label = self.label(edge);
+      if len - idx >= label.len() && input[idx..idx + label.len()] == label[..] {
         idx += label.len();
         curr_node_id = edge.get_dest_id();
         if idx < input.len() {
@@ -256,12 +467,198 @@ impl SuffixTree {
 
     // Update the input to the latest slice.
     if idx > 0 {
-      for i in idx..len {
-        input[i - idx] = input[i];
-      }
-      input.resize(len - idx, 0);
+      input.drain(..idx);
     }
 
     curr_node_id
   }
 }
+
+impl Default for SuffixTree {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl MemoryUsed for SuffixTree {
+  fn memory_used(&self) -> usize {
+    let nodes_size = self.tree_nodes.iter().fold(0, |n, node| n + node.memory_used());
+    size_of::<SuffixTree>() +
+      nodes_size +
+      (self.tree_nodes.capacity() - self.tree_nodes.len()) * size_of::<Node>() +
+      self.text.capacity() +
+      self.keys.capacity() * size_of::<(usize, usize)>()
+  }
+}
+
+/// Returns slice without the last element, or empty slice if input is empty.
+fn cut_last(input: &[u8]) -> &[u8] {
+  &input[..input.len().saturating_sub(1)]
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // Returns indices of keys that contain the word, same as search without a limit.
+  fn brute_force(keys: &[(&[u8], usize)], word: &[u8]) -> HashSet<usize> {
+    keys.iter()
+      .filter(|(key, _)| !word.is_empty() && key.windows(word.len()).any(|w| w == word))
+      .map(|&(_, index)| index)
+      .collect()
+  }
+
+  // Builds tree from keys and checks every substring of every key and a few missing words.
+  fn check(keys: &[(&[u8], usize)]) {
+    let mut tree = SuffixTree::new();
+    for &(key, index) in keys {
+      tree.put(key, index);
+    }
+    for &(key, _) in keys {
+      for start in 0..key.len() {
+        for end in start + 1..key.len() + 1 {
+          let word = &key[start..end];
+          assert_eq!(tree.search(word, usize::MAX), brute_force(keys, word), "word {:?}", word);
+        }
+      }
+    }
+    for word in &[&b"zzz"[..], b"xyzzy", b"\xff"] {
+      assert_eq!(tree.search(word, usize::MAX), brute_force(keys, word), "word {:?}", word);
+    }
+  }
+
+  #[test]
+  fn test_node_edges_sorted() {
+    let mut node = Node::new();
+    for &key in b"maz\xff\x00k" {
+      node.add_edge(key, Edge::new(0, key as usize, key as usize + 1, key as usize));
+    }
+    let keys = node.get_edges().iter().map(|&(key, _)| key).collect::<Vec<_>>();
+    assert_eq!(keys, vec![b'\x00', b'a', b'k', b'm', b'z', b'\xff']);
+    for &(key, ref edge) in node.get_edges() {
+      assert_eq!(node.get_edge(key).map(|edge| edge.get_dest_id()), Some(edge.get_dest_id()));
+    }
+    assert!(node.get_edge(b'b').is_none());
+
+    // Edge for existing key is replaced.
+    node.add_edge(b'k', Edge::new(1, 0, 2, 100));
+    assert_eq!(node.get_edges().len(), 6);
+    assert_eq!(node.get_edge(b'k').map(|edge| (edge.get_dest_id(), edge.len())), Some((100, 2)));
+  }
+
+  #[test]
+  fn test_node_indices_sorted() {
+    let mut node = Node::new();
+    assert!(node.add_index(5));
+    assert!(node.add_index(1));
+    assert!(node.add_index(3));
+    assert!(!node.add_index(3));
+    assert_eq!(node.get_indices(), &[1, 3, 5]);
+  }
+
+  #[test]
+  fn test_edge_split() {
+    let edge = Edge::new(2, 3, 10, 7);
+    let prefix = edge.prefix(4, 8);
+    let rest = edge.skip(4);
+    assert_eq!((prefix.start, prefix.end, prefix.get_dest_id()), (3, 7, 8));
+    assert_eq!((rest.start, rest.end, rest.get_dest_id()), (7, 10, 7));
+    assert_eq!(prefix.len() + rest.len(), edge.len());
+    assert!(edge.skip(7).is_empty());
+  }
+
+  #[test]
+  fn test_tree_edges_reference_keys() {
+    let mut tree = SuffixTree::new();
+    for (i, key) in ["banana.rs", "bandana.rs", "ärger"].iter().enumerate() {
+      tree.put(key.as_bytes(), i);
+    }
+    for node in &tree.tree_nodes {
+      let keys = node.get_edges().iter().map(|&(key, _)| key).collect::<Vec<_>>();
+      assert!(keys.windows(2).all(|pair| pair[0] < pair[1]), "edges {:?}", keys);
+      for &(key, ref edge) in node.get_edges() {
+        assert!(!edge.is_empty());
+        assert_eq!(tree.label(edge)[0], key);
+      }
+    }
+  }
+
+  #[test]
+  fn test_repeated_substrings() {
+    check(&[(b"banana", 0), (b"ananas", 1), (b"aaaa", 2), (b"abab", 3), (b"mississippi", 4)]);
+  }
+
+  #[test]
+  fn test_shared_suffixes() {
+    check(&[(b"main.rs", 0), (b"lib.rs", 1), (b"cache2.rs", 2), (b"rs", 3), (b".rs", 4)]);
+    check(&[(b"search", 0), (b"research", 1), (b"arch", 2), (b"ch", 3)]);
+  }
+
+  #[test]
+  fn test_same_key_and_index() {
+    // Same key under several indices and same index under several keys.
+    check(&[(b"readme.md", 0), (b"readme.md", 1), (b"notes.md", 1), (b"md", 2)]);
+  }
+
+  #[test]
+  fn test_empty_key_and_word() {
+    let mut tree = SuffixTree::new();
+    tree.put(b"", 0);
+    tree.put(b"abc", 1);
+    tree.put(b"", 2);
+    assert!(tree.search(b"", usize::MAX).is_empty());
+    assert_eq!(tree.search(b"b", usize::MAX), [1].iter().cloned().collect());
+    check(&[(b"", 0), (b"abc", 1), (b"", 2)]);
+  }
+
+  #[test]
+  fn test_non_ascii() {
+    let keys = ["ärger.txt", "größe.rs", "日本語.md", "語"];
+    let keys = keys.iter().enumerate().map(|(i, key)| (key.as_bytes(), i)).collect::<Vec<_>>();
+    check(&keys);
+  }
+
+  #[test]
+  fn test_generated_keys() {
+    // Keys over a small alphabet have many repeated and shared substrings.
+    let mut seed = 42u32;
+    let mut keys = Vec::new();
+    for _ in 0..40 {
+      let mut key = Vec::new();
+      seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+      for _ in 0..(seed >> 16) % 12 {
+        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+        key.push(b"abc."[(seed >> 16) as usize % 4]);
+      }
+      keys.push(key);
+    }
+    let keys = keys.iter().enumerate().map(|(i, key)| (key.as_slice(), i)).collect::<Vec<_>>();
+    check(&keys);
+  }
+
+  #[test]
+  fn test_search_limit() {
+    let mut tree = SuffixTree::new();
+    for i in 0..10 {
+      tree.put(format!("file{}.rs", i).as_bytes(), i);
+    }
+    assert_eq!(tree.search(b"file", 3).len(), 3);
+    assert_eq!(tree.search(b".rs", usize::MAX).len(), 10);
+    assert_eq!(tree.search(b"file", 0).len(), 0);
+  }
+
+  #[test]
+  fn test_rebuild() {
+    // Tree is append-only, removed keys are dropped by building a new tree.
+    let keys: Vec<(&[u8], usize)> = vec![(b"main.rs", 0), (b"old.rs", 1), (b"lib.rs", 2)];
+    check(&keys);
+    let rebuilt = keys.iter().filter(|&&(_, index)| index != 1).cloned().collect::<Vec<_>>();
+    check(&rebuilt);
+    let mut tree = SuffixTree::new();
+    for &(key, index) in &rebuilt {
+      tree.put(key, index);
+    }
+    assert!(tree.search(b"old", usize::MAX).is_empty());
+    assert_eq!(tree.search(b".rs", usize::MAX), [0, 2].iter().cloned().collect());
+  }
+}
@@ -0,0 +1,606 @@
+//! Symbol index: definitions extracted from cached files with per-language heuristics.
+//! Each line is matched against a small set of patterns for the file extension, patterns have
+//! a `name` capture group for the symbol name.
+
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::time;
+
+use cache2;
+use errors;
+use ext::Extension;
+use grep::matcher::{Captures, Match, Matcher};
+use grep::regex::{RegexCaptures, RegexMatcher, RegexMatcherBuilder};
+use params;
+use result::{Matched, SymbolItem, SymbolsResult};
+use search::relative_path;
+
+// Default number of symbols to return.
+const SYMBOLS_DEFAULT_LIMIT: usize = 50;
+// Lines longer than this are usually minified or generated code and are skipped.
+const MAX_LINE_LENGTH: usize = 1000;
+
+// Names that can be captured by method patterns but are control flow keywords.
+const KEYWORDS: &[&str] = &[
+  "catch", "else", "for", "foreach", "if", "new", "return", "switch", "throw", "while"
+];
+
+// Common prefixes of definitions.
+const RS_VIS: &str = r"^\s*(?:pub(?:\([^)]*\))?\s+)?";
+const JAVA_MODS: &str =
+  r"^\s*(?:@\w+\s+)*(?:(?:public|private|protected|static|final|abstract|sealed|strictfp)\s+)*";
+const SCALA_MODS: &str = concat!(
+  r"^\s*(?:@\w+\s+)*(?:(?:private|protected)(?:\[\w+\])?\s+|",
+  r"(?:final|sealed|abstract|implicit|lazy|override|case)\s+)*"
+);
+const JS_EXPORT: &str = r"^\s*(?:export\s+(?:default\s+)?)?";
+
+/// Kind of the symbol definition.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SymbolKind {
+  Function,
+  Method,
+  Class,
+  Struct,
+  Enum,
+  Trait,
+  Interface,
+  Type,
+  Constant,
+  Module
+}
+
+/// Symbol definition in the file, line numbers start with 1, column is a byte offset.
+#[derive(Clone, Debug)]
+pub struct Symbol {
+  name: String,
+  kind: SymbolKind,
+  line: u64,
+  column: usize
+}
+
+impl Symbol {
+  /// Returns name of the symbol.
+  pub fn name(&self) -> &str {
+    &self.name
+  }
+
+  /// Returns kind of the symbol.
+  pub fn kind(&self) -> SymbolKind {
+    self.kind
+  }
+
+  /// Returns line number of the definition.
+  pub fn line(&self) -> u64 {
+    self.line
+  }
+
+  /// Returns byte offset of the name within the line.
+  pub fn column(&self) -> usize {
+    self.column
+  }
+}
+
+impl cache2::MemoryUsed for Symbol {
+  fn memory_used(&self) -> usize {
+    ::std::mem::size_of::<Symbol>() + self.name.memory_used()
+  }
+}
+
+// Pattern for a single kind of definition.
+// Optional `alt` group is an alternative spelling of the name, optional `type` group is
+// the word before the name which must not be a keyword, e.g. `return foo(`.
+struct Rule {
+  kind: SymbolKind,
+  matcher: RegexMatcher,
+  name_index: usize,
+  alt_index: Option<usize>,
+  type_index: Option<usize>
+}
+
+// Returns list of (kind, regex) pairs for the extension.
+fn patterns(ext: Extension) -> Vec<(SymbolKind, String)> {
+  use self::SymbolKind::*;
+  let rules: Vec<(SymbolKind, String)> = match ext {
+    Extension::RS => vec![
+      (Function, format!(
+        r#"{}(?:(?:const|async|unsafe|extern\s+"[^"]*")\s+)*fn\s+(?P<name>\w+)"#, RS_VIS)),
+      (Struct, format!(r"{}struct\s+(?P<name>\w+)", RS_VIS)),
+      (Struct, format!(r"{}union\s+(?P<name>\w+)\s*[<{{]", RS_VIS)),
+      (Enum, format!(r"{}enum\s+(?P<name>\w+)", RS_VIS)),
+      (Trait, format!(r"{}(?:unsafe\s+)?trait\s+(?P<name>\w+)", RS_VIS)),
+      (Type, format!(r"{}type\s+(?P<name>\w+)", RS_VIS)),
+      (Constant, format!(r"{}(?:const|static(?:\s+mut)?)\s+(?P<name>\w+)\s*:", RS_VIS)),
+      (Module, format!(r"{}mod\s+(?P<name>\w+)\s*\{{", RS_VIS)),
+      (Function, r"^\s*macro_rules!\s*(?P<name>\w+)".to_owned())
+    ],
+    Extension::JAVA => vec![
+      (Class, format!(r"{}class\s+(?P<name>\w+)", JAVA_MODS)),
+      (Interface, format!(r"{}@?interface\s+(?P<name>\w+)", JAVA_MODS)),
+      (Enum, format!(r"{}enum\s+(?P<name>\w+)", JAVA_MODS)),
+      (Constant, format!(r"{}[\w<>\[\],.? ]+\s+(?P<name>[A-Z][A-Z0-9_]*)\s*=", JAVA_MODS)),
+      (Method, format!(concat!(
+        r"{}(?:(?:synchronized|native|default)\s+)*(?:<[^>]+>\s+)?",
+        r"(?P<type>[\w<>\[\],.?]+)\s+(?P<name>\w+)\s*\([^;]*$"), JAVA_MODS))
+    ],
+    Extension::SCALA => vec![
+      (Class, format!(r"{}class\s+(?P<name>\w+)", SCALA_MODS)),
+      (Trait, format!(r"{}trait\s+(?P<name>\w+)", SCALA_MODS)),
+      (Module, format!(r"{}object\s+(?P<name>\w+)", SCALA_MODS)),
+      (Type, format!(r"{}type\s+(?P<name>\w+)", SCALA_MODS)),
+      (Function, format!(r"{}def\s+(?P<name>\w+)", SCALA_MODS)),
+      (Constant, format!(r"{}val\s+(?P<name>[A-Z]\w*)\s*[:=]", SCALA_MODS))
+    ],
+    Extension::PY | Extension::PYST => vec![
+      (Class, r"^\s*class\s+(?P<name>\w+)".to_owned()),
+      (Function, r"^\s*(?:async\s+)?def\s+(?P<name>\w+)".to_owned()),
+      (Constant, r"^(?P<name>[A-Z][A-Z0-9_]*)\s*(?::[^=]+)?=[^=]".to_owned())
+    ],
+    Extension::GO => vec![
+      (Method, r"^func\s+\([^)]*\)\s*(?P<name>\w+)".to_owned()),
+      (Function, r"^func\s+(?P<name>\w+)".to_owned()),
+      (Struct, r"^(?:type\s+|\s+)(?P<name>\w+)\s+struct\b".to_owned()),
+      (Interface, r"^(?:type\s+|\s+)(?P<name>\w+)\s+interface\b".to_owned()),
+      // Struct and interface rules are checked first, any other type is a plain type.
+      (Type, r"^type\s+(?P<name>\w+)\s+\S".to_owned()),
+      (Constant, r"^const\s+(?P<name>\w+)".to_owned())
+    ],
+    Extension::JS | Extension::JSX | Extension::TS | Extension::TSX => vec![
+      (Class, format!(r"{}(?:abstract\s+)?class\s+(?P<name>\w+)", JS_EXPORT)),
+      (Interface, format!(r"{}interface\s+(?P<name>\w+)", JS_EXPORT)),
+      (Type, format!(r"{}type\s+(?P<name>\w+)\s*(?:<[^>]*>)?\s*=", JS_EXPORT)),
+      (Enum, format!(r"{}(?:const\s+)?enum\s+(?P<name>\w+)", JS_EXPORT)),
+      (Function, format!(r"{}(?:async\s+)?function\s*\*?\s*(?P<name>\w+)", JS_EXPORT)),
+      (Function, format!(concat!(
+        r"{}(?:const|let|var)\s+(?P<name>\w+)\s*=\s*(?:async\s+)?",
+        r"(?:function\b|\([^)]*\)\s*=>|\w+\s*=>)"), JS_EXPORT)),
+      (Constant, format!(r"{}const\s+(?P<name>[A-Z][A-Z0-9_]*)\s*=", JS_EXPORT)),
+      (Method, concat!(
+        r"^\s+(?:(?:static|async|get|set|public|private|protected)\s+)*",
+        r"(?P<name>\w+)\s*\([^)]*\)\s*(?::\s*[^{]+)?\{\s*$").to_owned())
+    ],
+    Extension::COFFEE => vec![
+      (Class, r"^\s*class\s+(?P<name>[\w.]+)".to_owned()),
+      (Function, r"^\s*@?(?P<name>\w+)\s*[:=]\s*(?:\([^)]*\)\s*)?[-=]>".to_owned())
+    ],
+    Extension::C | Extension::CPP | Extension::H | Extension::M => vec![
+      (Class, r"^\s*(?:template\s*<[^>]*>\s*)?class\s+(?P<name>\w+)\s*(?:[:{]|$)".to_owned()),
+      (Struct, r"^\s*(?:typedef\s+)?struct\s+(?P<name>\w+)\s*(?:[:{]|$)".to_owned()),
+      (Enum, r"^\s*(?:typedef\s+)?enum\s+(?:class\s+)?(?P<name>\w+)\s*(?:[:{]|$)".to_owned()),
+      (Constant, r"^\s*#\s*define\s+(?P<name>\w+)".to_owned()),
+      (Module, r"^\s*namespace\s+(?P<name>\w+)".to_owned()),
+      (Function, concat!(
+        r"^(?:[\w*&:<>,]+\s+)*(?P<type>[\w*&:<>,]+)\s+\**&?",
+        r"(?P<name>[\w:~]+)\s*\([^;]*$").to_owned())
+    ],
+    Extension::RB => vec![
+      (Class, r"^\s*class\s+(?P<name>[\w:]+)".to_owned()),
+      (Module, r"^\s*module\s+(?P<name>[\w:]+)".to_owned()),
+      (Function, r"^\s*def\s+(?:self\.)?(?P<name>\w+[?!=]?)".to_owned()),
+      (Constant, r"^\s*(?P<name>[A-Z][A-Z0-9_]*)\s*=[^=]".to_owned())
+    ],
+    Extension::PHP => vec![
+      (Class, r"^\s*(?:(?:abstract|final)\s+)?class\s+(?P<name>\w+)".to_owned()),
+      (Interface, r"^\s*interface\s+(?P<name>\w+)".to_owned()),
+      (Trait, r"^\s*trait\s+(?P<name>\w+)".to_owned()),
+      (Function, concat!(
+        r"^\s*(?:(?:public|private|protected|static|abstract|final)\s+)*",
+        r"function\s+&?(?P<name>\w+)").to_owned()),
+      (Constant, r"^\s*(?:(?:public|private|protected)\s+)?const\s+(?P<name>\w+)".to_owned())
+    ],
+    Extension::SWIFT => vec![
+      (Class, concat!(
+        r"^\s*(?:(?:public|private|internal|open|final)\s+)*",
+        r"class\s+(?P<name>\w+)").to_owned()),
+      (Struct, r"^\s*(?:(?:public|private|internal)\s+)*struct\s+(?P<name>\w+)".to_owned()),
+      (Enum, r"^\s*(?:(?:public|private|internal|indirect)\s+)*enum\s+(?P<name>\w+)".to_owned()),
+      (Interface, r"^\s*(?:(?:public|private|internal)\s+)*protocol\s+(?P<name>\w+)".to_owned()),
+      (Function, concat!(
+        r"^\s*(?:(?:public|private|internal|open|static|class|override|mutating|final)\s+)*",
+        r"func\s+(?P<name>\w+)").to_owned())
+    ],
+    Extension::PROTO | Extension::THRIFT => vec![
+      (Struct, r"^\s*(?:message|struct|union|exception)\s+(?P<name>\w+)".to_owned()),
+      (Enum, r"^\s*enum\s+(?P<name>\w+)".to_owned()),
+      (Interface, r"^\s*service\s+(?P<name>\w+)".to_owned()),
+      (Method, r"^\s*rpc\s+(?P<name>\w+)".to_owned()),
+      (Constant, r"^\s*const\s+\w+\s+(?P<name>\w+)".to_owned())
+    ],
+    Extension::SH => vec![
+      (Function, r"^\s*(?:function\s+(?P<name>[\w-]+)|(?P<alt>[\w-]+)\s*\(\)\s*\{)".to_owned())
+    ],
+    _ => Vec::new()
+  };
+  rules
+}
+
+/// Extracts symbols from file content, compiled patterns are reused between files.
+pub struct SymbolExtractor {
+  rules: HashMap<Extension, Vec<Rule>>
+}
+
+impl SymbolExtractor {
+  /// Creates a new extractor, patterns are compiled lazily per extension.
+  pub fn new() -> Self {
+    Self { rules: HashMap::new() }
+  }
+
+  // Compiles rules for the extension.
+  fn compile(ext: Extension) -> Result<Vec<Rule>, errors::Error> {
+    let mut rules = Vec::new();
+    for (kind, pattern) in patterns(ext) {
+      // Identifiers are matched as ASCII words, Unicode classes are much slower to compile.
+      let matcher = RegexMatcherBuilder::new().unicode(false).build(&pattern)?;
+      let name_index = match matcher.capture_index("name") {
+        Some(index) => index,
+        None => return err!("Symbol pattern {} has no name group", pattern)
+      };
+      let alt_index = matcher.capture_index("alt");
+      let type_index = matcher.capture_index("type");
+      rules.push(Rule { kind, matcher, name_index, alt_index, type_index });
+    }
+    Ok(rules)
+  }
+
+  // Returns compiled rules for the extension.
+  fn rules(&mut self, ext: Extension) -> Result<&[Rule], errors::Error> {
+    if let Entry::Vacant(entry) = self.rules.entry(ext) {
+      entry.insert(Self::compile(ext)?);
+    }
+    Ok(&self.rules[&ext])
+  }
+
+  /// Returns true if symbols can be extracted from files with the extension.
+  pub fn supports(&mut self, ext: Extension) -> Result<bool, errors::Error> {
+    Ok(!self.rules(ext)?.is_empty())
+  }
+
+  /// Returns symbols defined in the content, in the order they appear in the file.
+  pub fn extract(&mut self, ext: Extension, content: &[u8]) -> Result<Vec<Symbol>, errors::Error> {
+    let rules = self.rules(ext)?;
+    let mut symbols = Vec::new();
+    if rules.is_empty() {
+      return Ok(symbols);
+    }
+
+    let mut caps: Vec<RegexCaptures> = Vec::with_capacity(rules.len());
+    for rule in rules {
+      caps.push(rule.matcher.new_captures()?);
+    }
+
+    for (i, line) in content.split(|&byte| byte == b'\n').enumerate() {
+      if line.is_empty() || line.len() > MAX_LINE_LENGTH {
+        continue;
+      }
+      for (rule, caps) in rules.iter().zip(caps.iter_mut()) {
+        if !rule.matcher.captures(line, caps)? {
+          continue;
+        }
+        let is_keyword = |mat: Option<Match>| {
+          mat.map(|mat| &line[mat.start()..mat.end()])
+            .map(|word| KEYWORDS.iter().any(|keyword| keyword.as_bytes() == word))
+            .unwrap_or(false)
+        };
+        if is_keyword(rule.type_index.and_then(|index| caps.get(index))) {
+          continue;
+        }
+        let mat = caps.get(rule.name_index)
+          .or_else(|| rule.alt_index.and_then(|index| caps.get(index)));
+        let (start, end) = match mat {
+          Some(mat) if !is_keyword(Some(mat)) => (mat.start(), mat.end()),
+          _ => continue
+        };
+        let name = String::from_utf8_lossy(&line[start..end]).into_owned();
+        // Functions nested in a class or an impl block are methods.
+        let kind = if rule.kind == SymbolKind::Function && start > 0 &&
+            (line[0] == b' ' || line[0] == b'\t') {
+          SymbolKind::Method
+        } else {
+          rule.kind
+        };
+        symbols.push(Symbol { name, kind, line: i as u64 + 1, column: start });
+        break;
+      }
+    }
+    Ok(symbols)
+  }
+}
+
+impl Default for SymbolExtractor {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+// Returns rank of the name match: 0 for exact match, 1 for case-insensitive exact match,
+// 2 for prefix and 3 for substring match, or None if name does not match.
+fn match_rank(name: &str, pattern: &str, lower_pattern: &str) -> Option<u8> {
+  if name == pattern {
+    return Some(0);
+  }
+  let lower_name = name.to_lowercase();
+  if lower_name == lower_pattern {
+    Some(1)
+  } else if lower_name.starts_with(lower_pattern) {
+    Some(2)
+  } else if lower_name.contains(lower_pattern) {
+    Some(3)
+  } else {
+    None
+  }
+}
+
+/// Finds symbols whose name contains the pattern (case-insensitive) in the cached directory.
+/// Exact matches come first, then prefix matches and substring matches; ties are resolved by
+/// name length, path and line.
+pub fn find(
+  cache: &cache2::SharedCache,
+  params: params::SymbolsParams
+) -> Result<SymbolsResult, errors::Error> {
+  let start_time = time::Instant::now();
+
+  let path_buf = params.dir()?;
+  let root = path_buf.as_path();
+  if !root.is_dir() {
+    return err!("Path {} is not a directory", root.to_str().unwrap_or(""));
+  }
+
+  let pattern = params.pattern().trim();
+  if pattern.is_empty() {
+    return err!("Empty pattern, expected a symbol name");
+  }
+  let lower_pattern = pattern.to_lowercase();
+
+  let snapshot = match cache2::snapshot(cache, root)? {
+    Some(index) => index,
+    None => return err!("Path {} is not cached, symbols require cache", root.display())
+  };
+
+  let entries = snapshot.entries();
+  let mut matches = Vec::new();
+  for (path, ext, file_index) in entries.iter().flat_map(|entries| entries.iter()) {
+    let symbols = match file_index {
+      Some(file_index) => file_index.symbols(),
+      None => continue
+    };
+    for symbol in symbols {
+      if !params.has_kind(symbol.kind()) {
+        continue;
+      }
+      if let Some(rank) = match_rank(symbol.name(), pattern, &lower_pattern) {
+        matches.push((rank, path, *ext, symbol));
+      }
+    }
+  }
+
+  matches.sort_by(|a, b| {
+    a.0.cmp(&b.0)
+      .then_with(|| a.3.name().len().cmp(&b.3.name().len()))
+      .then_with(|| a.1.cmp(b.1))
+      .then_with(|| a.3.line().cmp(&b.3.line()))
+  });
+
+  // Every indexed symbol is matched, so the number of matches is known even if it exceeds the
+  // limit.
+  let limit = params.limit().unwrap_or(SYMBOLS_DEFAULT_LIMIT);
+  let symbol_matches = Matched::Exact(matches.len());
+
+  let symbols = matches.into_iter().take(limit).map(|(_, path, ext, symbol)| {
+    SymbolItem::new(
+      symbol.name().to_owned(),
+      symbol.kind(),
+      path.to_owned(),
+      relative_path(root, path).to_owned(),
+      ext,
+      symbol.line(),
+      symbol.column()
+    )
+  }).collect::<Vec<_>>();
+
+  let duration = start_time.elapsed();
+  let exec_time = duration.as_secs() as f64 + duration.subsec_nanos() as f64 * 1e-9;
+
+  Ok(SymbolsResult::new(exec_time, snapshot.txid(), symbols, symbol_matches))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use self::SymbolKind::*;
+
+  // Returns names and kinds of the symbols defined in the source.
+  fn symbols(ext: Extension, source: &str) -> Vec<(String, SymbolKind)> {
+    let symbols = SymbolExtractor::new().extract(ext, source.as_bytes()).unwrap();
+    symbols.into_iter().map(|symbol| (symbol.name().to_owned(), symbol.kind())).collect()
+  }
+
+  // Returns expected names and kinds.
+  fn expected(symbols: &[(&str, SymbolKind)]) -> Vec<(String, SymbolKind)> {
+    symbols.iter().map(|(name, kind)| (name.to_string(), *kind)).collect()
+  }
+
+  #[test]
+  fn test_extract_rust() {
+    let source = r#"
+pub(crate) struct Cache {
+pub enum State {
+pub trait Index {
+type Result<T> = std::result::Result<T, Error>;
+pub const MAX_SIZE: usize = 10;
+static mut COUNTER: u32 = 0;
+mod tests {
+macro_rules! err {
+pub async fn load() {}
+impl Cache {
+  pub fn get(&self) {}
+}
+  // fn commented() {}
+  let x = load();
+  get();
+"#;
+    assert_eq!(symbols(Extension::RS, source), expected(&[
+      ("Cache", Struct), ("State", Enum), ("Index", Trait), ("Result", Type),
+      ("MAX_SIZE", Constant), ("COUNTER", Constant), ("tests", Module), ("err", Function),
+      ("load", Function), ("get", Method)
+    ]));
+  }
+
+  #[test]
+  fn test_extract_java() {
+    let source = r#"
+public final class Search {
+  private static final int MAX_SIZE = 10;
+  public List<String> find(String pattern) {
+    return find(pattern, 10);
+    // find(pattern);
+  }
+}
+interface Index {
+enum State {
+"#;
+    assert_eq!(symbols(Extension::JAVA, source), expected(&[
+      ("Search", Class), ("MAX_SIZE", Constant), ("find", Method), ("Index", Interface),
+      ("State", Enum)
+    ]));
+  }
+
+  #[test]
+  fn test_extract_scala() {
+    let source = r#"
+final case class Query(pattern: String)
+sealed trait Index
+object Search {
+  val MaxSize = 10
+  type Id = Long
+  private[search] def find(query: Query): Seq[String] = {
+    find(query)
+  }
+}
+"#;
+    assert_eq!(symbols(Extension::SCALA, source), expected(&[
+      ("Query", Class), ("Index", Trait), ("Search", Module), ("MaxSize", Constant),
+      ("Id", Type), ("find", Method)
+    ]));
+  }
+
+  #[test]
+  fn test_extract_python() {
+    let source = r#"
+MAX_SIZE = 10
+class Search:
+    async def find(self, pattern):
+        return find(pattern)
+def main():
+    # def commented():
+    if MAX_SIZE == 10:
+        main()
+"#;
+    assert_eq!(symbols(Extension::PY, source), expected(&[
+      ("MAX_SIZE", Constant), ("Search", Class), ("find", Method), ("main", Function)
+    ]));
+  }
+
+  #[test]
+  fn test_extract_go() {
+    let source = r#"
+const MaxSize = 10
+type Search struct {
+type Index interface {
+type Id int64
+func (s *Search) Find(pattern string) []string {
+func main() {
+	s.Find("x")
+	// func commented() {
+}
+"#;
+    assert_eq!(symbols(Extension::GO, source), expected(&[
+      ("MaxSize", Constant), ("Search", Struct), ("Index", Interface), ("Id", Type),
+      ("Find", Method), ("main", Function)
+    ]));
+  }
+
+  #[test]
+  fn test_extract_js() {
+    let source = r#"
+export default class Search {
+  find(pattern) {
+    return this.find(pattern);
+  }
+}
+export const MAX_SIZE = 10;
+const load = async (path) => read(path);
+function* walk(root) {
+// function commented() {
+load("x");
+"#;
+    assert_eq!(symbols(Extension::JS, source), expected(&[
+      ("Search", Class), ("find", Method), ("MAX_SIZE", Constant), ("load", Function),
+      ("walk", Function)
+    ]));
+  }
+
+  #[test]
+  fn test_extract_ts() {
+    let source = r#"
+export interface Query {
+export type Id = string;
+export const enum State {
+export abstract class Index {
+"#;
+    assert_eq!(symbols(Extension::TS, source), expected(&[
+      ("Query", Interface), ("Id", Type), ("State", Enum), ("Index", Class)
+    ]));
+  }
+
+  #[test]
+  fn test_extract_c() {
+    let source = r#"
+#define MAX_SIZE 10
+namespace search {
+template <typename T> class Index {
+typedef struct node {
+enum class State {
+static int find(const char *pattern) {
+  return find(pattern);
+  find(pattern);
+}
+"#;
+    assert_eq!(symbols(Extension::CPP, source), expected(&[
+      ("MAX_SIZE", Constant), ("search", Module), ("Index", Class), ("node", Struct),
+      ("State", Enum), ("find", Function)
+    ]));
+  }
+
+  #[test]
+  fn test_extract_other_languages() {
+    assert_eq!(symbols(Extension::RB, "module Search\n  MAX = 1\n  def self.find?\n"),
+      expected(&[("Search", Module), ("MAX", Constant), ("find?", Method)]));
+    assert_eq!(symbols(Extension::PHP, "final class A {\n  public static function &get() {\n"),
+      expected(&[("A", Class), ("get", Method)]));
+    assert_eq!(symbols(Extension::SWIFT, "public struct A {\n  mutating func run() {\n"),
+      expected(&[("A", Struct), ("run", Method)]));
+    assert_eq!(symbols(Extension::PROTO, "service Search {\n  rpc Find(Query)\n"),
+      expected(&[("Search", Interface), ("Find", Method)]));
+    assert_eq!(symbols(Extension::SH, "function build {\ntest-all() {\nbuild\n"),
+      expected(&[("build", Function), ("test-all", Function)]));
+    assert_eq!(symbols(Extension::COFFEE, "class Search\n  find: (pattern) ->\n"),
+      expected(&[("Search", Class), ("find", Method)]));
+  }
+
+  #[test]
+  fn test_extract_position() {
+    let symbols = SymbolExtractor::new().extract(Extension::RS, b"\n  pub fn get() {}").unwrap();
+    assert_eq!((symbols[0].line(), symbols[0].column()), (2, 9));
+    assert!(!SymbolExtractor::new().supports(Extension::JSON).unwrap());
+  }
+
+  #[test]
+  fn test_match_rank() {
+    assert_eq!(match_rank("Search", "Search", "search"), Some(0));
+    assert_eq!(match_rank("search", "Search", "search"), Some(1));
+    assert_eq!(match_rank("SearchOptions", "search", "search"), Some(2));
+    assert_eq!(match_rank("find_search", "search", "search"), Some(3));
+    assert_eq!(match_rank("find", "search", "search"), None);
+  }
+}
@@ -0,0 +1,58 @@
+//! Helpers shared by unit tests.
+
+use std::env;
+use std::fs;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static DIR_SEQ: AtomicUsize = AtomicUsize::new(0);
+
+/// Unique temporary directory that is removed with its content when dropped.
+pub struct TempDir {
+  path: PathBuf
+}
+
+impl TempDir {
+  /// Creates directory with the files, paths are relative to the directory.
+  pub fn new(files: &[(&str, &str)]) -> Self {
+    let name = format!("omnisearch-test-{}-{}", process::id(),
+      DIR_SEQ.fetch_add(1, Ordering::Relaxed));
+    let path = env::temp_dir().join(name);
+    fs::create_dir_all(&path).unwrap();
+    let dir = Self { path: path.canonicalize().unwrap() };
+    for (path, content) in files {
+      dir.write(path, content.as_bytes());
+    }
+    dir
+  }
+
+  /// Writes file relative to the directory, parent directories are created if needed.
+  pub fn write(&self, path: &str, content: &[u8]) -> PathBuf {
+    let path = self.path.join(path);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(&path, content).unwrap();
+    path
+  }
+}
+
+impl Deref for TempDir {
+  type Target = Path;
+
+  fn deref(&self) -> &Path {
+    &self.path
+  }
+}
+
+impl AsRef<Path> for TempDir {
+  fn as_ref(&self) -> &Path {
+    &self.path
+  }
+}
+
+impl Drop for TempDir {
+  fn drop(&mut self) {
+    let _ = fs::remove_dir_all(&self.path);
+  }
+}